@@ -0,0 +1,368 @@
+//! CLAP ホストの代わり（GUI なし）：プラグインの .so を dlopen して、テスト信号を通して確かめる。
+//!
+//!     cargo build --features clap && cargo run --features clap --example clap_host [path/to/libmelody_dsp.so]
+//!
+//! 1. ミックス 0 % なら出力は入力を報告どおりの遅れだけずらしたものと一致する
+//! 2. A マイナー・即時補正なら 225 Hz のサイン波が 220 Hz（A3）に寄る
+//! 3. clap.state で保存したパラメータを読み戻せる
+//!
+//! どれかが外れたら 0 以外で終わる。
+
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::path::PathBuf;
+use std::ptr;
+
+use clap_sys::audio_buffer::clap_audio_buffer;
+use clap_sys::entry::clap_plugin_entry;
+use clap_sys::events::{
+    clap_event_header, clap_event_param_value, clap_input_events, clap_output_events, CLAP_CORE_EVENT_SPACE_ID,
+    CLAP_EVENT_PARAM_VALUE,
+};
+use clap_sys::ext::audio_ports::{clap_audio_port_info, clap_plugin_audio_ports, CLAP_EXT_AUDIO_PORTS};
+use clap_sys::ext::latency::{clap_plugin_latency, CLAP_EXT_LATENCY};
+use clap_sys::ext::params::{clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS};
+use clap_sys::ext::state::{clap_plugin_state, CLAP_EXT_STATE};
+use clap_sys::factory::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID};
+use clap_sys::host::clap_host;
+use clap_sys::id::clap_id;
+use clap_sys::plugin::clap_plugin;
+use clap_sys::process::{clap_process, CLAP_PROCESS_ERROR};
+use clap_sys::stream::{clap_istream, clap_ostream};
+use clap_sys::version::CLAP_VERSION;
+
+const SAMPLE_RATE: f64 = 48000.0;
+const BLOCK: usize = 512;
+const TEST_HZ: f32 = 225.0;
+// A3
+const EXPECTED_HZ: f32 = 220.0;
+
+// the plugin's parameter ids
+const PARAM_KEY: clap_id = 0;
+const PARAM_SCALE: clap_id = 1;
+const PARAM_RETUNE: clap_id = 2;
+const PARAM_MIX: clap_id = 4;
+
+const RTLD_NOW: c_int = 2;
+
+#[link(name = "dl")]
+extern "C" {
+    fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *const c_char;
+    fn dlclose(handle: *mut c_void) -> c_int;
+}
+
+unsafe extern "C" fn host_get_extension(_host: *const clap_host, _id: *const c_char) -> *const c_void {
+    ptr::null()
+}
+
+unsafe extern "C" fn host_request(_host: *const clap_host) {}
+
+unsafe extern "C" fn events_size(list: *const clap_input_events) -> u32 {
+    (*((*list).ctx as *const Vec<clap_event_param_value>)).len() as u32
+}
+
+unsafe extern "C" fn events_get(list: *const clap_input_events, index: u32) -> *const clap_event_header {
+    let events = &*((*list).ctx as *const Vec<clap_event_param_value>);
+    events.get(index as usize).map_or(ptr::null(), |e| &e.header)
+}
+
+unsafe extern "C" fn events_try_push(_list: *const clap_output_events, _event: *const clap_event_header) -> bool {
+    true
+}
+
+unsafe extern "C" fn stream_write(stream: *const clap_ostream, buffer: *const c_void, size: u64) -> i64 {
+    let out = &mut *((*stream).ctx as *mut Vec<u8>);
+    out.extend_from_slice(std::slice::from_raw_parts(buffer as *const u8, size as usize));
+    size as i64
+}
+
+unsafe extern "C" fn stream_read(stream: *const clap_istream, buffer: *mut c_void, size: u64) -> i64 {
+    let src = &mut *((*stream).ctx as *mut &[u8]);
+    let n = (size as usize).min(src.len());
+    ptr::copy_nonoverlapping(src.as_ptr(), buffer as *mut u8, n);
+    *src = &src[n..];
+    n as i64
+}
+
+fn param_event(time: u32, param_id: clap_id, value: f64) -> clap_event_param_value {
+    clap_event_param_value {
+        header: clap_event_header {
+            size: std::mem::size_of::<clap_event_param_value>() as u32,
+            time,
+            space_id: CLAP_CORE_EVENT_SPACE_ID,
+            type_: CLAP_EVENT_PARAM_VALUE,
+            flags: 0,
+        },
+        param_id,
+        cookie: ptr::null_mut(),
+        note_id: -1,
+        port_index: -1,
+        channel: -1,
+        key: -1,
+        value,
+    }
+}
+
+/// 読み込んだプラグイン 1 つ。
+struct Instance {
+    plugin: *const clap_plugin,
+}
+
+impl Instance {
+    unsafe fn extension<T>(&self, id: &CStr) -> Option<&T> {
+        let get = (*self.plugin).get_extension?;
+        (get(self.plugin, id.as_ptr()) as *const T).as_ref()
+    }
+
+    /// 入力（planar, 2ch）を BLOCK ずつ流して出力を返す。events は最初のブロックの頭で送る。
+    unsafe fn run(&self, input: &[Vec<f32>; 2], events: Vec<clap_event_param_value>) -> Result<[Vec<f32>; 2], String> {
+        let process = (*self.plugin).process.ok_or("no process callback")?;
+        let frames = input[0].len();
+        let mut output = [vec![0.0_f32; frames], vec![0.0_f32; frames]];
+        let no_events: Vec<clap_event_param_value> = Vec::new();
+        let out_events = clap_output_events {
+            ctx: ptr::null_mut(),
+            try_push: Some(events_try_push),
+        };
+
+        let mut pos = 0;
+        while pos < frames {
+            let n = BLOCK.min(frames - pos);
+            let list = if pos == 0 { &events } else { &no_events };
+            let in_events = clap_input_events {
+                ctx: list as *const Vec<clap_event_param_value> as *mut c_void,
+                size: Some(events_size),
+                get: Some(events_get),
+            };
+            let mut in_ptrs = [input[0][pos..].as_ptr() as *mut f32, input[1][pos..].as_ptr() as *mut f32];
+            let (left, right) = output.split_at_mut(1);
+            let mut out_ptrs = [left[0][pos..].as_mut_ptr(), right[0][pos..].as_mut_ptr()];
+            let audio_in = clap_audio_buffer {
+                data32: in_ptrs.as_mut_ptr(),
+                data64: ptr::null_mut(),
+                channel_count: 2,
+                latency: 0,
+                constant_mask: 0,
+            };
+            let mut audio_out = clap_audio_buffer {
+                data32: out_ptrs.as_mut_ptr(),
+                data64: ptr::null_mut(),
+                channel_count: 2,
+                latency: 0,
+                constant_mask: 0,
+            };
+            let status = process(
+                self.plugin,
+                &clap_process {
+                    steady_time: pos as i64,
+                    frames_count: n as u32,
+                    transport: ptr::null(),
+                    audio_inputs: &audio_in,
+                    audio_outputs: &mut audio_out,
+                    audio_inputs_count: 1,
+                    audio_outputs_count: 1,
+                    in_events: &in_events,
+                    out_events: &out_events,
+                },
+            );
+            if status == CLAP_PROCESS_ERROR {
+                return Err(format!("process failed at frame {pos}"));
+            }
+            pos += n;
+        }
+        Ok(output)
+    }
+}
+
+/// x の基本周波数（自己相関の差分の谷、放物線補間）。窓をいくつか取って中央値を返す。
+fn estimate_hz(x: &[f32], sr: f32) -> f32 {
+    let window = 2048;
+    let (min_lag, max_lag) = ((sr / 1000.0) as usize, (sr / 60.0) as usize);
+    let mut found: Vec<f32> = x
+        .windows(window + max_lag + 1)
+        .step_by(window)
+        .filter_map(|w| {
+            let d: Vec<f32> = (0..=max_lag)
+                .map(|lag| (0..window).map(|i| (w[i] - w[i + lag]).powi(2)).sum())
+                .collect();
+            let lag = (min_lag..max_lag).min_by(|&a, &b| d[a].total_cmp(&d[b]))?;
+            let (a, b, c) = (d[lag - 1], d[lag], d[lag + 1]);
+            let denom = a - 2.0 * b + c;
+            let shift = if denom.abs() > 1.0e-12 { 0.5 * (a - c) / denom } else { 0.0 };
+            Some(sr / (lag as f32 + shift))
+        })
+        .collect();
+    found.sort_by(f32::total_cmp);
+    found.get(found.len() / 2).copied().unwrap_or(f32::NAN)
+}
+
+fn default_plugin_path() -> PathBuf {
+    // target/<profile>/examples/clap_host -> target/<profile>/libmelody_dsp.so
+    let exe = std::env::current_exe().unwrap_or_default();
+    exe.parent()
+        .and_then(|p| p.parent())
+        .map(|dir| dir.join("libmelody_dsp.so"))
+        .unwrap_or_else(|| PathBuf::from("libmelody_dsp.so"))
+}
+
+fn check(ok: bool, what: &str, failures: &mut usize) {
+    println!("{} {what}", if ok { "ok  " } else { "FAIL" });
+    if !ok {
+        *failures += 1;
+    }
+}
+
+fn main() {
+    let path = std::env::args().nth(1).map(PathBuf::from).unwrap_or_else(default_plugin_path);
+    match unsafe { run_host(&path) } {
+        Ok(0) => println!("all checks passed"),
+        Ok(failures) => {
+            eprintln!("{failures} check(s) failed");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("clap_host: {}: {e}", path.display());
+            std::process::exit(2);
+        }
+    }
+}
+
+unsafe fn run_host(path: &std::path::Path) -> Result<usize, String> {
+    let c_path = CString::new(path.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+    let lib = dlopen(c_path.as_ptr(), RTLD_NOW);
+    if lib.is_null() {
+        return Err(CStr::from_ptr(dlerror()).to_string_lossy().into_owned());
+    }
+    let entry = (dlsym(lib, c"clap_entry".as_ptr()) as *const clap_plugin_entry)
+        .as_ref()
+        .ok_or("no clap_entry symbol (build with --features clap)")?;
+    if !(entry.init.ok_or("no entry.init")?)(c_path.as_ptr()) {
+        return Err("entry.init failed".to_string());
+    }
+    let factory = ((entry.get_factory.ok_or("no get_factory")?)(CLAP_PLUGIN_FACTORY_ID.as_ptr())
+        as *const clap_plugin_factory)
+        .as_ref()
+        .ok_or("no plugin factory")?;
+    let desc = (factory.get_plugin_descriptor.ok_or("no get_plugin_descriptor")?)(factory, 0)
+        .as_ref()
+        .ok_or("no plugin")?;
+    println!(
+        "plugin: {} ({})",
+        CStr::from_ptr(desc.name).to_string_lossy(),
+        CStr::from_ptr(desc.id).to_string_lossy()
+    );
+
+    let host = clap_host {
+        clap_version: CLAP_VERSION,
+        host_data: ptr::null_mut(),
+        name: c"clap_host".as_ptr(),
+        vendor: c"s-tune".as_ptr(),
+        url: c"".as_ptr(),
+        version: c"0.1.0".as_ptr(),
+        get_extension: Some(host_get_extension),
+        request_restart: Some(host_request),
+        request_process: Some(host_request),
+        request_callback: Some(host_request),
+    };
+    let plugin = (factory.create_plugin.ok_or("no create_plugin")?)(factory, &host, desc.id);
+    if plugin.is_null() || !((*plugin).init.ok_or("no init")?)(plugin) {
+        return Err("could not create the plugin".to_string());
+    }
+    let inst = Instance { plugin };
+    let mut failures = 0;
+
+    let params = inst.extension::<clap_plugin_params>(CLAP_EXT_PARAMS).ok_or("no clap.params")?;
+    let count = (params.count.ok_or("no params.count")?)(plugin);
+    for i in 0..count {
+        let mut info: clap_param_info = std::mem::zeroed();
+        if (params.get_info.ok_or("no params.get_info")?)(plugin, i, &mut info) {
+            let mut text = [0 as c_char; 64];
+            (params.value_to_text.ok_or("no value_to_text")?)(plugin, info.id, info.default_value, text.as_mut_ptr(), 64);
+            println!(
+                "param {}: {} [{} .. {}] default {}",
+                info.id,
+                CStr::from_ptr(info.name.as_ptr()).to_string_lossy(),
+                info.min_value,
+                info.max_value,
+                CStr::from_ptr(text.as_ptr()).to_string_lossy()
+            );
+        }
+    }
+    check(count == 5, "five parameters", &mut failures);
+
+    let ports = inst.extension::<clap_plugin_audio_ports>(CLAP_EXT_AUDIO_PORTS).ok_or("no clap.audio-ports")?;
+    let mut port: clap_audio_port_info = std::mem::zeroed();
+    let port_ok = (ports.get.ok_or("no audio_ports.get")?)(plugin, 0, true, &mut port);
+    check(port_ok && port.channel_count == 2, "stereo input port", &mut failures);
+
+    if !((*plugin).activate.ok_or("no activate")?)(plugin, SAMPLE_RATE, 1, BLOCK as u32) {
+        return Err("activate failed".to_string());
+    }
+    let latency_ext = inst.extension::<clap_plugin_latency>(CLAP_EXT_LATENCY).ok_or("no clap.latency")?;
+    let latency = (latency_ext.get.ok_or("no latency.get")?)(plugin) as usize;
+    println!("latency: {latency} samples");
+    check(latency > 0 && latency < BLOCK * 8, "latency reported", &mut failures);
+    ((*plugin).start_processing.ok_or("no start_processing")?)(plugin);
+
+    let sr = SAMPLE_RATE as f32;
+    let frames = (sr * 2.0) as usize;
+    let tone: Vec<f32> = (0..frames)
+        .map(|i| 0.5 * (2.0 * std::f32::consts::PI * TEST_HZ * i as f32 / sr).sin())
+        .collect();
+    let input = [tone.clone(), tone.clone()];
+
+    // 1. dry only: a pure delay
+    let out = inst.run(&input, vec![param_event(0, PARAM_MIX, 0.0)])?;
+    let delayed = out[0][latency..] == input[0][..frames - latency] && out[1] == out[0];
+    check(delayed, "mix 0 % = input delayed by the reported latency", &mut failures);
+
+    // 2. A minor, immediate retune
+    ((*plugin).reset.ok_or("no reset")?)(plugin);
+    let out = inst.run(
+        &input,
+        vec![
+            param_event(0, PARAM_KEY, 9.0),
+            param_event(0, PARAM_SCALE, 2.0),
+            param_event(0, PARAM_RETUNE, 0.0),
+            param_event(0, PARAM_MIX, 100.0),
+        ],
+    )?;
+    let hz = estimate_hz(&out[0][frames / 2..], sr);
+    println!("corrected: {TEST_HZ} Hz -> {hz:.2} Hz");
+    check((hz - EXPECTED_HZ).abs() < 1.0, "225 Hz is pulled to A3 in A minor", &mut failures);
+
+    // 3. state round trip
+    let state = inst.extension::<clap_plugin_state>(CLAP_EXT_STATE).ok_or("no clap.state")?;
+    let mut saved: Vec<u8> = Vec::new();
+    let ostream = clap_ostream {
+        ctx: &mut saved as *mut Vec<u8> as *mut c_void,
+        write: Some(stream_write),
+    };
+    let saved_ok = (state.save.ok_or("no state.save")?)(plugin, &ostream);
+    let get_value = params.get_value.ok_or("no params.get_value")?;
+    let flush = params.flush.ok_or("no params.flush")?;
+    let reset_key = vec![param_event(0, PARAM_KEY, 0.0)];
+    let reset_events = clap_input_events {
+        ctx: &reset_key as *const Vec<clap_event_param_value> as *mut c_void,
+        size: Some(events_size),
+        get: Some(events_get),
+    };
+    ((*plugin).stop_processing.ok_or("no stop_processing")?)(plugin);
+    flush(plugin, &reset_events, ptr::null());
+    let mut src: &[u8] = &saved;
+    let istream = clap_istream {
+        ctx: &mut src as *mut &[u8] as *mut c_void,
+        read: Some(stream_read),
+    };
+    let loaded_ok = (state.load.ok_or("no state.load")?)(plugin, &istream);
+    let mut key = 0.0;
+    get_value(plugin, PARAM_KEY, &mut key);
+    check(saved_ok && loaded_ok && key == 9.0, "state save/load restores the key", &mut failures);
+
+    ((*plugin).deactivate.ok_or("no deactivate")?)(plugin);
+    ((*plugin).destroy.ok_or("no destroy")?)(plugin);
+    (entry.deinit.ok_or("no deinit")?)();
+    dlclose(lib);
+    Ok(failures)
+}
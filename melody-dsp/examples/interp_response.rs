@@ -0,0 +1,178 @@
+//! シフタのディレイの補間方式ごとに、周波数特性と変調ノイズ（エイリアス）を測る。
+//!
+//!     cargo run --release --example interp_response
+//!
+//! 入力はディレイ長の半分（latency_samples）で割り切れる周期のサイン波にする。
+//! するとクロスフェードする 2 つのタップは同じ値を読むので、出力は補間の読み出し 1 本ぶんになり、
+//! 読み出し位置が一定の速さで動く（小数部がすべての値を通る）ときの補間の性質だけが見える。
+//! - gain: 出力の f × ratio の成分の振幅（dB、0 が理想。高域が落ちるほど負）
+//! - noise: それ以外の成分（変調ノイズ、エイリアス）と f × ratio の成分の比（dB、低いほどよい）
+//!
+//! 高次の補間が線形より悪い、または sinc が LIMITS を満たさなければ 0 以外で終わる。
+
+use std::f64::consts::PI;
+use std::time::Instant;
+
+use melody_dsp::MelodyShifter;
+
+const SAMPLE_RATE: f32 = 48000.0;
+const INTERPOLATIONS: [&str; 4] = ["linear", "hermite", "lagrange", "sinc"];
+const SEMITONES: [f32; 2] = [3.0, -5.0];
+// 入力の周波数（ディレイ長の半分の周期の倍数に丸める）
+const FREQUENCIES: [f32; 6] = [500.0, 2000.0, 5000.0, 10000.0, 14000.0, 18000.0];
+// 解析する長さ（シフタが動き出してから）
+const ANALYSIS: usize = 16384;
+// 出力の周波数を探す幅（0.1 cent 程度のずれが出る。主ローブの幅 SAMPLE_RATE / ANALYSIS より狭く）
+const SEARCH_HZ: f64 = 1.0;
+// sinc が満たすべき (この周波数以下の出力で, gain の下限 dB, noise の上限 dB)。
+// 読み出し位置は f32 で積算するので、その丸めの揺れ（下げるほうで大きく、高域ほど効く）が noise の床になる
+const LIMITS: [(f32, f64, f64); 2] = [(12000.0, -0.1, -45.0), (22000.0, -0.5, -40.0)];
+
+struct Measure {
+    gain_db: f64,
+    noise_db: f64,
+}
+
+/// hz 付近で残りが最小になる周波数を探して fit する。
+/// ディレイの読み出し位置は f32 で積算するので、出力の周波数は f × ratio からわずかにずれる。
+fn measure(y: &[f32], hz: f64) -> Measure {
+    // 黄金分割探索（±SEARCH_HZ）
+    let (mut lo, mut hi) = (hz - SEARCH_HZ, hz + SEARCH_HZ);
+    let g = (5.0_f64.sqrt() - 1.0) / 2.0;
+    for _ in 0..40 {
+        let (a, b) = (hi - g * (hi - lo), lo + g * (hi - lo));
+        if fit(y, a).noise_db < fit(y, b).noise_db {
+            hi = b;
+        } else {
+            lo = a;
+        }
+    }
+    fit(y, (lo + hi) / 2.0)
+}
+
+/// y を cos / sin（周波数 hz）と直流で最小二乗近似し、成分の振幅と残りの比を求める。
+fn fit(y: &[f32], hz: f64) -> Measure {
+    let w = 2.0 * PI * hz / SAMPLE_RATE as f64;
+    // 正規方程式（3 × 3）
+    let mut m = [[0.0_f64; 3]; 3];
+    let mut v = [0.0_f64; 3];
+    for (n, &s) in y.iter().enumerate() {
+        let basis = [(w * n as f64).cos(), (w * n as f64).sin(), 1.0];
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] += basis[i] * basis[j];
+            }
+            v[i] += basis[i] * s as f64;
+        }
+    }
+    let coef = solve3(m, v);
+    let mut residual = 0.0;
+    for (n, &s) in y.iter().enumerate() {
+        let fit = coef[0] * (w * n as f64).cos() + coef[1] * (w * n as f64).sin() + coef[2];
+        residual += (s as f64 - fit).powi(2);
+    }
+    let amp = coef[0].hypot(coef[1]);
+    let signal = amp * amp / 2.0 * y.len() as f64;
+    Measure {
+        gain_db: 20.0 * amp.log10(),
+        noise_db: 10.0 * (residual / signal).max(1.0e-30).log10(),
+    }
+}
+
+/// クラメルの公式
+fn solve3(m: [[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    let det = |m: &[[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let d = det(&m);
+    let mut out = [0.0; 3];
+    for (col, o) in out.iter_mut().enumerate() {
+        let mut mc = m;
+        for row in 0..3 {
+            mc[row][col] = v[row];
+        }
+        *o = det(&mc) / d;
+    }
+    out
+}
+
+fn main() -> Result<(), String> {
+    let half = MelodyShifter::new(SAMPLE_RATE).latency_samples();
+    let period_hz = SAMPLE_RATE / half as f32;
+    let warmup = 2 * half;
+    let frames = warmup + ANALYSIS;
+    let mut failures = Vec::new();
+
+    for semitones in SEMITONES {
+        let ratio = 2.0_f32.powf(semitones / 12.0);
+        println!("{semitones:+} semitones (ratio {ratio:.4})");
+        println!("{:>8} {:>8}  {}", "in Hz", "out Hz", INTERPOLATIONS.map(|n| format!("{n:>18}")).join(""));
+
+        for hz in FREQUENCIES {
+            let hz = (hz / period_hz).round() * period_hz;
+            let out_hz = hz * ratio;
+            if out_hz >= SAMPLE_RATE * 0.45 {
+                continue;
+            }
+            let input: Vec<f32> = (0..frames)
+                .map(|i| (2.0 * PI * hz as f64 * i as f64 / SAMPLE_RATE as f64).sin() as f32)
+                .collect();
+
+            let mut row = Vec::new();
+            for name in INTERPOLATIONS {
+                let mut shifter = MelodyShifter::with_interpolation(SAMPLE_RATE, name)?;
+                let mut y = input.clone();
+                for block in y.chunks_mut(128) {
+                    shifter.process_block(block, semitones);
+                }
+                row.push(measure(&y[warmup..], out_hz as f64));
+            }
+            println!(
+                "{hz:>8.0} {out_hz:>8.0}  {}",
+                row.iter().map(|m| format!("{:>8.2} {:>8.1} ", m.gain_db, m.noise_db)).collect::<String>()
+            );
+
+            let linear = &row[0];
+            for (name, m) in INTERPOLATIONS.iter().zip(&row).skip(1) {
+                // 丸めの差は許す
+                if m.gain_db < linear.gain_db - 0.01 || m.noise_db > linear.noise_db + 0.5 {
+                    failures.push(format!("{name} is worse than linear at {hz:.0} Hz ({semitones:+} semitones)"));
+                }
+            }
+            let sinc = &row[3];
+            if let Some(&(_, min_gain, max_noise)) = LIMITS.iter().find(|(below, _, _)| out_hz <= *below) {
+                if sinc.gain_db < min_gain || sinc.noise_db > max_noise {
+                    failures.push(format!(
+                        "sinc at {hz:.0} Hz ({semitones:+} semitones): gain {:.2} dB, noise {:.1} dB",
+                        sinc.gain_db, sinc.noise_db
+                    ));
+                }
+            }
+        }
+        println!();
+    }
+    println!("(gain dB / noise dB per interpolation)");
+
+    // 処理の重さ（10 秒ぶん）
+    let input: Vec<f32> = (0..(10.0 * SAMPLE_RATE) as usize)
+        .map(|i| (2.0 * PI * 440.0 * i as f64 / SAMPLE_RATE as f64).sin() as f32)
+        .collect();
+    for name in INTERPOLATIONS {
+        let mut shifter = MelodyShifter::with_interpolation(SAMPLE_RATE, name)?;
+        let mut y = input.clone();
+        let started = Instant::now();
+        for block in y.chunks_mut(128) {
+            shifter.process_block(block, 3.0);
+        }
+        println!("{name:>9}: 10 s in {:.3} s", started.elapsed().as_secs_f64());
+    }
+
+    if !failures.is_empty() {
+        for f in &failures {
+            eprintln!("FAIL {f}");
+        }
+        return Err(format!("{} check(s) failed", failures.len()));
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! 長いテイクのレンダリング時間を測る（倍音 EQ のフィルタ 24 本 + シフタ）。
+//!
+//!     cargo run --release --example render_bench -- --save /tmp/scalar.f32
+//!     cargo run --release --features simd --example render_bench -- --compare /tmp/scalar.f32
+//!
+//! --save は結果（f32 LE）を書き出し、--compare は書き出した結果と比べて
+//! 最大の差が TOLERANCE を超えたら 0 以外で終わる（simd feature の有無で同じ音になるかの確認）。
+
+use std::f32::consts::PI;
+use std::time::Instant;
+
+use melody_dsp::{MelodyEngine, MelodyShifter};
+
+const SAMPLE_RATE: f32 = 48000.0;
+const SECONDS: f32 = 60.0;
+const NOTE_SEC: f32 = 0.5;
+const TOLERANCE: f32 = 1.0e-3;
+// 計測は RUNS 回のうち最短を取る
+const RUNS: usize = 5;
+
+/// 倍音の多いのこぎり波風の信号（音高はノートごとに変わる）
+fn take(frames: usize) -> Vec<f32> {
+    let mut phase = 0.0_f32;
+    (0..frames)
+        .map(|i| {
+            let note = (i as f32 / SAMPLE_RATE / NOTE_SEC) as usize;
+            let hz = 220.0 * 2.0_f32.powf((note % 7) as f32 / 12.0);
+            phase = (phase + hz / SAMPLE_RATE).fract();
+            (1..=8).map(|h| (2.0 * PI * phase * h as f32).sin() / h as f32).sum::<f32>() * 0.3
+        })
+        .collect()
+}
+
+/// f を RUNS 回実行し、最短の時間（秒）と最後の結果を返す。
+fn best_of(f: impl Fn() -> Vec<f32>) -> (f64, Vec<f32>) {
+    let mut best = f64::INFINITY;
+    let mut out = Vec::new();
+    for _ in 0..RUNS {
+        let started = Instant::now();
+        out = f();
+        best = best.min(started.elapsed().as_secs_f64());
+    }
+    (best, out)
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let frames = (SECONDS * SAMPLE_RATE) as usize;
+    let input = take(frames);
+
+    let notes = (SECONDS / NOTE_SEC) as usize;
+    let mut engine = MelodyEngine::new(SAMPLE_RATE);
+    engine.set_harmonic_gains((0..24).map(|h| 1.0 + 0.5 * ((h % 3) as f32 - 1.0)).collect());
+    engine.set_notes(
+        (0..notes).map(|n| n as f32 * NOTE_SEC).collect(),
+        (0..notes).map(|n| (n as f32 + 0.9) * NOTE_SEC).collect(),
+        (0..notes).map(|n| 57.0 + (n % 7) as f32 + 0.3).collect(),
+        vec![0.0; notes],
+        vec![0.0; notes],
+        vec![1.0; notes],
+        vec![1.0; notes],
+        vec![1.0; notes],
+        vec![1.0; notes],
+        (0..notes).map(|n| (n % 5) as f32 - 2.0).collect(),
+        0,
+        Vec::new(),
+    );
+    engine.set_key(9.0, "minor");
+
+    let (engine_sec, output) = best_of(|| {
+        let mut output = input.clone();
+        engine.clone().process_buffer(&mut output);
+        output
+    });
+    let (shifter_sec, shifted) = best_of(|| {
+        let mut shifted = input.clone();
+        let mut shifter = MelodyShifter::new(SAMPLE_RATE);
+        for block in shifted.chunks_mut(128) {
+            shifter.process_block(block, 3.0);
+        }
+        shifted
+    });
+
+    let simd = if cfg!(feature = "simd") { "simd" } else { "scalar" };
+    println!("{simd}: {SECONDS} s take: engine {engine_sec:.3} s ({:.0}x real time), shifter only {shifter_sec:.3} s",
+        SECONDS as f64 / engine_sec);
+
+    match args.first().map(String::as_str) {
+        Some("--save") => {
+            let path = args.get(1).ok_or("--save needs a path")?;
+            let bytes: Vec<u8> = output.iter().chain(shifted.iter()).flat_map(|v| v.to_le_bytes()).collect();
+            std::fs::write(path, bytes).map_err(|e| format!("{path}: {e}"))?;
+        }
+        Some("--compare") => {
+            let path = args.get(1).ok_or("--compare needs a path")?;
+            let bytes = std::fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+            let saved: Vec<f32> = bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+            if saved.len() != output.len() + shifted.len() {
+                return Err(format!("{path}: length differs"));
+            }
+            let diff = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| (x - y).abs()).fold(0.0_f32, f32::max);
+            let (engine_diff, shifter_diff) = (diff(&output, &saved[..frames]), diff(&shifted, &saved[frames..]));
+            println!("max difference: engine {engine_diff:.2e}, shifter {shifter_diff:.2e} (tolerance {TOLERANCE:.0e})");
+            if engine_diff > TOLERANCE || shifter_diff > TOLERANCE {
+                return Err("outputs differ".to_string());
+            }
+        }
+        Some(other) => return Err(format!("unknown option: {other}")),
+        None => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+#ifndef MELODY_DSP_H
+#define MELODY_DSP_H
+
+/* Generated by cbindgen from src/capi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// エンジンと、最後に失敗した呼び出しの理由。
+typedef struct MelodyDspEngine MelodyDspEngine;
+
+// 1 ノート分のパラメータ（set_notes の配列 1 要素ずつと同じ意味・単位）。
+typedef struct MelodyNote {
+  // 秒
+  float start;
+  float end;
+  // 元の音高（MIDI ノート番号）
+  float base_semitone;
+  // 半音
+  float pitch_offset;
+  float pitch_center_offset;
+  // 0..2
+  float pitch_mod_amount;
+  float pitch_drift_amount;
+  // 0.5..2.0
+  float time_stretch_start;
+  float time_stretch_end;
+  // 半音
+  float formant_shift;
+} MelodyNote;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// エンジンを作る。使い終わったら melody_engine_free で解放する。
+struct MelodyDspEngine *melody_engine_new(float sample_rate);
+
+// # Safety
+// engine は melody_engine_new が返したもの（または NULL）で、まだ解放していないこと。
+void melody_engine_free(struct MelodyDspEngine *engine);
+
+// ノート列を置き換える（MelodyEngine::set_notes と同じく不正なノートは捨て、開始時刻順に並べる）。
+// harmonics は NULL か、count * harmonics_per_note 個のノートごとの倍音ゲイン。
+//
+// # Safety
+// engine は有効なハンドル、notes は NULL か count 個の MelodyNote を指していること。
+void melody_engine_set_notes(struct MelodyDspEngine *engine,
+                             const struct MelodyNote *notes,
+                             size_t count,
+                             const float *harmonics,
+                             uint32_t harmonics_per_note);
+
+// 全体の倍音ゲイン（倍音 1..count、線形）
+//
+// # Safety
+// engine は有効なハンドル、gains は NULL か count 個の float を指していること。
+void melody_engine_set_harmonic_gains(struct MelodyDspEngine *engine,
+                                      const float *gains,
+                                      size_t count);
+
+// キー/スケールを名前で設定する（major / minor / ... / chromatic）。未知の名前なら false。
+//
+// # Safety
+// engine は有効なハンドル、scale_name は NUL 終端の UTF-8 文字列であること。
+bool melody_engine_set_key(struct MelodyDspEngine *engine,
+                           float root_pitch_class,
+                           const char *scale_name);
+
+// # Safety
+// engine は有効なハンドルであること。
+void melody_engine_clear_key(struct MelodyDspEngine *engine);
+
+// 全ノートの補正量（0..100 %）
+//
+// # Safety
+// engine は有効なハンドルであること。
+void melody_engine_set_correction_strength(struct MelodyDspEngine *engine, float percent);
+
+// A4 の周波数（Hz）
+//
+// # Safety
+// engine は有効なハンドルであること。
+void melody_engine_set_reference_pitch(struct MelodyDspEngine *engine, float hz);
+
+// save_project の JSON を読み込む。失敗したら false（エンジンは変わらない、理由は melody_engine_last_error）。
+//
+// # Safety
+// engine は有効なハンドル、json は NUL 終端の UTF-8 文字列であること。
+bool melody_engine_load_project(struct MelodyDspEngine *engine,
+                                const char *json);
+
+// 最後に失敗した呼び出しの理由（なければ NULL）。次にそのハンドルを使うまで有効。
+//
+// # Safety
+// engine は有効なハンドルであること。
+const char *melody_engine_last_error(const struct MelodyDspEngine *engine);
+
+// 処理するバッファの最大の frames / channels を伝えて作業領域を確保する。
+// これ以下なら melody_engine_process_* はメモリを確保しない（オーディオスレッドから呼べる）。
+//
+// # Safety
+// engine は有効なハンドルであること。
+void melody_engine_set_max_buffer(struct MelodyDspEngine *engine,
+                                  size_t frames,
+                                  size_t channels);
+
+// インターリーブ（LRLR...）の frames * channels サンプルを in-place で処理する。
+// バッファの先頭をノートの時刻 0 として扱う（MelodyEngine::process_interleaved と同じ）。
+//
+// # Safety
+// engine は有効なハンドル、samples は NULL か frames * channels 個の float を指していること。
+void melody_engine_process_interleaved(struct MelodyDspEngine *engine,
+                                       float *samples,
+                                       size_t frames,
+                                       size_t channels);
+
+// planar（[ch0..., ch1..., ...]）の frames * channels サンプルを in-place で処理する。
+//
+// # Safety
+// engine は有効なハンドル、samples は NULL か frames * channels 個の float を指していること。
+void melody_engine_process_planar(struct MelodyDspEngine *engine,
+                                  float *samples,
+                                  size_t frames,
+                                  size_t channels);
+
+// エンジンの出力の遅れ（サンプル）。常に 0: process_* はシフタの遅れぶん入力を先読みして補償するので、
+// 出力は入力と揃っている（ホスト側で補償し直さないこと）。
+//
+// # Safety
+// engine は有効なハンドルであること。
+uint32_t melody_engine_latency_samples(const struct MelodyDspEngine *engine);
+
+// # Safety
+// engine は有効なハンドルであること。
+float melody_engine_sample_rate(const struct MelodyDspEngine *engine);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MELODY_DSP_H */
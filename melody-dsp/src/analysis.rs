@@ -0,0 +1,254 @@
+//! ピッチ解析（YIN）とノート分割。
+//!
+//! ノート分割は UI 側（note-detection.ts の detectNotesFromPitch）と同じ手順：
+//! 有声フレームを時間順に見て、間隔・音程の跳び・ばらつきが閾値を超えたところで切る。
+//! こちらはノートの音高を四捨五入せず、フレームの中央値（小数の MIDI）のまま返す。
+
+use crate::project::PitchFrame;
+use crate::tuning::Tuning;
+
+// YIN の探索範囲（Hz）と閾値
+const MIN_F0_HZ: f32 = 60.0;
+const MAX_F0_HZ: f32 = 1000.0;
+const YIN_THRESHOLD: f32 = 0.15;
+// これより小さいフレームは無声とみなす（RMS）
+const SILENCE_RMS: f32 = 1.0e-3;
+
+/// mono（複数チャンネルなら平均してから渡す）の f0 を hop_sec ごとに求める。
+pub(crate) fn detect_pitch(samples: &[f32], sample_rate: f32, hop_sec: f32) -> Vec<PitchFrame> {
+    let sr = sample_rate;
+    if !sr.is_finite() || sr <= 0.0 || samples.is_empty() {
+        return Vec::new();
+    }
+    let hop = ((if hop_sec.is_finite() && hop_sec > 0.0 { hop_sec } else { 0.01 }) * sr).round().max(1.0) as usize;
+
+    let (window, min_lag, max_lag) = yin_lags(sr);
+
+    let mut diff = vec![0.0_f32; max_lag + 1];
+    let mut frames = Vec::with_capacity(samples.len() / hop + 1);
+    let mut pos = 0;
+    while pos < samples.len() {
+        let time = pos as f32 / sr;
+        frames.push(yin_frame(samples, pos, window, min_lag, max_lag, sr, &mut diff).map_or(
+            PitchFrame {
+                time,
+                f0: None,
+                confidence: 0.0,
+            },
+            |(f0, confidence)| PitchFrame {
+                time,
+                f0: Some(f0),
+                confidence,
+            },
+        ));
+        pos += hop;
+    }
+    frames
+}
+
+/// YIN の (積分窓, 最小ラグ, 最大ラグ)（サンプル）。1 フレームには窓 + 最大ラグぶんの入力を使う。
+pub(crate) fn yin_lags(sample_rate: f32) -> (usize, usize, usize) {
+    let max_lag = ((sample_rate / MIN_F0_HZ).ceil() as usize).max(2);
+    let min_lag = ((sample_rate / MAX_F0_HZ).floor() as usize).max(2);
+    // 積分窓は最長周期ぶん
+    (max_lag, min_lag, max_lag)
+}
+
+/// pos から始まる窓の (f0, 信頼度)。無声なら None。diff は max_lag + 1 個以上。
+pub(crate) fn yin_frame(
+    samples: &[f32],
+    pos: usize,
+    window: usize,
+    min_lag: usize,
+    max_lag: usize,
+    sr: f32,
+    diff: &mut [f32],
+) -> Option<(f32, f32)> {
+    if pos + window + max_lag > samples.len() {
+        return None;
+    }
+    let x = &samples[pos..pos + window + max_lag];
+
+    let energy: f32 = x[..window].iter().map(|v| v * v).sum::<f32>() / window as f32;
+    if energy.sqrt() < SILENCE_RMS {
+        return None;
+    }
+
+    // difference function + cumulative mean normalisation
+    diff[0] = 1.0;
+    let mut running = 0.0_f32;
+    for tau in 1..=max_lag {
+        let d: f32 = (0..window)
+            .map(|j| {
+                let e = x[j] - x[j + tau];
+                e * e
+            })
+            .sum();
+        running += d;
+        diff[tau] = if running > 0.0 { d * tau as f32 / running } else { 1.0 };
+    }
+
+    // 閾値を下回った最初の谷（なければ全体の最小）
+    let mut tau = min_lag;
+    let mut best = None;
+    while tau < max_lag {
+        if diff[tau] < YIN_THRESHOLD {
+            while tau + 1 < max_lag && diff[tau + 1] < diff[tau] {
+                tau += 1;
+            }
+            best = Some(tau);
+            break;
+        }
+        tau += 1;
+    }
+    let tau = best.unwrap_or_else(|| {
+        (min_lag..max_lag)
+            .min_by(|&a, &b| diff[a].partial_cmp(&diff[b]).unwrap_or(std::cmp::Ordering::Equal))
+            .unwrap_or(min_lag)
+    });
+
+    let confidence = (1.0 - diff[tau]).clamp(0.0, 1.0);
+    if best.is_none() && confidence < 1.0 - 2.0 * YIN_THRESHOLD {
+        return None;
+    }
+
+    // parabolic interpolation around the dip
+    let (a, b, c) = (diff[tau - 1], diff[tau], diff[tau + 1]);
+    let denom = a - 2.0 * b + c;
+    let shift = if denom.abs() > 1.0e-12 { (0.5 * (a - c) / denom).clamp(-0.5, 0.5) } else { 0.0 };
+    let period = tau as f32 + shift;
+    let f0 = sr / period;
+    if !f0.is_finite() || f0 <= 0.0 {
+        return None;
+    }
+    Some((f0, confidence))
+}
+
+/// ノート分割のしきい値（note-detection.ts の NOTE_DETECTION_DEFAULTS と同じ既定値）
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct NoteDetectionConfig {
+    pub(crate) min_frame_confidence: f32,
+    pub(crate) max_gap_sec: f32,
+    pub(crate) max_jump_semitones: f32,
+    pub(crate) max_std_dev_semitones: f32,
+    pub(crate) min_note_sec: f32,
+    pub(crate) min_frames_per_note: usize,
+}
+
+impl Default for NoteDetectionConfig {
+    fn default() -> Self {
+        Self {
+            min_frame_confidence: 0.3,
+            max_gap_sec: 0.05,
+            max_jump_semitones: 1.2,
+            max_std_dev_semitones: 0.6,
+            min_note_sec: 0.06,
+            min_frames_per_note: 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DetectedNote {
+    pub(crate) start: f32,
+    pub(crate) end: f32,
+    // median pitch of the note's frames (fractional MIDI)
+    pub(crate) midi: f32,
+}
+
+/// ピッチフレーム列をノートに分ける。音高は tuning で Hz → ノート番号にする。
+pub(crate) fn detect_notes(frames: &[PitchFrame], tuning: &Tuning, cfg: &NoteDetectionConfig) -> Vec<DetectedNote> {
+    struct Voiced {
+        time: f32,
+        midi: f32,
+    }
+
+    let mut voiced: Vec<Voiced> = frames
+        .iter()
+        .filter_map(|f| {
+            let f0 = f.f0.filter(|v| v.is_finite() && *v > 0.0)?;
+            if f.confidence < cfg.min_frame_confidence {
+                return None;
+            }
+            let midi = tuning.hz_to_midi(f0);
+            midi.is_finite().then_some(Voiced { time: f.time, midi })
+        })
+        .collect();
+    if voiced.is_empty() {
+        return Vec::new();
+    }
+    voiced.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(std::cmp::Ordering::Equal));
+
+    let hop_sec = {
+        let deltas: Vec<f32> = voiced
+            .windows(2)
+            .map(|w| w[1].time - w[0].time)
+            .filter(|d| d.is_finite() && *d > 0.0)
+            .collect();
+        if voiced.len() <= 2 || deltas.is_empty() {
+            0.01
+        } else {
+            median(deltas)
+        }
+    };
+
+    let mut clusters: Vec<Vec<Voiced>> = Vec::new();
+    let mut cur: Vec<Voiced> = Vec::new();
+    for f in voiced {
+        if let Some(prev) = cur.last() {
+            let gap = f.time - prev.time;
+            let jump = (f.midi - prev.midi).abs();
+            // 「追加したら散らばりすぎる」なら分割
+            let spread = std_dev(cur.iter().map(|v| v.midi).chain(std::iter::once(f.midi)));
+            if !gap.is_finite() || gap > cfg.max_gap_sec || jump > cfg.max_jump_semitones || spread > cfg.max_std_dev_semitones {
+                clusters.push(std::mem::take(&mut cur));
+            }
+        }
+        cur.push(f);
+    }
+    if !cur.is_empty() {
+        clusters.push(cur);
+    }
+
+    clusters
+        .into_iter()
+        .filter(|cl| cl.len() >= cfg.min_frames_per_note.max(1))
+        .filter_map(|cl| {
+            let start = cl[0].time;
+            let end = cl[cl.len() - 1].time + hop_sec;
+            let dur = end - start;
+            if !dur.is_finite() || dur < cfg.min_note_sec {
+                return None;
+            }
+            Some(DetectedNote {
+                start,
+                end,
+                midi: median(cl.iter().map(|v| v.midi).collect()),
+            })
+        })
+        .collect()
+}
+
+fn median(mut values: Vec<f32>) -> f32 {
+    if values.is_empty() {
+        return f32::NAN;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        values[mid]
+    } else {
+        0.5 * (values[mid - 1] + values[mid])
+    }
+}
+
+/// 標本標準偏差（n - 1 で割る）
+fn std_dev(values: impl Iterator<Item = f32> + Clone) -> f32 {
+    let n = values.clone().count();
+    if n <= 1 {
+        return 0.0;
+    }
+    let mean = values.clone().sum::<f32>() / n as f32;
+    let var = values.map(|x| (x - mean) * (x - mean)).sum::<f32>() / (n - 1) as f32;
+    var.sqrt()
+}
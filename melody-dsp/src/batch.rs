@@ -0,0 +1,194 @@
+//! 複数テイクの一括処理：ピッチ解析 → ノート分割 → 補正 → レンダリング。
+//!
+//! 設定（キー・補正量・音律・倍音 EQ・純正律など）は手本のエンジンからプロジェクト JSON で
+//! 各テイク用の新しいエンジンに写す。parallel feature があればテイクはスレッドに分けて処理する
+//! （parallel::map_parallel、結果は同じ）。
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::cache::SourceAudio;
+use crate::parallel::map_parallel;
+use crate::{project, MelodyEngine};
+
+// この量（半音）を超える補正が掛かったノートを「補正あり」と数える
+const CORRECTED_EPS_SEMITONES: f32 = 0.005;
+
+struct Take {
+    name: String,
+    sample_rate: f32,
+    source: SourceAudio,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TakeReport {
+    name: String,
+    sample_rate: f32,
+    channels: usize,
+    frames: usize,
+    pitch_frames: usize,
+    voiced_frames: usize,
+    notes_detected: usize,
+    notes_corrected: usize,
+    // |correction| over all notes (cents)
+    mean_correction_cents: f32,
+    max_correction_cents: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchReport<'a> {
+    takes: usize,
+    failed: usize,
+    notes_detected: usize,
+    notes_corrected: usize,
+    reports: &'a [TakeReport],
+}
+
+/// テイクの一覧と処理結果。
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Batch {
+    takes: Vec<Take>,
+    // semitones, added to every detected note
+    formant_shift: f32,
+    // pitch analysis hop (sec)
+    hop_sec: f32,
+    outputs: Vec<Vec<f32>>,
+    reports: Vec<TakeReport>,
+}
+
+#[wasm_bindgen]
+impl Batch {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Batch {
+        Batch {
+            hop_sec: 0.01,
+            ..Batch::default()
+        }
+    }
+
+    /// テイクを追加し、そのインデックスを返す。input は planar（channels ch）。
+    #[wasm_bindgen]
+    pub fn add_take(&mut self, name: &str, sample_rate: f32, input: &[f32], channels: usize) -> usize {
+        self.takes.push(Take {
+            name: name.to_string(),
+            sample_rate,
+            source: SourceAudio::new(input, channels),
+        });
+        self.takes.len() - 1
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn take_count(&self) -> usize {
+        self.takes.len()
+    }
+
+    /// 全テイクのノートに足すフォルマントシフト（半音）
+    #[wasm_bindgen]
+    pub fn set_formant_shift(&mut self, semitones: f32) {
+        self.formant_shift = if semitones.is_finite() { semitones } else { 0.0 };
+    }
+
+    /// ピッチ解析の間隔（秒、既定 0.01）
+    #[wasm_bindgen]
+    pub fn set_hop(&mut self, hop_sec: f32) {
+        self.hop_sec = hop_sec;
+    }
+
+    /// settings（キー・補正量などを設定したエンジン）の設定で全テイクを処理する。
+    /// settings のノートは使わない（テイクごとに検出する）。失敗したテイクはレポートに残る。
+    #[wasm_bindgen]
+    pub fn run(&mut self, settings: &MelodyEngine) {
+        let json = project::save(settings);
+        let (formant, hop) = (self.formant_shift, self.hop_sec);
+        let results = map_parallel(&self.takes, |take| process_take(&json, take, formant, hop));
+        (self.outputs, self.reports) = results.into_iter().unzip();
+    }
+
+    /// add_take で付けた名前
+    #[wasm_bindgen]
+    pub fn take_name(&self, take: usize) -> Option<String> {
+        self.takes.get(take).map(|t| t.name.clone())
+    }
+
+    #[wasm_bindgen]
+    pub fn take_channels(&self, take: usize) -> usize {
+        self.takes.get(take).map_or(0, |t| t.source.channels())
+    }
+
+    /// テイク i が失敗していればその理由
+    #[wasm_bindgen]
+    pub fn take_error(&self, take: usize) -> Option<String> {
+        self.reports.get(take).and_then(|r| r.error.clone())
+    }
+
+    /// テイク i の補正後の音声（planar）。未処理/失敗なら空。
+    #[wasm_bindgen]
+    pub fn output(&self, take: usize) -> Vec<f32> {
+        self.outputs.get(take).cloned().unwrap_or_default()
+    }
+
+    /// 処理結果のまとめ（JSON）。テイクごとの検出ノート数・補正量と合計。
+    #[wasm_bindgen]
+    pub fn report_json(&self) -> String {
+        let report = BatchReport {
+            takes: self.reports.len(),
+            failed: self.reports.iter().filter(|r| r.error.is_some()).count(),
+            notes_detected: self.reports.iter().map(|r| r.notes_detected).sum(),
+            notes_corrected: self.reports.iter().map(|r| r.notes_corrected).sum(),
+            reports: &self.reports,
+        };
+        serde_json::to_string_pretty(&report).unwrap_or_default()
+    }
+}
+
+fn process_take(settings_json: &str, take: &Take, formant_shift: f32, hop_sec: f32) -> (Vec<f32>, TakeReport) {
+    let mut report = TakeReport {
+        name: take.name.clone(),
+        sample_rate: take.sample_rate,
+        channels: take.source.channels(),
+        frames: take.source.frames(),
+        ..TakeReport::default()
+    };
+
+    let mut engine = MelodyEngine::new(take.sample_rate);
+    let rendered = project::load(&mut engine, settings_json).and_then(|()| {
+        engine.set_audio_reference(&take.name, take.sample_rate, take.source.channels() as u32, take.source.frames() as u32);
+        engine.source = Some(take.source.clone());
+        engine.analyze_pitch(hop_sec)?;
+        engine.detect_notes();
+        if formant_shift != 0.0 {
+            for note in engine.notes.iter_mut() {
+                note.formant_shift += formant_shift;
+            }
+        }
+        engine.render_source()
+    });
+
+    let output = match rendered {
+        Ok(output) => output,
+        Err(e) => {
+            report.error = Some(e);
+            return (Vec::new(), report);
+        }
+    };
+
+    report.pitch_frames = engine.pitch_frames.len();
+    report.voiced_frames = engine.pitch_frames.iter().filter(|f| f.f0.is_some()).count();
+    report.notes_detected = engine.notes.len();
+    let corrections: Vec<f32> = engine
+        .notes
+        .iter()
+        .map(|n| (n.correction_offset + n.just_offset).abs())
+        .collect();
+    report.notes_corrected = corrections.iter().filter(|&&c| c > CORRECTED_EPS_SEMITONES).count();
+    if !corrections.is_empty() {
+        report.mean_correction_cents = 100.0 * corrections.iter().sum::<f32>() / corrections.len() as f32;
+        report.max_correction_cents = 100.0 * corrections.iter().fold(0.0_f32, |a, &b| a.max(b));
+    }
+    (output, report)
+}
@@ -0,0 +1,407 @@
+//! s-tune: WAV をノート/プロジェクト JSON に従って補正して書き出すコマンドライン版。
+//!
+//!     s-tune input.wav -o output.wav --project take1.json
+//!     s-tune input.wav -o output.wav --detect --key A --scale minor --strength 80
+//!     s-tune batch takes/ --out-dir corrected/ --key A --scale minor --report report.json
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use melody_dsp::{Batch, MelodyEngine, WavAudio};
+
+const USAGE: &str = "\
+usage: s-tune <input.wav> -o <output.wav> [options]
+       s-tune batch <input.wav | dir>... --out-dir <dir> [options]
+
+batch detects the notes of every take and applies the same settings to all of them
+(in parallel when built with --features parallel). Directories are expanded to the .wav files they contain.
+
+options:
+  -o, --output <file>        output WAV
+      --out-dir <dir>        output directory (batch; files keep their names, repeated names get -2, -3, ...)
+  -p, --project <file>       project JSON (save_project) or note track JSON
+                             (batch: used for its settings only)
+      --detect               analyse pitch and detect notes (replaces the project's notes; always on in batch)
+      --hop <sec>            pitch analysis hop (default 0.01)
+      --formant <semitones>  formant shift added to every note (batch)
+      --report <file>        write the batch summary JSON (default: print it)
+      --key <root>           key root: C, C#, Db, ... or 0..11 (enables scale correction)
+      --scale <name>         scale name for --key (default major)
+      --strength <percent>   correction strength 0..100 (default 100 with --key)
+      --reference <hz>       reference pitch for A4 (default 440)
+      --format <name>        output format: pcm16 / pcm24 / pcm32 / float32 / ... (default: input format)
+      --dither               TPDF dither when quantising
+      --save-project <file>  write the final project JSON
+  -q, --quiet                no progress output
+  -h, --help                 show this help";
+
+#[derive(Debug, Default)]
+struct Args {
+    batch: bool,
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    out_dir: Option<PathBuf>,
+    formant: f32,
+    report: Option<PathBuf>,
+    project: Option<PathBuf>,
+    detect: bool,
+    hop_sec: f32,
+    key: Option<f32>,
+    scale: String,
+    strength: Option<f32>,
+    reference_hz: Option<f32>,
+    format: Option<String>,
+    dither: bool,
+    save_project: Option<PathBuf>,
+    quiet: bool,
+}
+
+/// 引数（プログラム名を除く）を読む。--help なら None。
+fn parse_args(argv: impl IntoIterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut args = Args {
+        hop_sec: 0.01,
+        scale: "major".to_string(),
+        ..Args::default()
+    };
+
+    let mut it = argv.into_iter().peekable();
+    if it.peek().map(String::as_str) == Some("batch") {
+        it.next();
+        args.batch = true;
+        args.detect = true;
+    }
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| it.next().ok_or_else(|| format!("{name} needs a value"));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => args.output = Some(value(&arg)?.into()),
+            "--out-dir" => args.out_dir = Some(value(&arg)?.into()),
+            "--formant" => args.formant = parse_number(&arg, &value(&arg)?)?,
+            "--report" => args.report = Some(value(&arg)?.into()),
+            "-p" | "--project" => args.project = Some(value(&arg)?.into()),
+            "--detect" => args.detect = true,
+            "--hop" => args.hop_sec = parse_number(&arg, &value(&arg)?)?,
+            "--key" => args.key = Some(parse_pitch_class(&value(&arg)?)?),
+            "--scale" => args.scale = value(&arg)?,
+            "--strength" => args.strength = Some(parse_number(&arg, &value(&arg)?)?),
+            "--reference" => args.reference_hz = Some(parse_number(&arg, &value(&arg)?)?),
+            "--format" => args.format = Some(value(&arg)?),
+            "--dither" => args.dither = true,
+            "--save-project" => args.save_project = Some(value(&arg)?.into()),
+            "-q" | "--quiet" => args.quiet = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}")),
+            _ if args.batch || args.inputs.is_empty() => args.inputs.push(arg.into()),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+    Ok(Some(args))
+}
+
+fn parse_number(name: &str, v: &str) -> Result<f32, String> {
+    v.parse::<f32>()
+        .ok()
+        .filter(|x| x.is_finite())
+        .ok_or_else(|| format!("{name}: not a number: {v}"))
+}
+
+/// C, C#, Db, ... または 0..11
+fn parse_pitch_class(v: &str) -> Result<f32, String> {
+    if let Ok(n) = v.parse::<f32>() {
+        return Ok(n);
+    }
+    let mut chars = v.chars();
+    let base = match chars.next().map(|c| c.to_ascii_uppercase()) {
+        Some('C') => 0,
+        Some('D') => 2,
+        Some('E') => 4,
+        Some('F') => 5,
+        Some('G') => 7,
+        Some('A') => 9,
+        Some('B') => 11,
+        _ => return Err(format!("--key: unknown note {v}")),
+    };
+    let accidental = match chars.as_str() {
+        "" => 0,
+        "#" | "s" | "sharp" => 1,
+        "b" | "flat" => -1,
+        _ => return Err(format!("--key: unknown note {v}")),
+    };
+    Ok(((base + accidental) as i32).rem_euclid(12) as f32)
+}
+
+fn log(args: &Args, msg: String) {
+    if !args.quiet {
+        eprintln!("{msg}");
+    }
+}
+
+/// WAV を読んで (ファイル, planar のサンプル) を返す。
+fn read_wav(path: &Path) -> Result<(WavAudio, Vec<f32>), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let wav = WavAudio::decode(&bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+    let planar: Vec<f32> = (0..wav.channels()).flat_map(|ch| wav.channel(ch)).collect();
+    Ok((wav, planar))
+}
+
+/// planar のサンプルを入力と同じ形式（または --format）で書き出す。
+fn write_wav(path: &Path, like: &WavAudio, planar: &[f32], args: &Args) -> Result<(), String> {
+    let mut out = WavAudio::from_planar(like.sample_rate(), like.channels(), planar);
+    out.set_channel_mask(like.channel_mask());
+    let format = args.format.clone().unwrap_or_else(|| like.format());
+    if !out.set_format(&format) {
+        return Err(format!("--format: unknown format {format}"));
+    }
+    let bytes = out.encode(args.dither, 0).map_err(|e| format!("{}: {e}", path.display()))?;
+    std::fs::write(path, bytes).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// --project / --key / --strength / --reference をエンジンに反映する。
+fn apply_settings(engine: &mut MelodyEngine, args: &Args) -> Result<(), String> {
+    if let Some(path) = &args.project {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        engine.load_project(&json).map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    if let Some(hz) = args.reference_hz {
+        engine.set_reference_pitch(hz);
+    }
+    if let Some(root) = args.key {
+        if !engine.set_key(root, &args.scale) {
+            return Err(format!("--scale: unknown scale {}", args.scale));
+        }
+        if args.strength.is_none() {
+            engine.set_correction_strength(100.0);
+        }
+    }
+    if let Some(percent) = args.strength {
+        engine.set_correction_strength(percent);
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let input = args.inputs.first().ok_or("missing input WAV")?;
+    let output = args.output.as_ref().ok_or("missing -o <output.wav>")?;
+
+    let (wav, planar) = read_wav(input)?;
+    let channels = wav.channels();
+    log(
+        &args,
+        format!(
+            "{}: {} Hz, {} ch, {} frames, {}",
+            input.display(),
+            wav.sample_rate(),
+            channels,
+            wav.frames(),
+            wav.format()
+        ),
+    );
+
+    let mut engine = MelodyEngine::new(wav.sample_rate() as f32);
+    apply_settings(&mut engine, &args)?;
+    let name = input.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    engine.set_audio_reference(&name, wav.sample_rate() as f32, channels as u32, wav.frames() as u32);
+    engine.load_source(&planar, channels as usize);
+
+    if args.detect {
+        let frames = engine.analyze_pitch(args.hop_sec)?;
+        let notes = engine.detect_notes();
+        log(&args, format!("analysis: {frames} frames, {notes} notes"));
+    }
+    if engine.note_list().length() == 0 {
+        log(&args, "warning: no notes (the output is the input unchanged); use --project or --detect".to_string());
+    }
+
+    let rendered = engine.render_source()?;
+    write_wav(output, &wav, &rendered, &args)?;
+    log(&args, format!("wrote {}", output.display()));
+
+    if let Some(path) = &args.save_project {
+        std::fs::write(path, engine.save_project()).map_err(|e| format!("{}: {e}", path.display()))?;
+        log(&args, format!("wrote {}", path.display()));
+    }
+    Ok(())
+}
+
+/// 入力（ファイル/ディレクトリ）を .wav ファイルの一覧に広げる（ディレクトリ内は名前順）。
+fn expand_inputs(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for path in inputs {
+        if path.is_dir() {
+            let mut found: Vec<PathBuf> = std::fs::read_dir(path)
+                .map_err(|e| format!("{}: {e}", path.display()))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("wav")))
+                .collect();
+            found.sort();
+            files.extend(found);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// 出力ファイル名（= レポートのテイク名）。別のディレクトリの同じ名前は
+/// 2つ目から take-2.wav, take-3.wav, ... にして、上書きし合わないようにする。
+fn output_names(files: &[PathBuf]) -> Vec<String> {
+    let mut used = std::collections::HashSet::new();
+    files
+        .iter()
+        .map(|path| {
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let stem = path.file_stem().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+            // 大文字小文字を区別しないファイルシステムでもぶつからないように比べる
+            let unique = std::iter::once(name)
+                .chain((2..).map(|n| format!("{stem}-{n}{ext}")))
+                .find(|candidate| !used.contains(&candidate.to_lowercase()))
+                .unwrap_or_default();
+            used.insert(unique.to_lowercase());
+            unique
+        })
+        .collect()
+}
+
+fn run_batch(args: Args) -> Result<(), String> {
+    let out_dir = args.out_dir.as_ref().ok_or("batch: missing --out-dir <dir>")?;
+    let files = expand_inputs(&args.inputs)?;
+    if files.is_empty() {
+        return Err("batch: no input WAV files".to_string());
+    }
+    std::fs::create_dir_all(out_dir).map_err(|e| format!("{}: {e}", out_dir.display()))?;
+
+    // 設定の手本（サンプルレートはテイクごとに作り直すので何でもよい）
+    let mut settings = MelodyEngine::new(44100.0);
+    apply_settings(&mut settings, &args)?;
+
+    let mut batch = Batch::new();
+    batch.set_hop(args.hop_sec);
+    batch.set_formant_shift(args.formant);
+    let mut wavs = Vec::with_capacity(files.len());
+    for (path, name) in files.iter().zip(output_names(&files)) {
+        let (wav, planar) = read_wav(path)?;
+        if name != path.file_name().unwrap_or_default().to_string_lossy() {
+            log(&args, format!("{}: name already used, writing {name}", path.display()));
+        }
+        batch.add_take(&name, wav.sample_rate() as f32, &planar, wav.channels() as usize);
+        wavs.push(wav);
+    }
+    log(&args, format!("batch: {} takes", files.len()));
+
+    batch.run(&settings);
+
+    let mut failed = 0;
+    for (i, wav) in wavs.iter().enumerate() {
+        let name = batch.take_name(i).unwrap_or_default();
+        if let Some(e) = batch.take_error(i) {
+            failed += 1;
+            log(&args, format!("{name}: failed: {e}"));
+            continue;
+        }
+        let path = out_dir.join(&name);
+        write_wav(&path, wav, &batch.output(i), &args)?;
+        log(&args, format!("wrote {}", path.display()));
+    }
+
+    let report = batch.report_json();
+    match &args.report {
+        Some(path) => std::fs::write(path, report).map_err(|e| format!("{}: {e}", path.display()))?,
+        None => println!("{report}"),
+    }
+    if failed > 0 {
+        return Err(format!("batch: {failed} of {} takes failed", files.len()));
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)) {
+        Ok(None) => {
+            println!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        Ok(Some(args)) => match if args.batch { run_batch(args) } else { run(args) } {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("s-tune: {e}");
+                ExitCode::FAILURE
+            }
+        },
+        Err(e) => {
+            eprintln!("s-tune: {e}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Option<Args>, String> {
+        parse_args(line.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn single_take_options() {
+        let args = parse("in.wav -o out.wav --key F# --scale minor --strength 80 --reference 432 --format pcm24 --dither -q")
+            .unwrap()
+            .unwrap();
+        assert!(!args.batch && !args.detect);
+        assert_eq!(args.inputs, vec![PathBuf::from("in.wav")]);
+        assert_eq!(args.output, Some(PathBuf::from("out.wav")));
+        assert_eq!((args.key, args.scale.as_str(), args.strength), (Some(6.0), "minor", Some(80.0)));
+        assert_eq!(args.reference_hz, Some(432.0));
+        assert_eq!(args.format.as_deref(), Some("pcm24"));
+        assert!(args.dither && args.quiet);
+        // 既定値
+        let args = parse("in.wav -o out.wav").unwrap().unwrap();
+        assert_eq!((args.hop_sec, args.scale.as_str(), args.key), (0.01, "major", None));
+    }
+
+    #[test]
+    fn batch_takes_several_inputs_and_always_detects() {
+        let args = parse("batch a.wav takes/ --out-dir out --formant -1.5 --report r.json").unwrap().unwrap();
+        assert!(args.batch && args.detect);
+        assert_eq!(args.inputs, vec![PathBuf::from("a.wav"), PathBuf::from("takes/")]);
+        assert_eq!(args.out_dir, Some(PathBuf::from("out")));
+        assert_eq!(args.formant, -1.5);
+        assert_eq!(args.report, Some(PathBuf::from("r.json")));
+        // "batch" は先頭でだけサブコマンド
+        assert_eq!(parse("in.wav batch").unwrap_err(), "unexpected argument batch");
+    }
+
+    #[test]
+    fn bad_arguments_are_reported() {
+        assert!(parse("in.wav --help").unwrap().is_none());
+        assert_eq!(parse("in.wav -o").unwrap_err(), "-o needs a value");
+        assert_eq!(parse("in.wav --frobnicate").unwrap_err(), "unknown option --frobnicate");
+        assert_eq!(parse("a.wav b.wav").unwrap_err(), "unexpected argument b.wav");
+        assert_eq!(parse("in.wav --strength NaN").unwrap_err(), "--strength: not a number: NaN");
+        assert_eq!(parse("in.wav --hop x").unwrap_err(), "--hop: not a number: x");
+        assert_eq!(parse("in.wav --key H").unwrap_err(), "--key: unknown note H");
+    }
+
+    #[test]
+    fn batch_output_names_do_not_collide() {
+        let files: Vec<PathBuf> = ["a/take.wav", "b/take.wav", "take-2.wav", "c/Take.WAV", "d/other.wav", "e/take.wav"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(
+            output_names(&files),
+            vec!["take.wav", "take-2.wav", "take-2-2.wav", "Take-3.WAV", "other.wav", "take-4.wav"]
+        );
+    }
+
+    #[test]
+    fn pitch_classes() {
+        let pcs: Vec<f32> = ["C", "c#", "Db", "Eb", "E", "Fsharp", "Bb", "B#", "Cb", "9"]
+            .iter()
+            .map(|v| parse_pitch_class(v).unwrap())
+            .collect();
+        assert_eq!(pcs, vec![0.0, 1.0, 1.0, 3.0, 4.0, 6.0, 10.0, 0.0, 11.0, 9.0]);
+        assert!(parse_pitch_class("Ex").is_err());
+        assert!(parse_pitch_class("").is_err());
+    }
+}
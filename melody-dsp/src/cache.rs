@@ -0,0 +1,288 @@
+//! 編集後の部分再レンダリング（ノート単位のレンダリングキャッシュ）。
+//!
+//! ノート列を「間にバイパス区間を挟まずにつながるノートの塊（グループ）」に分け、
+//! グループごとに音に効くパラメータの指紋を持つ。更新時は指紋が変わったグループと
+//! 消えたグループの範囲だけを元音声から作り直す。
+//!
+//! シフタはノートの外ではバイパスし、音色フィルタもノート内しか掛けないので、
+//! グループの外に尾は残らない。ノートに入るときにシフタの位相を戻すので（process_segments）、
+//! 各グループは前後のノートと無関係に、全体を一括で処理した場合と同じ結果になる。
+//! ただしノート内のシフタは前後にディレイ長（最大 40ms）の半分ずつの入力を読むので、
+//! 前はディレイ長ぶんを先に流し、後ろも先読みぶんを余分に渡しておく。
+//!
+//! グループどうしは独立なので、作り直すグループは parallel feature があればスレッドに分けて
+//! レンダリングする（parallel::map_parallel）。グループの間はバイパス（元音声そのまま）なので、つなぎ目に
+//! クロスフェードは要らず、結果は順番にレンダリングした場合とビット単位で同じになる。
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::parallel::map_parallel;
+use crate::{ChannelState, MelodyEngine, RenderCursor};
+
+// グループ範囲の前後に足す余白（サンプル）。ノート境界の丸め分を確実に含める
+const EDGE_MARGIN: usize = 2;
+// ブロック長（process_segments の BLOCK_SAMPLES）以上。グループの後ろをこれだけ余分に流し、
+// ノート末尾のブロックの区切りを一括処理と揃える
+const TAIL_SAMPLES: usize = 256;
+
+#[derive(Clone, Debug)]
+struct RenderGroup {
+    // samples (half-open), including EDGE_MARGIN
+    range: Range<usize>,
+    // indices into engine.notes
+    notes: Range<usize>,
+    signature: u64,
+}
+
+impl RenderGroup {
+    /// 同じ範囲を同じ内容で鳴らすか（ノートの位置が前後にずれても変わらない）
+    fn same_as(&self, other: &RenderGroup) -> bool {
+        self.range == other.range && self.signature == other.signature
+    }
+}
+
+/// エンジンが持つ元音声（planar）。処理では書き換えないので、クローンどうしで共有する。
+#[derive(Clone)]
+pub(crate) struct SourceAudio {
+    samples: Arc<[f32]>,
+    channels: usize,
+    frames: usize,
+}
+
+impl SourceAudio {
+    pub(crate) fn new(input: &[f32], channels: usize) -> Self {
+        let channels = channels.max(1);
+        let frames = input.len() / channels;
+        Self {
+            samples: input[..frames * channels].into(),
+            channels,
+            frames,
+        }
+    }
+
+    pub(crate) fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub(crate) fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub(crate) fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// チャンネル平均（モノラル）
+    pub(crate) fn mono(&self) -> Vec<f32> {
+        let (channels, frames) = (self.channels, self.frames);
+        (0..frames)
+            .map(|i| (0..channels).map(|ch| self.samples[ch * frames + i]).sum::<f32>() / channels as f32)
+            .collect()
+    }
+}
+
+/// 元音声と、最後にレンダリングした結果。
+pub(crate) struct RenderCache {
+    source: SourceAudio,
+    output: Vec<f32>,
+    channels: usize,
+    frames: usize,
+    groups: Vec<RenderGroup>,
+}
+
+impl RenderCache {
+    /// 全グループをレンダリングしたキャッシュを作る。
+    pub(crate) fn build(engine: &MelodyEngine, source: SourceAudio) -> Self {
+        let mut cache = Self {
+            output: source.samples().to_vec(),
+            channels: source.channels(),
+            frames: source.frames(),
+            source,
+            groups: Vec::new(),
+        };
+        cache.update(engine);
+        cache
+    }
+
+    pub(crate) fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub(crate) fn output(&self) -> &[f32] {
+        &self.output
+    }
+
+    /// [start, end) サンプル（planar, チャンネルごとに end - start 個）。範囲は丸める。
+    pub(crate) fn region(&self, start: usize, end: usize) -> Vec<f32> {
+        let end = end.min(self.frames);
+        let start = start.min(end);
+        let mut out = Vec::with_capacity((end - start) * self.channels);
+        for ch in 0..self.channels {
+            out.extend_from_slice(&self.output[ch * self.frames + start..ch * self.frames + end]);
+        }
+        out
+    }
+
+    /// 現在のノート列/設定に合わせて変わったグループだけ作り直し、
+    /// 書き換えたサンプル範囲（重なりなし、昇順）を返す。
+    pub(crate) fn update(&mut self, engine: &MelodyEngine) -> Vec<Range<usize>> {
+        let groups = group_notes(engine, self.frames);
+
+        let mut changed: Vec<Range<usize>> = Vec::new();
+        // 消えた/変わったグループは一旦ドライに戻す
+        for old in self.groups.iter().filter(|g| !groups.iter().any(|n| n.same_as(g))) {
+            for ch in 0..self.channels {
+                let base = ch * self.frames;
+                let r = base + old.range.start..base + old.range.end;
+                self.output[r.clone()].copy_from_slice(&self.source.samples()[r]);
+            }
+            changed.push(old.range.clone());
+        }
+        let old = std::mem::replace(&mut self.groups, groups.clone());
+        let fresh: Vec<&RenderGroup> = groups.iter().filter(|g| !old.iter().any(|o| o.same_as(g))).collect();
+        let rendered = map_parallel(&fresh, |g| self.render_group(engine, g));
+        for (g, samples) in fresh.iter().zip(rendered) {
+            let n = g.range.end - g.range.start;
+            for (ch, plane) in samples.chunks_exact(n).enumerate() {
+                let dst = ch * self.frames + g.range.start;
+                self.output[dst..dst + n].copy_from_slice(plane);
+            }
+            changed.push(g.range.clone());
+        }
+
+        changed.sort_by_key(|r| r.start);
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(changed.len());
+        for r in changed {
+            match merged.last_mut() {
+                Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+                _ => merged.push(r),
+            }
+        }
+        merged
+    }
+
+    /// グループ 1 つを、そのノートだけを持つ新しい処理状態でレンダリングする。
+    /// 戻り値はグループの範囲だけの planar（チャンネルごとに range の長さ）。
+    fn render_group(&self, engine: &MelodyEngine, g: &RenderGroup) -> Vec<f32> {
+        let sr = engine.sample_rate;
+        let max_delay = engine.channels[0].shifter.max_delay;
+
+        // ディレイに入る入力を先に流す（出力は捨てる）
+        let pre = g.range.start.saturating_sub(max_delay);
+        let end = (g.range.end + TAIL_SAMPLES).min(self.frames);
+        // シフタが先読みする入力（end までのレンダリングに使う）
+        let input_end = (end + engine.shifter_latency()).min(self.frames);
+
+        let mut e = engine.clone();
+        e.notes = engine.notes[g.notes.clone()].to_vec();
+        e.channels = (0..self.channels)
+            .map(|_| {
+                let mut state = ChannelState::new(sr, engine.interpolation);
+                // リングバッファ上の位置を一括処理と揃える（補間の丸めまで一致させる）
+                state.shifter.write_idx = pre % state.shifter.max_delay;
+                state
+            })
+            .collect();
+
+        let mut scratch: Vec<f32> = Vec::with_capacity((input_end - pre) * self.channels);
+        for ch in 0..self.channels {
+            let base = ch * self.frames;
+            scratch.extend_from_slice(&self.source.samples()[base + pre..base + input_end]);
+        }
+        let mut cursor = RenderCursor {
+            sample_idx: pre,
+            ..RenderCursor::default()
+        };
+        e.process_segments(&mut scratch, input_end - pre, pre, &mut cursor, end);
+
+        let n = g.range.end - g.range.start;
+        let mut out = Vec::with_capacity(n * self.channels);
+        for ch in 0..self.channels {
+            let src = ch * (input_end - pre) + (g.range.start - pre);
+            out.extend_from_slice(&scratch[src..src + n]);
+        }
+        out
+    }
+}
+
+/// エンジンのクローン（ハモり/ダブリング用など）にはキャッシュを持ち越さない。
+#[derive(Default)]
+pub(crate) struct CacheSlot(pub(crate) Option<RenderCache>);
+
+impl Clone for CacheSlot {
+    fn clone(&self) -> Self {
+        Self(None)
+    }
+}
+
+/// 開始時刻順のノートを、範囲が重なる/接するものどうしでまとめる。
+fn group_notes(engine: &MelodyEngine, frames: usize) -> Vec<RenderGroup> {
+    let sr = engine.sample_rate;
+    let mut groups: Vec<RenderGroup> = Vec::new();
+    if !sr.is_finite() || sr <= 0.0 || frames == 0 {
+        return groups;
+    }
+
+    for (i, note) in engine.notes.iter().enumerate() {
+        let lo = ((note.start * sr).floor().max(0.0) as usize).saturating_sub(EDGE_MARGIN);
+        let hi = ((note.end * sr).ceil().max(0.0) as usize).saturating_add(EDGE_MARGIN);
+        let lo = lo.min(frames);
+        let hi = hi.min(frames);
+        if lo >= hi {
+            continue;
+        }
+
+        match groups.last_mut() {
+            Some(last) if lo <= last.range.end => {
+                last.range.end = last.range.end.max(hi);
+                last.notes.end = i + 1;
+            }
+            _ => groups.push(RenderGroup {
+                range: lo..hi,
+                notes: i..i + 1,
+                signature: 0,
+            }),
+        }
+    }
+
+    for g in groups.iter_mut() {
+        g.signature = signature(engine, g.notes.clone());
+    }
+    groups
+}
+
+/// グループの出力に効くものすべての指紋。
+fn signature(engine: &MelodyEngine, notes: Range<usize>) -> u64 {
+    let mut h = DefaultHasher::new();
+    engine.sample_rate.to_bits().hash(&mut h);
+    engine.interpolation.hash(&mut h);
+    for g in engine.harmonic_eq.gains.iter() {
+        g.to_bits().hash(&mut h);
+    }
+    for note in engine.notes[notes].iter() {
+        for v in [
+            note.start,
+            note.end,
+            note.base_semitone,
+            note.pitch_offset,
+            note.pitch_center_offset,
+            note.pitch_mod_amount,
+            note.pitch_drift_amount,
+            note.time_stretch_start,
+            note.time_stretch_end,
+            note.formant_shift,
+            note.correction_offset,
+            note.just_offset,
+        ] {
+            v.to_bits().hash(&mut h);
+        }
+        note.harmonic_profile.len().hash(&mut h);
+        for g in note.harmonic_profile.iter() {
+            g.to_bits().hash(&mut h);
+        }
+    }
+    h.finish()
+}
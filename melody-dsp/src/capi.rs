@@ -0,0 +1,278 @@
+//! C/C++ ホストから使うための extern "C" API（ネイティブのみ）。
+//!
+//! エンジンは不透明なハンドル（MelodyDspEngine *）として渡し、melody_engine_free で解放する。
+//! ヘッダは include/melody_dsp.h（cbindgen で生成。cbindgen.toml を参照）。
+//! 関数はどれも NULL のハンドル/ポインタを受け付け、その場合は何もしない（または 0 / false を返す）。
+//! 1 つのハンドルを複数スレッドから同時に使ってはいけない。
+
+use std::ffi::{c_char, CStr, CString};
+use std::slice;
+
+use crate::MelodyEngine;
+
+/// エンジンと、最後に失敗した呼び出しの理由。
+pub struct MelodyDspEngine {
+    engine: MelodyEngine,
+    last_error: Option<CString>,
+}
+
+impl MelodyDspEngine {
+    fn fail(&mut self, message: String) -> bool {
+        // NUL を含むメッセージは作らないが、念のため落とさない
+        self.last_error = Some(CString::new(message.replace('\0', " ")).unwrap_or_default());
+        false
+    }
+}
+
+/// 1 ノート分のパラメータ（set_notes の配列 1 要素ずつと同じ意味・単位）。
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MelodyNote {
+    /// 秒
+    pub start: f32,
+    pub end: f32,
+    /// 元の音高（MIDI ノート番号）
+    pub base_semitone: f32,
+    /// 半音
+    pub pitch_offset: f32,
+    pub pitch_center_offset: f32,
+    /// 0..2
+    pub pitch_mod_amount: f32,
+    pub pitch_drift_amount: f32,
+    /// 0.5..2.0
+    pub time_stretch_start: f32,
+    pub time_stretch_end: f32,
+    /// 半音
+    pub formant_shift: f32,
+}
+
+/// # Safety
+/// ptr は NULL か、len 個の T を指していること。
+unsafe fn slice_or_empty<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
+    if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    }
+}
+
+/// # Safety
+/// ptr は NULL か、len 個の T を指していること。
+unsafe fn slice_or_empty_mut<'a, T>(ptr: *mut T, len: usize) -> &'a mut [T] {
+    if ptr.is_null() || len == 0 {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(ptr, len)
+    }
+}
+
+/// エンジンを作る。使い終わったら melody_engine_free で解放する。
+#[no_mangle]
+pub extern "C" fn melody_engine_new(sample_rate: f32) -> *mut MelodyDspEngine {
+    Box::into_raw(Box::new(MelodyDspEngine {
+        engine: MelodyEngine::new(sample_rate),
+        last_error: None,
+    }))
+}
+
+/// # Safety
+/// engine は melody_engine_new が返したもの（または NULL）で、まだ解放していないこと。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_free(engine: *mut MelodyDspEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// ノート列を置き換える（MelodyEngine::set_notes と同じく不正なノートは捨て、開始時刻順に並べる）。
+/// harmonics は NULL か、count * harmonics_per_note 個のノートごとの倍音ゲイン。
+///
+/// # Safety
+/// engine は有効なハンドル、notes は NULL か count 個の MelodyNote を指していること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_set_notes(
+    engine: *mut MelodyDspEngine,
+    notes: *const MelodyNote,
+    count: usize,
+    harmonics: *const f32,
+    harmonics_per_note: u32,
+) {
+    let Some(h) = engine.as_mut() else { return };
+    let notes = slice_or_empty(notes, count);
+    let harmonics = slice_or_empty(harmonics, count * harmonics_per_note as usize);
+    let col = |f: fn(&MelodyNote) -> f32| notes.iter().map(f).collect::<Vec<f32>>();
+    h.engine.set_notes(
+        col(|n| n.start),
+        col(|n| n.end),
+        col(|n| n.base_semitone),
+        col(|n| n.pitch_offset),
+        col(|n| n.pitch_center_offset),
+        col(|n| n.pitch_mod_amount),
+        col(|n| n.pitch_drift_amount),
+        col(|n| n.time_stretch_start),
+        col(|n| n.time_stretch_end),
+        col(|n| n.formant_shift),
+        if harmonics.is_empty() { 0 } else { harmonics_per_note },
+        harmonics.to_vec(),
+    );
+}
+
+/// 全体の倍音ゲイン（倍音 1..count、線形）
+///
+/// # Safety
+/// engine は有効なハンドル、gains は NULL か count 個の float を指していること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_set_harmonic_gains(engine: *mut MelodyDspEngine, gains: *const f32, count: usize) {
+    if let Some(h) = engine.as_mut() {
+        h.engine.set_harmonic_gains(slice_or_empty(gains, count).to_vec());
+    }
+}
+
+/// キー/スケールを名前で設定する（major / minor / ... / chromatic）。未知の名前なら false。
+///
+/// # Safety
+/// engine は有効なハンドル、scale_name は NUL 終端の UTF-8 文字列であること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_set_key(
+    engine: *mut MelodyDspEngine,
+    root_pitch_class: f32,
+    scale_name: *const c_char,
+) -> bool {
+    let Some(h) = engine.as_mut() else { return false };
+    if scale_name.is_null() {
+        return h.fail("scale name is NULL".to_string());
+    }
+    let Ok(name) = CStr::from_ptr(scale_name).to_str() else {
+        return h.fail("scale name is not UTF-8".to_string());
+    };
+    if !h.engine.set_key(root_pitch_class, name) {
+        return h.fail(format!("unknown scale: {name}"));
+    }
+    true
+}
+
+/// # Safety
+/// engine は有効なハンドルであること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_clear_key(engine: *mut MelodyDspEngine) {
+    if let Some(h) = engine.as_mut() {
+        h.engine.clear_key();
+    }
+}
+
+/// 全ノートの補正量（0..100 %）
+///
+/// # Safety
+/// engine は有効なハンドルであること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_set_correction_strength(engine: *mut MelodyDspEngine, percent: f32) {
+    if let Some(h) = engine.as_mut() {
+        h.engine.set_correction_strength(percent);
+    }
+}
+
+/// A4 の周波数（Hz）
+///
+/// # Safety
+/// engine は有効なハンドルであること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_set_reference_pitch(engine: *mut MelodyDspEngine, hz: f32) {
+    if let Some(h) = engine.as_mut() {
+        h.engine.set_reference_pitch(hz);
+    }
+}
+
+/// save_project の JSON を読み込む。失敗したら false（エンジンは変わらない、理由は melody_engine_last_error）。
+///
+/// # Safety
+/// engine は有効なハンドル、json は NUL 終端の UTF-8 文字列であること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_load_project(engine: *mut MelodyDspEngine, json: *const c_char) -> bool {
+    let Some(h) = engine.as_mut() else { return false };
+    if json.is_null() {
+        return h.fail("project JSON is NULL".to_string());
+    }
+    let Ok(text) = CStr::from_ptr(json).to_str() else {
+        return h.fail("project JSON is not UTF-8".to_string());
+    };
+    match h.engine.load_project(text) {
+        Ok(()) => true,
+        Err(e) => h.fail(e),
+    }
+}
+
+/// 最後に失敗した呼び出しの理由（なければ NULL）。次にそのハンドルを使うまで有効。
+///
+/// # Safety
+/// engine は有効なハンドルであること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_last_error(engine: *const MelodyDspEngine) -> *const c_char {
+    engine
+        .as_ref()
+        .and_then(|h| h.last_error.as_ref())
+        .map_or(std::ptr::null(), |e| e.as_ptr())
+}
+
+/// 処理するバッファの最大の frames / channels を伝えて作業領域を確保する。
+/// これ以下なら melody_engine_process_* はメモリを確保しない（オーディオスレッドから呼べる）。
+///
+/// # Safety
+/// engine は有効なハンドルであること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_set_max_buffer(engine: *mut MelodyDspEngine, frames: usize, channels: usize) {
+    if let Some(h) = engine.as_mut() {
+        h.engine.set_max_buffer(frames, channels);
+    }
+}
+
+/// インターリーブ（LRLR...）の frames * channels サンプルを in-place で処理する。
+/// バッファの先頭をノートの時刻 0 として扱う（MelodyEngine::process_interleaved と同じ）。
+///
+/// # Safety
+/// engine は有効なハンドル、samples は NULL か frames * channels 個の float を指していること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_process_interleaved(
+    engine: *mut MelodyDspEngine,
+    samples: *mut f32,
+    frames: usize,
+    channels: usize,
+) {
+    if let Some(h) = engine.as_mut() {
+        let channels = channels.max(1);
+        h.engine.process_interleaved(slice_or_empty_mut(samples, frames * channels), channels);
+    }
+}
+
+/// planar（[ch0..., ch1..., ...]）の frames * channels サンプルを in-place で処理する。
+///
+/// # Safety
+/// engine は有効なハンドル、samples は NULL か frames * channels 個の float を指していること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_process_planar(
+    engine: *mut MelodyDspEngine,
+    samples: *mut f32,
+    frames: usize,
+    channels: usize,
+) {
+    if let Some(h) = engine.as_mut() {
+        let channels = channels.max(1);
+        h.engine.process_planar(slice_or_empty_mut(samples, frames * channels), channels);
+    }
+}
+
+/// エンジンの出力の遅れ（サンプル）。常に 0: process_* はシフタの遅れぶん入力を先読みして補償するので、
+/// 出力は入力と揃っている（ホスト側で補償し直さないこと）。
+///
+/// # Safety
+/// engine は有効なハンドルであること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_latency_samples(engine: *const MelodyDspEngine) -> u32 {
+    engine.as_ref().map_or(0, |h| h.engine.latency_samples() as u32)
+}
+
+/// # Safety
+/// engine は有効なハンドルであること。
+#[no_mangle]
+pub unsafe extern "C" fn melody_engine_sample_rate(engine: *const MelodyDspEngine) -> f32 {
+    engine.as_ref().map_or(0.0, |h| h.engine.sample_rate())
+}
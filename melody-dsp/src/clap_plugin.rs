@@ -0,0 +1,672 @@
+//! CLAP プラグイン（feature "clap"）。LiveTuner をホストの process コールバックで鳴らす。
+//!
+//! パラメータ：キー・スケール・リチューン速度・フォルマント・ミックス（すべてオートメーション可）。
+//! ステレオ入出力 1 組、遅れは clap.latency で報告し、パラメータは clap.state で保存する。
+//! cdylib（libmelody_dsp.so）が clap_entry を公開するので、拡張子を .clap にして置けば読み込める。
+
+use std::cell::UnsafeCell;
+use std::ffi::{c_char, c_void, CStr};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use clap_sys::audio_buffer::clap_audio_buffer;
+use clap_sys::entry::clap_plugin_entry;
+use clap_sys::events::{
+    clap_event_header, clap_event_param_value, clap_input_events, clap_output_events, CLAP_CORE_EVENT_SPACE_ID,
+    CLAP_EVENT_PARAM_VALUE,
+};
+use clap_sys::ext::audio_ports::{
+    clap_audio_port_info, clap_plugin_audio_ports, CLAP_AUDIO_PORT_IS_MAIN, CLAP_EXT_AUDIO_PORTS, CLAP_PORT_STEREO,
+};
+use clap_sys::ext::latency::{clap_plugin_latency, CLAP_EXT_LATENCY};
+use clap_sys::ext::params::{
+    clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS, CLAP_PARAM_IS_AUTOMATABLE, CLAP_PARAM_IS_ENUM,
+    CLAP_PARAM_IS_STEPPED,
+};
+use clap_sys::ext::state::{clap_plugin_state, CLAP_EXT_STATE};
+use clap_sys::factory::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID};
+use clap_sys::host::clap_host;
+use clap_sys::id::clap_id;
+use clap_sys::plugin::{clap_plugin, clap_plugin_descriptor};
+use clap_sys::process::{clap_process, clap_process_status, CLAP_PROCESS_CONTINUE, CLAP_PROCESS_ERROR};
+use clap_sys::stream::{clap_istream, clap_ostream};
+use clap_sys::version::CLAP_VERSION;
+
+use crate::scale::Scale;
+use crate::LiveTuner;
+
+const PLUGIN_ID: &CStr = c"dev.s-tune.melody-dsp.autotune";
+const CHANNELS: usize = 2;
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+// Scale::from_name で引ける名前（パラメータ値 = この並びの位置）
+const SCALE_NAMES: [&str; 13] = [
+    "chromatic",
+    "major",
+    "minor",
+    "dorian",
+    "phrygian",
+    "lydian",
+    "mixolydian",
+    "locrian",
+    "harmonic_minor",
+    "melodic_minor",
+    "major_pentatonic",
+    "minor_pentatonic",
+    "blues",
+];
+
+const PARAM_KEY: usize = 0;
+const PARAM_SCALE: usize = 1;
+const PARAM_RETUNE: usize = 2;
+const PARAM_FORMANT: usize = 3;
+const PARAM_MIX: usize = 4;
+const PARAM_COUNT: usize = 5;
+
+struct ParamSpec {
+    name: &'static CStr,
+    min: f64,
+    max: f64,
+    default: f64,
+    stepped: bool,
+    unit: &'static str,
+}
+
+const PARAMS: [ParamSpec; PARAM_COUNT] = [
+    ParamSpec {
+        name: c"Key",
+        min: 0.0,
+        max: 11.0,
+        default: 0.0,
+        stepped: true,
+        unit: "",
+    },
+    ParamSpec {
+        name: c"Scale",
+        min: 0.0,
+        max: (SCALE_NAMES.len() - 1) as f64,
+        default: 1.0,
+        stepped: true,
+        unit: "",
+    },
+    ParamSpec {
+        name: c"Retune Speed",
+        min: 0.0,
+        max: 400.0,
+        default: 20.0,
+        stepped: false,
+        unit: "ms",
+    },
+    ParamSpec {
+        name: c"Formant",
+        min: -12.0,
+        max: 12.0,
+        default: 0.0,
+        stepped: false,
+        unit: "st",
+    },
+    ParamSpec {
+        name: c"Mix",
+        min: 0.0,
+        max: 100.0,
+        default: 100.0,
+        stepped: false,
+        unit: "%",
+    },
+];
+
+/// ポインタの配列を static に置くためのラッパ（中身は 'static な文字列だけ）。
+struct Features([*const c_char; 4]);
+unsafe impl Sync for Features {}
+
+static FEATURES: Features = Features([
+    c"audio-effect".as_ptr(),
+    c"pitch-correction".as_ptr(),
+    c"stereo".as_ptr(),
+    ptr::null(),
+]);
+
+static DESCRIPTOR: clap_plugin_descriptor = clap_plugin_descriptor {
+    clap_version: CLAP_VERSION,
+    id: PLUGIN_ID.as_ptr(),
+    name: c"s-tune".as_ptr(),
+    vendor: c"s-tune".as_ptr(),
+    url: c"".as_ptr(),
+    manual_url: c"".as_ptr(),
+    support_url: c"".as_ptr(),
+    version: c"0.1.0".as_ptr(),
+    description: c"Automatic pitch correction".as_ptr(),
+    features: &FEATURES.0 as *const *const c_char,
+};
+
+/// CLAP のプラグインインスタンス。clap_plugin.plugin_data がこれを指す。
+///
+/// メインスレッドのコールバック（パラメータ・状態・遅れ）は処理中にも呼ばれるので、
+/// 共有参照（plugin_of）でアトミックな値だけを触る。チューナーは audio_of 経由で、
+/// ホストが同時には呼ばない activate / deactivate / reset / process だけが触る。
+struct Plugin {
+    raw: clap_plugin,
+    // parameter values (f64 bits): written by the audio thread and flush, read from the main thread
+    values: [AtomicU64; PARAM_COUNT],
+    // reported by clap.latency, set in activate
+    latency: AtomicU32,
+    audio: UnsafeCell<AudioState>,
+}
+
+/// activate / process 側だけが持つ状態。
+struct AudioState {
+    // values last pushed into the tuner
+    applied: [f64; PARAM_COUNT],
+    // created in activate (needs the sample rate)
+    tuner: Option<LiveTuner>,
+    // SCALE_NAMES の並び（ルート C）。オートメーションで名前を引いて確保しないよう activate で作る
+    scales: Vec<Scale>,
+}
+
+impl Plugin {
+    fn value(&self, param: usize) -> f64 {
+        f64::from_bits(self.values[param].load(Ordering::Relaxed))
+    }
+
+    fn set_value(&self, param: usize, value: f64) {
+        let spec = &PARAMS[param];
+        let mut v = if value.is_finite() { value.clamp(spec.min, spec.max) } else { spec.default };
+        if spec.stepped {
+            v = v.round();
+        }
+        self.values[param].store(v.to_bits(), Ordering::Relaxed);
+    }
+
+    /// パラメータ変更イベントなら値を書き換えて true。
+    ///
+    /// # Safety
+    /// event は有効なイベントヘッダを指していること。
+    unsafe fn handle_event(&self, event: *const clap_event_header) -> bool {
+        let Some(header) = event.as_ref() else { return false };
+        if header.space_id != CLAP_CORE_EVENT_SPACE_ID || header.type_ != CLAP_EVENT_PARAM_VALUE {
+            return false;
+        }
+        let ev = &*(event as *const clap_event_param_value);
+        let param = ev.param_id as usize;
+        if param >= PARAM_COUNT {
+            return false;
+        }
+        self.set_value(param, ev.value);
+        true
+    }
+}
+
+impl AudioState {
+    /// 変わったパラメータだけチューナーに反映する（メモリは確保しない）。
+    fn apply_params(&mut self, plugin: &Plugin) {
+        let current: [f64; PARAM_COUNT] = std::array::from_fn(|i| plugin.value(i));
+        let Some(tuner) = self.tuner.as_mut() else { return };
+        if current[PARAM_KEY] != self.applied[PARAM_KEY] || current[PARAM_SCALE] != self.applied[PARAM_SCALE] {
+            if let Some(scale) = self.scales.get(current[PARAM_SCALE] as usize) {
+                tuner.set_scale(current[PARAM_KEY] as f32, scale);
+            }
+        }
+        if current[PARAM_RETUNE] != self.applied[PARAM_RETUNE] {
+            tuner.set_retune_speed(current[PARAM_RETUNE] as f32);
+        }
+        if current[PARAM_FORMANT] != self.applied[PARAM_FORMANT] {
+            tuner.set_formant_shift(current[PARAM_FORMANT] as f32);
+        }
+        if current[PARAM_MIX] != self.applied[PARAM_MIX] {
+            tuner.set_mix(current[PARAM_MIX] as f32);
+        }
+        self.applied = current;
+    }
+
+    /// 今のパラメータで planes の [from, to) を処理する。
+    fn render(&mut self, plugin: &Plugin, planes: &mut [&mut [f32]], from: usize, to: usize) {
+        self.apply_params(plugin);
+        let Some(tuner) = self.tuner.as_mut() else { return };
+        let channels = planes.len().min(CHANNELS);
+        let mut sub: [&mut [f32]; CHANNELS] = [&mut [], &mut []];
+        for (s, plane) in sub.iter_mut().zip(planes.iter_mut()) {
+            *s = &mut plane[from..to];
+        }
+        tuner.process_channels(&mut sub[..channels]);
+    }
+}
+
+/// # Safety
+/// plugin は create_plugin が返したもの。
+unsafe fn plugin_of<'a>(plugin: *const clap_plugin) -> &'a Plugin {
+    &*((*plugin).plugin_data as *const Plugin)
+}
+
+/// # Safety
+/// plugin は create_plugin が返したもの。activate / deactivate / reset / process の中からだけ呼ぶこと
+/// （ホストはこれらを同時に呼ばないので、返した参照は他と重ならない）。
+unsafe fn audio_of<'a>(plugin: *const clap_plugin) -> &'a mut AudioState {
+    &mut *plugin_of(plugin).audio.get()
+}
+
+fn param_index(id: clap_id) -> Option<usize> {
+    let i = id as usize;
+    (i < PARAM_COUNT).then_some(i)
+}
+
+fn value_text(param: usize, value: f64) -> String {
+    match param {
+        PARAM_KEY => NOTE_NAMES[(value.round() as usize).min(11)].to_string(),
+        PARAM_SCALE => SCALE_NAMES[(value.round() as usize).min(SCALE_NAMES.len() - 1)].to_string(),
+        PARAM_FORMANT => format!("{value:+.1} {}", PARAMS[param].unit),
+        _ => format!("{value:.0} {}", PARAMS[param].unit),
+    }
+}
+
+fn text_value(param: usize, text: &str) -> Option<f64> {
+    let text = text.trim();
+    let names: &[&str] = match param {
+        PARAM_KEY => &NOTE_NAMES,
+        PARAM_SCALE => &SCALE_NAMES,
+        _ => &[],
+    };
+    if let Some(i) = names.iter().position(|n| n.eq_ignore_ascii_case(text)) {
+        return Some(i as f64);
+    }
+    let number = text.trim_end_matches(PARAMS[param].unit).trim();
+    number.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+/// s を NUL 終端で buffer（capacity バイト）に書く。入り切らない分は切る。
+///
+/// # Safety
+/// buffer は capacity バイト書けること。
+unsafe fn write_c_string(s: &str, buffer: *mut c_char, capacity: usize) {
+    if buffer.is_null() || capacity == 0 {
+        return;
+    }
+    let mut n = s.len().min(capacity - 1);
+    while !s.is_char_boundary(n) {
+        n -= 1;
+    }
+    ptr::copy_nonoverlapping(s.as_ptr() as *const c_char, buffer, n);
+    *buffer.add(n) = 0;
+}
+
+// --- clap_plugin ---
+
+unsafe extern "C" fn plugin_init(_plugin: *const clap_plugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_destroy(plugin: *const clap_plugin) {
+    if !plugin.is_null() {
+        drop(Box::from_raw((*plugin).plugin_data as *mut Plugin));
+    }
+}
+
+unsafe extern "C" fn plugin_activate(plugin: *const clap_plugin, sample_rate: f64, _min: u32, _max: u32) -> bool {
+    let p = plugin_of(plugin);
+    let audio = audio_of(plugin);
+    let tuner = LiveTuner::new(sample_rate as f32, CHANNELS);
+    p.latency.store(tuner.latency_samples() as u32, Ordering::Relaxed);
+    audio.tuner = Some(tuner);
+    audio.scales = SCALE_NAMES.iter().filter_map(|name| Scale::from_name(0.0, name)).collect();
+    // force every parameter into the new tuner
+    audio.applied = [f64::NAN; PARAM_COUNT];
+    audio.apply_params(p);
+    true
+}
+
+unsafe extern "C" fn plugin_deactivate(plugin: *const clap_plugin) {
+    audio_of(plugin).tuner = None;
+    plugin_of(plugin).latency.store(0, Ordering::Relaxed);
+}
+
+unsafe extern "C" fn plugin_start_processing(_plugin: *const clap_plugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_stop_processing(_plugin: *const clap_plugin) {}
+
+unsafe extern "C" fn plugin_reset(plugin: *const clap_plugin) {
+    if let Some(tuner) = audio_of(plugin).tuner.as_mut() {
+        tuner.reset();
+    }
+}
+
+/// 入力を出力へ写してから、イベントの時刻で区切ってチューナーに通す。
+unsafe extern "C" fn plugin_process(plugin: *const clap_plugin, process: *const clap_process) -> clap_process_status {
+    let p = plugin_of(plugin);
+    let audio = audio_of(plugin);
+    let Some(process) = process.as_ref() else { return CLAP_PROCESS_ERROR };
+    if audio.tuner.is_none() || process.audio_outputs_count == 0 || process.audio_outputs.is_null() {
+        return CLAP_PROCESS_ERROR;
+    }
+    let frames = process.frames_count as usize;
+
+    let out: &clap_audio_buffer = &*process.audio_outputs;
+    let input: Option<&clap_audio_buffer> = if process.audio_inputs_count > 0 {
+        process.audio_inputs.as_ref()
+    } else {
+        None
+    };
+    let out_channels = (out.channel_count as usize).min(CHANNELS);
+    if out.data32.is_null() || out_channels == 0 {
+        return CLAP_PROCESS_ERROR;
+    }
+
+    let mut planes: [&mut [f32]; CHANNELS] = [&mut [], &mut []];
+    for (ch, plane) in planes.iter_mut().enumerate().take(out_channels) {
+        let dst = *out.data32.add(ch);
+        if dst.is_null() {
+            return CLAP_PROCESS_ERROR;
+        }
+        // missing input channels repeat the last one (mono in -> stereo out), or silence
+        let src = input
+            .filter(|b| !b.data32.is_null() && b.channel_count > 0)
+            .map(|b| *b.data32.add(ch.min(b.channel_count as usize - 1)));
+        match src {
+            Some(src) if !src.is_null() => {
+                if src != dst {
+                    ptr::copy(src, dst, frames);
+                }
+            }
+            _ => ptr::write_bytes(dst, 0, frames),
+        }
+        *plane = std::slice::from_raw_parts_mut(dst, frames);
+    }
+    let planes = &mut planes[..out_channels];
+
+    let events = process.in_events.as_ref();
+    let event_count = events.and_then(|e| e.size).map_or(0, |size| size(process.in_events));
+    let mut pos = 0;
+    for i in 0..event_count {
+        let Some(get) = events.and_then(|e| e.get) else { break };
+        let event = get(process.in_events, i);
+        let Some(header) = event.as_ref() else { continue };
+        let at = (header.time as usize).min(frames);
+        if at > pos {
+            audio.render(p, planes, pos, at);
+            pos = at;
+        }
+        p.handle_event(event);
+    }
+    audio.render(p, planes, pos, frames);
+    CLAP_PROCESS_CONTINUE
+}
+
+unsafe extern "C" fn plugin_get_extension(_plugin: *const clap_plugin, id: *const c_char) -> *const c_void {
+    if id.is_null() {
+        return ptr::null();
+    }
+    let id = CStr::from_ptr(id);
+    if id == CLAP_EXT_PARAMS {
+        &PARAMS_EXT as *const clap_plugin_params as *const c_void
+    } else if id == CLAP_EXT_LATENCY {
+        &LATENCY_EXT as *const clap_plugin_latency as *const c_void
+    } else if id == CLAP_EXT_AUDIO_PORTS {
+        &AUDIO_PORTS_EXT as *const clap_plugin_audio_ports as *const c_void
+    } else if id == CLAP_EXT_STATE {
+        &STATE_EXT as *const clap_plugin_state as *const c_void
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn plugin_on_main_thread(_plugin: *const clap_plugin) {}
+
+// --- clap.params ---
+
+static PARAMS_EXT: clap_plugin_params = clap_plugin_params {
+    count: Some(params_count),
+    get_info: Some(params_get_info),
+    get_value: Some(params_get_value),
+    value_to_text: Some(params_value_to_text),
+    text_to_value: Some(params_text_to_value),
+    flush: Some(params_flush),
+};
+
+unsafe extern "C" fn params_count(_plugin: *const clap_plugin) -> u32 {
+    PARAM_COUNT as u32
+}
+
+unsafe extern "C" fn params_get_info(_plugin: *const clap_plugin, index: u32, info: *mut clap_param_info) -> bool {
+    let (Some(i), Some(info)) = (param_index(index), info.as_mut()) else { return false };
+    let spec = &PARAMS[i];
+    info.id = i as clap_id;
+    info.flags = CLAP_PARAM_IS_AUTOMATABLE;
+    if spec.stepped {
+        info.flags |= CLAP_PARAM_IS_STEPPED | CLAP_PARAM_IS_ENUM;
+    }
+    info.cookie = ptr::null_mut();
+    info.name = [0; clap_sys::string_sizes::CLAP_NAME_SIZE];
+    write_c_string(spec.name.to_str().unwrap_or_default(), info.name.as_mut_ptr(), info.name.len());
+    info.module = [0; clap_sys::string_sizes::CLAP_PATH_SIZE];
+    info.min_value = spec.min;
+    info.max_value = spec.max;
+    info.default_value = spec.default;
+    true
+}
+
+unsafe extern "C" fn params_get_value(plugin: *const clap_plugin, id: clap_id, out: *mut f64) -> bool {
+    let (Some(i), false) = (param_index(id), out.is_null()) else { return false };
+    *out = plugin_of(plugin).value(i);
+    true
+}
+
+unsafe extern "C" fn params_value_to_text(
+    _plugin: *const clap_plugin,
+    id: clap_id,
+    value: f64,
+    buffer: *mut c_char,
+    capacity: u32,
+) -> bool {
+    let Some(i) = param_index(id) else { return false };
+    write_c_string(&value_text(i, value), buffer, capacity as usize);
+    true
+}
+
+unsafe extern "C" fn params_text_to_value(
+    _plugin: *const clap_plugin,
+    id: clap_id,
+    text: *const c_char,
+    out: *mut f64,
+) -> bool {
+    let (Some(i), false, false) = (param_index(id), text.is_null(), out.is_null()) else { return false };
+    let Ok(text) = CStr::from_ptr(text).to_str() else { return false };
+    match text_value(i, text) {
+        Some(v) => {
+            *out = v;
+            true
+        }
+        None => false,
+    }
+}
+
+/// 処理していない間のパラメータ変更（値は次の process / activate で反映される）。
+unsafe extern "C" fn params_flush(
+    plugin: *const clap_plugin,
+    in_events: *const clap_input_events,
+    _out: *const clap_output_events,
+) {
+    let p = plugin_of(plugin);
+    let Some(events) = in_events.as_ref() else { return };
+    let (Some(size), Some(get)) = (events.size, events.get) else { return };
+    for i in 0..size(in_events) {
+        p.handle_event(get(in_events, i));
+    }
+}
+
+// --- clap.latency ---
+
+static LATENCY_EXT: clap_plugin_latency = clap_plugin_latency {
+    get: Some(latency_get),
+};
+
+unsafe extern "C" fn latency_get(plugin: *const clap_plugin) -> u32 {
+    plugin_of(plugin).latency.load(Ordering::Relaxed)
+}
+
+// --- clap.audio-ports ---
+
+static AUDIO_PORTS_EXT: clap_plugin_audio_ports = clap_plugin_audio_ports {
+    count: Some(audio_ports_count),
+    get: Some(audio_ports_get),
+};
+
+unsafe extern "C" fn audio_ports_count(_plugin: *const clap_plugin, _is_input: bool) -> u32 {
+    1
+}
+
+unsafe extern "C" fn audio_ports_get(
+    _plugin: *const clap_plugin,
+    index: u32,
+    is_input: bool,
+    info: *mut clap_audio_port_info,
+) -> bool {
+    let Some(info) = info.as_mut() else { return false };
+    if index != 0 {
+        return false;
+    }
+    info.id = 0;
+    info.name = [0; clap_sys::string_sizes::CLAP_NAME_SIZE];
+    write_c_string(if is_input { "Input" } else { "Output" }, info.name.as_mut_ptr(), info.name.len());
+    info.flags = CLAP_AUDIO_PORT_IS_MAIN;
+    info.channel_count = CHANNELS as u32;
+    info.port_type = CLAP_PORT_STEREO.as_ptr();
+    info.in_place_pair = 0;
+    true
+}
+
+// --- clap.state: "STUN" + version + パラメータ値（f64 LE）---
+
+const STATE_MAGIC: &[u8; 4] = b"STUN";
+const STATE_VERSION: u32 = 1;
+
+static STATE_EXT: clap_plugin_state = clap_plugin_state {
+    save: Some(state_save),
+    load: Some(state_load),
+};
+
+unsafe extern "C" fn state_save(plugin: *const clap_plugin, stream: *const clap_ostream) -> bool {
+    let p = plugin_of(plugin);
+    let Some(write) = stream.as_ref().and_then(|s| s.write) else { return false };
+    let mut bytes = Vec::with_capacity(8 + 8 * PARAM_COUNT);
+    bytes.extend_from_slice(STATE_MAGIC);
+    bytes.extend_from_slice(&STATE_VERSION.to_le_bytes());
+    for i in 0..PARAM_COUNT {
+        bytes.extend_from_slice(&p.value(i).to_le_bytes());
+    }
+    let mut done = 0;
+    while done < bytes.len() {
+        let n = write(stream, bytes[done..].as_ptr() as *const c_void, (bytes.len() - done) as u64);
+        if n <= 0 {
+            return false;
+        }
+        done += n as usize;
+    }
+    true
+}
+
+unsafe extern "C" fn state_load(plugin: *const clap_plugin, stream: *const clap_istream) -> bool {
+    let p = plugin_of(plugin);
+    let Some(read) = stream.as_ref().and_then(|s| s.read) else { return false };
+    let mut bytes = Vec::new();
+    let mut chunk = [0_u8; 256];
+    loop {
+        let n = read(stream, chunk.as_mut_ptr() as *mut c_void, chunk.len() as u64);
+        if n < 0 {
+            return false;
+        }
+        if n == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..n as usize]);
+    }
+    if bytes.len() < 8 || &bytes[..4] != STATE_MAGIC {
+        return false;
+    }
+    // 後の版で増えたパラメータは無視し、足りない分は既定値のまま
+    for (i, v) in bytes[8..].chunks_exact(8).take(PARAM_COUNT).enumerate() {
+        p.set_value(i, f64::from_le_bytes(v.try_into().unwrap_or_default()));
+    }
+    true
+}
+
+// --- factory / entry ---
+
+static FACTORY: clap_plugin_factory = clap_plugin_factory {
+    get_plugin_count: Some(factory_get_plugin_count),
+    get_plugin_descriptor: Some(factory_get_plugin_descriptor),
+    create_plugin: Some(factory_create_plugin),
+};
+
+unsafe extern "C" fn factory_get_plugin_count(_factory: *const clap_plugin_factory) -> u32 {
+    1
+}
+
+unsafe extern "C" fn factory_get_plugin_descriptor(
+    _factory: *const clap_plugin_factory,
+    index: u32,
+) -> *const clap_plugin_descriptor {
+    if index == 0 {
+        &DESCRIPTOR
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn factory_create_plugin(
+    _factory: *const clap_plugin_factory,
+    _host: *const clap_host,
+    plugin_id: *const c_char,
+) -> *const clap_plugin {
+    if plugin_id.is_null() || CStr::from_ptr(plugin_id) != PLUGIN_ID {
+        return ptr::null();
+    }
+    let plugin = Box::into_raw(Box::new(Plugin {
+        raw: clap_plugin {
+            desc: &DESCRIPTOR,
+            plugin_data: ptr::null_mut(),
+            init: Some(plugin_init),
+            destroy: Some(plugin_destroy),
+            activate: Some(plugin_activate),
+            deactivate: Some(plugin_deactivate),
+            start_processing: Some(plugin_start_processing),
+            stop_processing: Some(plugin_stop_processing),
+            reset: Some(plugin_reset),
+            process: Some(plugin_process),
+            get_extension: Some(plugin_get_extension),
+            on_main_thread: Some(plugin_on_main_thread),
+        },
+        values: std::array::from_fn(|i| AtomicU64::new(PARAMS[i].default.to_bits())),
+        latency: AtomicU32::new(0),
+        audio: UnsafeCell::new(AudioState {
+            applied: [f64::NAN; PARAM_COUNT],
+            tuner: None,
+            scales: Vec::new(),
+        }),
+    }));
+    (*plugin).raw.plugin_data = plugin as *mut c_void;
+    &(*plugin).raw
+}
+
+unsafe extern "C" fn entry_init(_plugin_path: *const c_char) -> bool {
+    true
+}
+
+unsafe extern "C" fn entry_deinit() {}
+
+unsafe extern "C" fn entry_get_factory(factory_id: *const c_char) -> *const c_void {
+    if !factory_id.is_null() && CStr::from_ptr(factory_id) == CLAP_PLUGIN_FACTORY_ID {
+        &FACTORY as *const clap_plugin_factory as *const c_void
+    } else {
+        ptr::null()
+    }
+}
+
+/// CLAP のエントリポイント。
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+pub static clap_entry: clap_plugin_entry = clap_plugin_entry {
+    clap_version: CLAP_VERSION,
+    init: Some(entry_init),
+    deinit: Some(entry_deinit),
+    get_factory: Some(entry_get_factory),
+};
@@ -0,0 +1,176 @@
+//! ダブリング（ADT）/ クワイア的な厚み付け。
+//!
+//! 補正後のボーカルを少しずつずらした複製を N 本作り、パンを振ってステレオに混ぜる。
+//! 遅れはコピーごとの一定のずれに、ゆっくり揺れる成分を足す（人が重ね録りしたときのタイミングのよれ）。
+//! ばらつきは seed から決まるので、同じ設定なら毎回同じ結果になる。
+
+use crate::rng::SplitMix64;
+use crate::MelodyEngine;
+
+// delay_ms に対するコピーごとのばらつき（±割合）
+const DELAY_JITTER_RATIO: f32 = 0.2;
+// 遅れの時間的な揺れの大きさ（delay_ms に対する±割合）と、揺れの乱数点の間隔（秒）
+const DELAY_DRIFT_RATIO: f32 = 0.25;
+const DELAY_DRIFT_SEC: f32 = 0.4;
+
+#[derive(Clone, Debug)]
+pub(crate) struct DoubleCopy {
+    // constant detune (cents)
+    pub(crate) detune_cents: f32,
+    // base delay (ms), jittered per copy and drifting slowly over time
+    pub(crate) delay_ms: f32,
+    // ± cents, random per note
+    pub(crate) pitch_variation_cents: f32,
+    // ± semitones, random per note
+    pub(crate) formant_variation: f32,
+    // -1 (L) .. 1 (R)
+    pub(crate) pan: f32,
+    // linear
+    pub(crate) gain: f32,
+}
+
+impl DoubleCopy {
+    fn render(&self, engine: &MelodyEngine, input: &[f32], rng: &mut SplitMix64) -> Vec<f32> {
+        let mut copy = engine.clone();
+        copy.reset_processing_state();
+
+        let detune = finite_or_zero(self.detune_cents) / 100.0;
+        let variation = finite_or_zero(self.pitch_variation_cents).abs() / 100.0;
+        let formant_var = finite_or_zero(self.formant_variation).abs();
+        for note in copy.notes.iter_mut() {
+            note.pitch_offset += detune + rng.bipolar() * variation;
+            note.formant_shift += rng.bipolar() * formant_var;
+        }
+
+        let mut rendered = input.to_vec();
+        copy.process_buffer(&mut rendered);
+
+        let base_ms = finite_or_zero(self.delay_ms).max(0.0);
+        if base_ms <= 0.0 {
+            return rendered;
+        }
+        let to_samples = 0.001 * copy.sample_rate;
+        let delay = base_ms * (1.0 + DELAY_JITTER_RATIO * rng.bipolar()) * to_samples;
+        let drift = base_ms * DELAY_DRIFT_RATIO * to_samples;
+        let curve = delay_curve(rendered.len(), copy.sample_rate, delay, drift, rng);
+
+        // 小数サンプルの遅れは線形補間で読む（先頭より前は無音）
+        curve
+            .iter()
+            .enumerate()
+            .map(|(j, &d)| {
+                let pos = j as f32 - d;
+                if pos < 0.0 {
+                    return 0.0;
+                }
+                let i0 = pos as usize;
+                let frac = pos - i0 as f32;
+                let a = rendered[i0];
+                let b = rendered.get(i0 + 1).copied().unwrap_or(0.0);
+                a + (b - a) * frac
+            })
+            .collect()
+    }
+}
+
+/// サンプルごとの遅れ（サンプル）：delay ± drift。
+/// DELAY_DRIFT_SEC ごとの乱数点を smoothstep でつなぐので、ゆっくり滑らかに揺れる。
+fn delay_curve(len: usize, sample_rate: f32, delay: f32, drift: f32, rng: &mut SplitMix64) -> Vec<f32> {
+    let step = (DELAY_DRIFT_SEC * sample_rate).max(1.0);
+    let points: Vec<f32> = (0..(len as f32 / step) as usize + 2).map(|_| rng.bipolar()).collect();
+    (0..len)
+        .map(|j| {
+            let u = j as f32 / step;
+            let k = u as usize;
+            let f = u - k as f32;
+            let s = f * f * (3.0 - 2.0 * f);
+            delay + drift * (points[k] + (points[k + 1] - points[k]) * s)
+        })
+        .collect()
+}
+
+/// 全コピーをレンダリングしてステレオ（planar: 先頭 len が L、続く len が R）で返す。
+/// 元のボーカル（ドライ）は含まない。
+pub(crate) fn render_doubles(engine: &MelodyEngine, copies: &[DoubleCopy], seed: u64, input: &[f32]) -> Vec<f32> {
+    let len = input.len();
+    let mut out = vec![0.0_f32; len * 2];
+    let (left, right) = out.split_at_mut(len);
+
+    for (i, c) in copies.iter().enumerate() {
+        // コピーごとに独立した系列（本数を増やしても既存コピーの結果は変わらない）
+        let mut rng = SplitMix64::new(seed ^ (i as u64).wrapping_mul(0xA24B_AED4_963E_E407));
+        let y = c.render(engine, input, &mut rng);
+
+        // equal-power pan
+        let pan = if c.pan.is_finite() { c.pan.clamp(-1.0, 1.0) } else { 0.0 };
+        let theta = (pan + 1.0) * std::f32::consts::FRAC_PI_4;
+        let gain = if c.gain.is_finite() { c.gain.max(0.0) } else { 1.0 };
+        let gl = theta.cos() * gain;
+        let gr = theta.sin() * gain;
+
+        for (j, &v) in y.iter().enumerate() {
+            left[j] += v * gl;
+            right[j] += v * gr;
+        }
+    }
+
+    out
+}
+
+fn finite_or_zero(v: f32) -> f32 {
+    if v.is_finite() {
+        v
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_drifts_slowly_around_the_offset() {
+        let sr = 48000.0;
+        let len = (sr * 4.0) as usize;
+        let (delay, drift) = (960.0, 240.0);
+        let curve = delay_curve(len, sr, delay, drift, &mut SplitMix64::new(7));
+
+        let (min, max) = curve.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &d| (lo.min(d), hi.max(d)));
+        assert!(min >= delay - drift && max <= delay + drift, "{min}..{max}");
+        // 4 秒のあいだに揺れの幅の半分以上は動く
+        assert!(max - min > drift, "{min}..{max}");
+        // 1 サンプルあたりの変化は小さい（ピッチがほとんど揺れない）
+        let steepest = curve.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0_f32, f32::max);
+        assert!(steepest < 1.5 * 2.0 * drift / (DELAY_DRIFT_SEC * sr), "{steepest}");
+
+        let again = delay_curve(len, sr, delay, drift, &mut SplitMix64::new(7));
+        assert_eq!(curve, again);
+        let other = delay_curve(len, sr, delay, drift, &mut SplitMix64::new(8));
+        assert_ne!(curve, other);
+    }
+
+    #[test]
+    fn copies_are_reproducible_per_seed() {
+        let sr = 48000.0;
+        let input: Vec<f32> = (0..24000).map(|i| (i as f32 * 0.03).sin() * 0.5).collect();
+        let mut engine = MelodyEngine::new(sr);
+        engine.set_notes(
+            vec![0.0], vec![0.5], vec![60.0], vec![0.0], vec![0.0], vec![1.0], vec![1.0], vec![1.0], vec![1.0],
+            vec![0.0], 0, Vec::new(),
+        );
+        let copies = [DoubleCopy {
+            detune_cents: 8.0,
+            delay_ms: 20.0,
+            pitch_variation_cents: 5.0,
+            formant_variation: 0.0,
+            pan: -0.5,
+            gain: 1.0,
+        }];
+        let a = render_doubles(&engine, &copies, 3, &input);
+        assert_eq!(a, render_doubles(&engine, &copies, 3, &input));
+        assert_ne!(a, render_doubles(&engine, &copies, 4, &input));
+        // 遅れぶん先頭は無音
+        assert!(a[..input.len()][..500].iter().all(|&v| v == 0.0));
+    }
+}
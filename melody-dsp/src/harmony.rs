@@ -0,0 +1,194 @@
+//! リードのノート列からハモりパートを作る。
+//!
+//! 各パートはエンジンの設定（音律・キー・補正量など）をそのまま複製し、
+//! ノートごとの pitch_offset にハモり音程を足して同じシフタでレンダリングする。
+//! 出力はパートごとに別バッファ（ノート区間以外は無音）。
+
+use crate::rng::SplitMix64;
+use crate::tuning::equal_440_hz;
+use crate::MelodyEngine;
+
+// ノート頭/尻のフェード（クリック防止）
+const VOICE_FADE_SEC: f32 = 0.005;
+
+#[derive(Clone, Debug)]
+pub(crate) enum IntervalRule {
+    /// キー上で n 度ずらす（+2 = 3度上, +4 = 5度上, -2 = 3度下）
+    Diatonic(i32),
+    /// set_notes の順に並んだ目標ノート（MIDI）。NaN のノートは歌わない。
+    Custom(Vec<f32>),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct HarmonyVoice {
+    pub(crate) rule: IntervalRule,
+    // semitones, added to each note's formant_shift
+    pub(crate) formant_shift: f32,
+    // ± ms, per-note random timing offset
+    pub(crate) humanize_ms: f32,
+    // linear
+    pub(crate) gain: f32,
+    pub(crate) seed: u64,
+}
+
+impl HarmonyVoice {
+    /// リードのエンジンを複製し、各ノートをこのパートの音高にしたもの（歌わないノートは除く）。
+    ///
+    /// リードの音高は 440 Hz 12平均律の単位なので、キー上の移動や目標ノートは
+    /// update_corrections と同じく現在の音律の鍵に直してから行い、差は周波数の比で求める。
+    fn voice_engine(&self, engine: &MelodyEngine) -> Result<MelodyEngine, String> {
+        if matches!(self.rule, IntervalRule::Diatonic(_)) && engine.scale.is_none() {
+            return Err("harmony: diatonic voices need a key (set_key)".to_string());
+        }
+
+        let mut voice = engine.clone();
+        voice.reset_processing_state();
+
+        let tuning = &engine.tuning;
+        voice.notes.retain_mut(|note| {
+            // リードが実際に鳴らす音高（補正込み、純正律のずれは除く）
+            let lead = note.base_semitone + note.pitch_offset + note.pitch_center_offset + note.correction_offset;
+            let lead_hz = equal_440_hz(lead);
+            let target = match &self.rule {
+                IntervalRule::Diatonic(steps) => engine
+                    .scale
+                    .as_ref()
+                    .map(|scale| scale.transpose_degrees(tuning.hz_to_midi(lead_hz), *steps))
+                    .unwrap_or(f32::NAN),
+                IntervalRule::Custom(targets) => targets.get(note.index).copied().unwrap_or(f32::NAN),
+            };
+            let interval = 12.0 * (tuning.midi_to_hz(target) / lead_hz).log2();
+            if !interval.is_finite() {
+                return false;
+            }
+            note.pitch_offset += interval;
+            note.formant_shift += self.formant_shift;
+            true
+        });
+        // pitch_offset が変わったので純正律のずれを取り直す
+        voice.update_corrections();
+        Ok(voice)
+    }
+
+    /// input（リードの元音声）から、このパートだけのバッファを作る。
+    pub(crate) fn render(&self, engine: &MelodyEngine, input: &[f32]) -> Result<Vec<f32>, String> {
+        let mut voice = self.voice_engine(engine)?;
+
+        let mut rendered = input.to_vec();
+        voice.process_buffer(&mut rendered);
+
+        let sr = voice.sample_rate;
+        let len = input.len();
+        let gain = if self.gain.is_finite() { self.gain.max(0.0) } else { 1.0 };
+        let humanize = if self.humanize_ms.is_finite() { self.humanize_ms.abs() } else { 0.0 };
+        let fade = ((VOICE_FADE_SEC * sr) as usize).max(1);
+
+        let mut rng = SplitMix64::new(self.seed);
+        let mut out = vec![0.0_f32; len];
+        for note in voice.notes.iter() {
+            let s = ((note.start * sr) as usize).min(len);
+            let e = ((note.end * sr).ceil() as usize).min(len);
+            let jitter = (rng.bipolar() * humanize * 0.001 * sr).round() as isize;
+            if e <= s {
+                continue;
+            }
+
+            let n = e - s;
+            let fade = fade.min(n / 2).max(1);
+            for i in 0..n {
+                let dst = (s + i) as isize + jitter;
+                if dst < 0 || dst as usize >= len {
+                    continue;
+                }
+                let env = ((i + 1).min(n - i) as f32 / fade as f32).min(1.0);
+                out[dst as usize] += rendered[s + i] * env * gain;
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48000.0;
+
+    fn third_above() -> HarmonyVoice {
+        HarmonyVoice {
+            rule: IntervalRule::Diatonic(2),
+            formant_shift: 0.0,
+            humanize_ms: 0.0,
+            gain: 1.0,
+            seed: 1,
+        }
+    }
+
+    /// C4 と E4（reference_hz の音律での周波数）を歌ったリード
+    fn lead(reference_hz: f32) -> MelodyEngine {
+        let mut engine = MelodyEngine::new(SR);
+        engine.set_reference_pitch(reference_hz);
+        let base = |key: f32| 69.0 + 12.0 * (reference_hz / 440.0).log2() + (key - 69.0);
+        engine.set_notes(
+            vec![0.0, 0.5],
+            vec![0.5, 1.0],
+            vec![base(60.0), base(64.0)],
+            vec![0.0; 2],
+            vec![0.0; 2],
+            vec![1.0; 2],
+            vec![1.0; 2],
+            vec![1.0; 2],
+            vec![1.0; 2],
+            vec![0.0; 2],
+            0,
+            Vec::new(),
+        );
+        engine.set_key(0.0, "major");
+        engine.set_correction_strength(100.0);
+        engine
+    }
+
+    fn sounding_hz(engine: &MelodyEngine) -> Vec<f32> {
+        engine
+            .notes
+            .iter()
+            .map(|n| equal_440_hz(n.base_semitone + n.static_shift()))
+            .collect()
+    }
+
+    #[test]
+    fn diatonic_third_follows_the_tuning() {
+        for reference_hz in [440.0, 432.0] {
+            let engine = lead(reference_hz);
+            let voice = third_above().voice_engine(&engine).unwrap();
+            let lead_hz = sounding_hz(&engine);
+            let voice_hz = sounding_hz(&voice);
+            // C → E（長3度）、E → G（短3度）
+            for (i, (&l, &v)) in lead_hz.iter().zip(&voice_hz).enumerate() {
+                let semis = 12.0 * (v / l).log2();
+                let expected = [4.0, 3.0][i];
+                assert!((semis - expected).abs() < 1.0e-3, "{reference_hz} Hz, note {i}: {semis}");
+            }
+            // パートの音も同じ音律の E4 / G4 に乗っている
+            let e4 = engine.tuning.midi_to_hz(64.0);
+            assert!((voice_hz[0] / e4 - 1.0).abs() < 1.0e-4, "{reference_hz} Hz: {} vs {e4}", voice_hz[0]);
+        }
+    }
+
+    #[test]
+    fn rendered_voice_covers_only_the_notes() {
+        let mut engine = lead(432.0);
+        engine.notes.truncate(1);
+        let input: Vec<f32> = (0..SR as usize)
+            .map(|i| 0.5 * (std::f32::consts::TAU * 256.87 * i as f32 / SR).sin())
+            .collect();
+        let out = third_above().render(&engine, &input).unwrap();
+        assert_eq!(out.len(), input.len());
+        assert!(out[(0.6 * SR) as usize..].iter().all(|&v| v == 0.0));
+        assert!(out[(0.2 * SR) as usize..(0.3 * SR) as usize].iter().any(|&v| v.abs() > 0.1));
+
+        engine.scale = None;
+        assert!(third_above().render(&engine, &input).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+//! ディレイライン（リングバッファ）の小数位置読み出し。
+//!
+//! - linear: 2 点の線形補間（軽いが高域が落ち、読み出し位置が動くと変調ノイズが出る）
+//! - hermite: 4 点 3 次エルミート
+//! - lagrange: 6 点 5 次ラグランジュ
+//! - sinc: 24 タップの Kaiser 窓付き sinc（テーブルを前計算し、位相は線形補間で引く）
+//!
+//! 読み出しは位置の前後の点を使うので、delay が小さいと書き込み位置より先（ディレイ長ぶん前の古い入力）も読む。
+//! 周波数特性と変調ノイズは examples/interp_response.rs で測る。
+
+use std::sync::OnceLock;
+
+use crate::resample::{bessel_i0, sinc};
+
+// sinc のタップ数と Kaiser β、テーブルの分解能（1 サンプルあたりの位相数）
+const SINC_TAPS: usize = 24;
+const SINC_BETA: f64 = 7.0;
+const SINC_PHASES: usize = 256;
+const LAGRANGE_POINTS: usize = 6;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub(crate) enum Interpolation {
+    #[default]
+    Linear,
+    Hermite,
+    Lagrange,
+    Sinc,
+}
+
+impl Interpolation {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "linear" => Some(Self::Linear),
+            "hermite" | "cubic" => Some(Self::Hermite),
+            "lagrange" => Some(Self::Lagrange),
+            "sinc" => Some(Self::Sinc),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Linear => "linear",
+            Self::Hermite => "hermite",
+            Self::Lagrange => "lagrange",
+            Self::Sinc => "sinc",
+        }
+    }
+}
+
+/// 補間方式ごとの読み出し。sinc のテーブルはプロセス内で 1 度だけ作って共有する（処理中は確保しない）。
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DelayReader {
+    interpolation: Interpolation,
+    table: &'static [f32],
+}
+
+impl DelayReader {
+    pub(crate) fn new(interpolation: Interpolation) -> Self {
+        let table: &'static [f32] = match interpolation {
+            Interpolation::Sinc => sinc_table(),
+            _ => &[],
+        };
+        Self { interpolation, table }
+    }
+
+    pub(crate) fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// 書き込み位置 write_idx から delay サンプル前（小数可）の値。
+    pub(crate) fn read(&self, buffer: &[f32], write_idx: usize, delay: f32) -> f32 {
+        let len = buffer.len() as f32;
+        if len <= 1.0 {
+            return 0.0;
+        }
+
+        let mut pos = (write_idx as f32) - delay;
+        // 0 <= delay < len なら範囲内なので剰余は要らない（剰余は遅い）
+        if pos <= -len || pos >= len {
+            pos %= len;
+        }
+        if pos < 0.0 {
+            pos += len;
+        }
+
+        // pos >= 0 なので切り捨て = floor
+        let i0 = pos as usize;
+        let frac = pos - (i0 as f32);
+        match self.interpolation {
+            Interpolation::Linear => {
+                let i1 = if i0 + 1 >= buffer.len() { 0 } else { i0 + 1 };
+                let a = buffer[i0];
+                let b = buffer[i1];
+                a + (b - a) * frac
+            }
+            Interpolation::Hermite => {
+                let [xm1, x0, x1, x2] = taps::<4>(buffer, i0);
+                let c1 = 0.5 * (x1 - xm1);
+                let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+                let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+                ((c3 * frac + c2) * frac + c1) * frac + x0
+            }
+            Interpolation::Lagrange => {
+                let x = taps::<LAGRANGE_POINTS>(buffer, i0);
+                // 点の位置は i0 からの -2..=3、読み出し位置は frac
+                let offset = (LAGRANGE_POINTS / 2 - 1) as f32;
+                let mut acc = 0.0;
+                for (k, &xk) in x.iter().enumerate() {
+                    let tk = k as f32 - offset;
+                    let mut w = 1.0;
+                    for j in 0..LAGRANGE_POINTS {
+                        if j != k {
+                            let tj = j as f32 - offset;
+                            w *= (frac - tj) / (tk - tj);
+                        }
+                    }
+                    acc += xk * w;
+                }
+                acc
+            }
+            Interpolation::Sinc => {
+                let x = taps::<SINC_TAPS>(buffer, i0);
+                let p = frac * SINC_PHASES as f32;
+                let pi = (p as usize).min(SINC_PHASES - 1);
+                let pf = p - pi as f32;
+                let a = &self.table[pi * SINC_TAPS..(pi + 1) * SINC_TAPS];
+                let b = &self.table[(pi + 1) * SINC_TAPS..(pi + 2) * SINC_TAPS];
+                let mut acc = 0.0;
+                for k in 0..SINC_TAPS {
+                    acc += x[k] * (a[k] + (b[k] - a[k]) * pf);
+                }
+                acc
+            }
+        }
+    }
+}
+
+/// i0 を N 点の (N/2 - 1) 番目として、前後 N 点をリングバッファから取り出す。
+#[inline]
+fn taps<const N: usize>(buffer: &[f32], i0: usize) -> [f32; N] {
+    let len = buffer.len();
+    let back = (N / 2 - 1) % len;
+    let start = if i0 >= back { i0 - back } else { i0 + len - back };
+    let mut out = [0.0; N];
+    if start + N <= len {
+        out.copy_from_slice(&buffer[start..start + N]);
+    } else {
+        let mut i = start;
+        for v in out.iter_mut() {
+            *v = buffer[i];
+            i += 1;
+            if i >= len {
+                i = 0;
+            }
+        }
+    }
+    out
+}
+
+/// 位相 p / SINC_PHASES（p = 0..=SINC_PHASES）ごとの SINC_TAPS 個の係数。
+/// 各位相の係数の和は 1（直流の利得が位相によって揺れない）。
+fn sinc_table() -> &'static [f32] {
+    static TABLE: OnceLock<Vec<f32>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let half = (SINC_TAPS / 2) as f64;
+        let i0_beta = bessel_i0(SINC_BETA);
+        let mut table = Vec::with_capacity((SINC_PHASES + 1) * SINC_TAPS);
+        for p in 0..=SINC_PHASES {
+            let frac = p as f64 / SINC_PHASES as f64;
+            // タップ k は i0 - (half - 1) + k、読み出し位置からの距離は k - (half - 1) - frac
+            let row: Vec<f64> = (0..SINC_TAPS)
+                .map(|k| {
+                    let x = k as f64 - (half - 1.0) - frac;
+                    let r = x / half;
+                    if r.abs() >= 1.0 {
+                        return 0.0;
+                    }
+                    sinc(x) * bessel_i0(SINC_BETA * (1.0 - r * r).sqrt()) / i0_beta
+                })
+                .collect();
+            let sum: f64 = row.iter().sum();
+            table.extend(row.iter().map(|&c| (c / sum) as f32));
+        }
+        table
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f64 = 48000.0;
+
+    /// 小数部を 0..1 で一様に動かして hz のサインを読んだときの (gain dB, noise dB)。
+    /// gain: 応答の平均の大きさ。noise: 小数部による応答の揺れ（読み出し位置が動くときの変調ノイズ）と平均の比。
+    fn response(interpolation: Interpolation, hz: f64) -> (f64, f64) {
+        let w = 2.0 * std::f64::consts::PI * hz / SR;
+        let cos: Vec<f32> = (0..64).map(|n| (w * n as f64).cos() as f32).collect();
+        let sin: Vec<f32> = (0..64).map(|n| (w * n as f64).sin() as f32).collect();
+        let reader = DelayReader::new(interpolation);
+        let write_idx = 40;
+        let steps = 1000;
+        let h: Vec<(f64, f64)> = (0..steps)
+            .map(|k| {
+                let delay = 10.0 + k as f64 / steps as f64;
+                let re = reader.read(&cos, write_idx, delay as f32) as f64;
+                let im = reader.read(&sin, write_idx, delay as f32) as f64;
+                // 理想の値 e^{j w pos} で割る
+                let phase = w * (write_idx as f64 - delay);
+                (re * phase.cos() + im * phase.sin(), im * phase.cos() - re * phase.sin())
+            })
+            .collect();
+        let mean = (
+            h.iter().map(|v| v.0).sum::<f64>() / steps as f64,
+            h.iter().map(|v| v.1).sum::<f64>() / steps as f64,
+        );
+        let power = mean.0 * mean.0 + mean.1 * mean.1;
+        let spread = h.iter().map(|v| (v.0 - mean.0).powi(2) + (v.1 - mean.1).powi(2)).sum::<f64>() / steps as f64;
+        (10.0 * power.log10(), 10.0 * (spread / power).max(1.0e-30).log10())
+    }
+
+    #[test]
+    fn sinc_is_flat_up_to_0_4_fs() {
+        for k in 1..=48 {
+            let hz = 0.4 * SR * k as f64 / 48.0;
+            let (gain, noise) = response(Interpolation::Sinc, hz);
+            assert!(gain.abs() <= 0.1, "{hz} Hz: gain {gain:.3} dB");
+            assert!(noise <= -40.0, "{hz} Hz: noise {noise:.1} dB");
+        }
+    }
+
+    #[test]
+    fn higher_orders_beat_linear() {
+        for hz in [2000.0, 5000.0, 10000.0, 14000.0] {
+            let (linear_gain, linear_noise) = response(Interpolation::Linear, hz);
+            for interpolation in [Interpolation::Hermite, Interpolation::Lagrange, Interpolation::Sinc] {
+                let (gain, noise) = response(interpolation, hz);
+                let name = interpolation.name();
+                assert!(gain > linear_gain, "{name} at {hz} Hz: gain {gain:.3} dB vs {linear_gain:.3} dB");
+                assert!(noise < linear_noise, "{name} at {hz} Hz: noise {noise:.1} dB vs {linear_noise:.1} dB");
+            }
+        }
+        // 10 kHz（examples/interp_response の表）で linear は約 -1.3 dB / -22 dB
+        let (gain, noise) = response(Interpolation::Linear, 10000.0);
+        assert!((gain + 1.26).abs() < 0.05 && (noise + 22.2).abs() < 0.5, "{gain} {noise}");
+    }
+}
@@ -0,0 +1,101 @@
+//! 純正律補正：トニック（またはコード区間のルート）からの音程を純正比に寄せる。
+//!
+//! 12平均律の目標半音に対して「セント単位のずらし量」だけを返す。
+//! ルート自体は平均律のまま（ルートからの相対音程だけ純正にする）。
+
+/// ルートからの半音数 0..12 に対する純正比（5-limit）
+const JUST_RATIOS: [(f64, f64); 12] = [
+    (1.0, 1.0),
+    (16.0, 15.0),
+    (9.0, 8.0),
+    (6.0, 5.0),
+    (5.0, 4.0),
+    (4.0, 3.0),
+    (45.0, 32.0),
+    (3.0, 2.0),
+    (8.0, 5.0),
+    (5.0, 3.0),
+    (9.0, 5.0),
+    (15.0, 8.0),
+];
+
+/// 平均律からのずれ（セント）。例: 長3度 = -13.7, 完全5度 = +2.0
+fn just_cents_from_equal(interval: i32) -> f32 {
+    let idx = interval.rem_euclid(12) as usize;
+    let (n, d) = JUST_RATIOS[idx];
+    (1200.0 * (n / d).log2() - 100.0 * idx as f64) as f32
+}
+
+#[derive(Clone, Debug)]
+struct ChordRegion {
+    start: f32,
+    end: f32,
+    root_pc: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct JustIntonation {
+    tonic_pc: f32,
+    regions: Vec<ChordRegion>,
+}
+
+impl JustIntonation {
+    pub(crate) fn set_tonic(&mut self, tonic_pc: f32) {
+        self.tonic_pc = wrap_pc(tonic_pc);
+    }
+
+    /// コード区間（秒）とルート（ピッチクラス）を差し替える。不正な区間は捨てる。
+    pub(crate) fn set_regions(&mut self, starts: &[f32], ends: &[f32], roots: &[f32]) {
+        let n = starts.len().min(ends.len()).min(roots.len());
+        self.regions.clear();
+        for i in 0..n {
+            let (s, e, r) = (starts[i], ends[i], roots[i]);
+            if !s.is_finite() || !e.is_finite() || !r.is_finite() || e <= s {
+                continue;
+            }
+            self.regions.push(ChordRegion {
+                start: s,
+                end: e,
+                root_pc: wrap_pc(r),
+            });
+        }
+        self.regions
+            .sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap_or(std::cmp::Ordering::Equal));
+    }
+
+    pub(crate) fn tonic_pc(&self) -> f32 {
+        self.tonic_pc
+    }
+
+    /// (start, end, root) の並び
+    pub(crate) fn regions(&self) -> impl Iterator<Item = (f32, f32, f32)> + '_ {
+        self.regions.iter().map(|r| (r.start, r.end, r.root_pc))
+    }
+
+    /// 時刻 t のルート。コード区間外ならトニック。
+    fn root_at(&self, t: f32) -> f32 {
+        self.regions
+            .iter()
+            .find(|r| t >= r.start && t < r.end)
+            .map(|r| r.root_pc)
+            .unwrap_or(self.tonic_pc)
+    }
+
+    /// 目標ノート（平均律の半音, MIDI）に上乗せするずれ（半音単位）
+    pub(crate) fn offset_semitones(&self, target_midi: f32, t: f32) -> f32 {
+        if !target_midi.is_finite() {
+            return 0.0;
+        }
+        let root = self.root_at(t);
+        let interval = (target_midi.round() - root.round()) as i32;
+        just_cents_from_equal(interval) / 100.0
+    }
+}
+
+fn wrap_pc(pc: f32) -> f32 {
+    if pc.is_finite() {
+        pc.rem_euclid(12.0)
+    } else {
+        0.0
+    }
+}
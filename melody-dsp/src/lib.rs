@@ -2,6 +2,54 @@ use wasm_bindgen::prelude::*;
 
 use std::f32::consts::PI;
 
+mod analysis;
+mod batch;
+mod cache;
+#[cfg(not(target_arch = "wasm32"))]
+mod capi;
+#[cfg(feature = "clap")]
+mod clap_plugin;
+mod doubler;
+mod harmony;
+mod interp;
+mod intonation;
+mod live;
+mod midi;
+mod parallel;
+mod project;
+#[cfg(feature = "python")]
+mod python;
+mod resample;
+mod rng;
+mod scale;
+mod simd;
+mod snapshot;
+mod tuning;
+mod wav;
+
+use analysis::NoteDetectionConfig;
+use cache::{CacheSlot, RenderCache, SourceAudio};
+use doubler::DoubleCopy;
+use harmony::{HarmonyVoice, IntervalRule};
+use interp::{DelayReader, Interpolation};
+use intonation::JustIntonation;
+use midi::MidiExportOptions;
+use project::{AudioRef, PitchFrame};
+use resample::ResampleQuality;
+use scale::Scale;
+use simd::{Lane, LANES};
+use tuning::{equal_440_hz, Tuning};
+
+pub use batch::Batch;
+pub use live::LiveTuner;
+pub use midi::MidiAlignment;
+pub use resample::resample;
+pub use snapshot::NoteList;
+pub use wav::WavAudio;
+// JS から await initThreadPool(navigator.hardwareConcurrency) で Web Worker のスレッドプールを作る
+#[cfg(all(feature = "parallel", target_arch = "wasm32"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
+
 #[wasm_bindgen(start)]
 pub fn wasm_start() {
     // ブラウザのコンソールにpanicを出しやすくする
@@ -11,21 +59,68 @@ pub fn wasm_start() {
 /// 「Melodyneライト」土台：後でF0やノート列を入れられるように、
 /// まずは固定半音のブロック処理だけを提供。
 #[wasm_bindgen]
+#[derive(Clone)]
 pub struct MelodyShifter {
     sample_rate: f32,
     max_delay: usize,
     buffer: Vec<f32>,
     write_idx: usize,
     delay_pos: f32,
+    reader: DelayReader,
 }
 
 #[wasm_bindgen]
 impl MelodyShifter {
+    /// 線形補間で読み出すシフタ。
     #[wasm_bindgen(constructor)]
     pub fn new(sample_rate: f32) -> MelodyShifter {
+        Self::with_reader(sample_rate, Interpolation::Linear)
+    }
+
+    /// ディレイの読み出しの補間方式を選んで作る。
+    /// - interpolation: linear / hermite（cubic）/ lagrange / sinc（後ろほど高域が落ちにくく重い）
+    #[wasm_bindgen]
+    pub fn with_interpolation(sample_rate: f32, interpolation: &str) -> Result<MelodyShifter, String> {
+        let interpolation = Interpolation::from_name(interpolation)
+            .ok_or_else(|| format!("shifter: unknown interpolation {interpolation:?}"))?;
+        Ok(Self::with_reader(sample_rate, interpolation))
+    }
+
+    /// input(モノラル)を **in-place** にピッチシフトする（オフライン寄り）。
+    ///
+    /// - input: Float32Array 相当（JSから渡す）
+    /// - semitones: +12で1オクターブ上、-12で1オクターブ下
+    #[wasm_bindgen]
+    pub fn process_block(&mut self, input: &mut [f32], semitones: f32) {
+        self.process(input, semitones, true);
+    }
+
+    /// ピッチシフト中の出力の遅れ（サンプル、ディレイ長の半分）。0 半音付近はバイパスなので遅れない。
+    /// process_block（ストリーミング）の出力はこのぶん遅れる。補償は呼び出し側で行う。
+    #[wasm_bindgen(getter)]
+    pub fn latency_samples(&self) -> usize {
+        self.max_delay / 2
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// 補間方式の名前（linear / hermite / lagrange / sinc）
+    #[wasm_bindgen(getter)]
+    pub fn interpolation(&self) -> String {
+        self.reader.interpolation().name().to_string()
+    }
+}
+
+impl MelodyShifter {
+    fn with_reader(sample_rate: f32, interpolation: Interpolation) -> MelodyShifter {
         // delay-line pitch shifter: 40ms程度のディレイバッファ
         let mut max_delay = (sample_rate * 0.04).round() as usize;
-        max_delay = max_delay.clamp(256, 16384);
+        // 遅れ（ディレイ長の半分）が整数サンプルになるよう偶数にする
+        // （奇数になるのは 11025 Hz などで、そのときだけディレイが 1 サンプル短くなる）
+        max_delay = max_delay.clamp(256, 16384) / 2 * 2;
 
         MelodyShifter {
             sample_rate,
@@ -33,17 +128,15 @@ impl MelodyShifter {
             buffer: vec![0.0; max_delay],
             write_idx: 0,
             delay_pos: 0.0,
+            reader: DelayReader::new(interpolation),
         }
     }
 
-    /// input(モノラル)を **in-place** にピッチシフトする（オフライン寄り）。
-    ///
-    /// - input: Float32Array 相当（JSから渡す）
-    /// - semitones: +12で1オクターブ上、-12で1オクターブ下
-    #[wasm_bindgen]
-    pub fn process_block(&mut self, input: &mut [f32], semitones: f32) {
+    /// allow_bypass = false なら 0 半音付近でもディレイを通して鳴らす（遅れが途切れない）。
+    /// シフトした（ディレイから読んだ）なら true、バイパスなら false（入力はそのまま、ディレイには書く）。
+    fn process(&mut self, input: &mut [f32], semitones: f32, allow_bypass: bool) -> bool {
         if input.is_empty() {
-            return;
+            return false;
         }
 
         // semitones + は高く、- は低く
@@ -54,84 +147,85 @@ impl MelodyShifter {
         // 極端な値は暴れるので軽く制限
         ratio = ratio.clamp(0.5, 2.0);
 
-        let bypass = semitones.abs() < 1.0e-3 || (ratio - 1.0).abs() < 1.0e-3;
+        let bypass = allow_bypass && (semitones.abs() < 1.0e-3 || (ratio - 1.0).abs() < 1.0e-3);
         let len = self.max_delay as f32;
         let half = len * 0.5;
 
-        for x in input.iter_mut() {
-            let in_sample = *x;
+        let mut d1s = [0.0_f32; SHIFTER_BLOCK];
+        let mut fades = [0.0_f32; SHIFTER_BLOCK];
+        for block in input.chunks_mut(SHIFTER_BLOCK) {
+            let n = block.len();
+            if !bypass {
+                // 読み出し位置は入力によらないので、ブロックぶん先に並べてクロスフェードをまとめて求める
+                let mut d = self.delay_pos;
+                for d1 in d1s[..n].iter_mut() {
+                    *d1 = d;
+                    d = advance_delay(d, ratio, len);
+                }
+                simd::raised_cosine(&d1s[..n], len, &mut fades[..n]);
+            }
 
-            // write
-            self.buffer[self.write_idx] = in_sample;
+            for (i, x) in block.iter_mut().enumerate() {
+                let in_sample = *x;
 
-            let out_sample = if bypass {
-                in_sample
-            } else {
-                // 2-tap crossfade delay pitch shifter (Bernsee系)
-                let d1 = self.delay_pos;
-                let mut d2 = d1 + half;
-                if d2 >= len {
-                    d2 -= len;
-                }
+                // write
+                self.buffer[self.write_idx] = in_sample;
 
-                let y1 = read_delay_interp(&self.buffer, self.write_idx, d1);
-                let y2 = read_delay_interp(&self.buffer, self.write_idx, d2);
+                let out_sample = if bypass {
+                    in_sample
+                } else {
+                    // 2-tap crossfade delay pitch shifter (Bernsee系)
+                    let d1 = d1s[i];
+                    let mut d2 = d1 + half;
+                    if d2 >= len {
+                        d2 -= len;
+                    }
 
-                let fade = 0.5 - 0.5 * (2.0 * PI * d1 / len).cos();
-                y1 * fade + y2 * (1.0 - fade)
-            };
+                    let y1 = self.reader.read(&self.buffer, self.write_idx, d1);
+                    let y2 = self.reader.read(&self.buffer, self.write_idx, d2);
 
-            *x = out_sample;
+                    let fade = fades[i];
+                    y1 * fade + y2 * (1.0 - fade)
+                };
 
-            // advance
-            self.write_idx += 1;
-            if self.write_idx >= self.max_delay {
-                self.write_idx = 0;
-            }
+                *x = out_sample;
 
-            // delay pos update: read speed = 1 + (ratio - 1) => ratio
-            self.delay_pos += 1.0 - ratio;
-            while self.delay_pos < 0.0 {
-                self.delay_pos += len;
-            }
-            while self.delay_pos >= len {
-                self.delay_pos -= len;
+                // advance
+                self.write_idx += 1;
+                if self.write_idx >= self.max_delay {
+                    self.write_idx = 0;
+                }
+                self.delay_pos = advance_delay(self.delay_pos, ratio, len);
             }
         }
-    }
-
-    #[wasm_bindgen(getter)]
-    pub fn sample_rate(&self) -> f32 {
-        self.sample_rate
+        !bypass
     }
 }
 
-fn read_delay_interp(buffer: &[f32], write_idx: usize, delay: f32) -> f32 {
-    let len = buffer.len() as f32;
-    if len <= 1.0 {
-        return 0.0;
-    }
+// MelodyShifter::process でクロスフェードをまとめて求める単位（サンプル）
+const SHIFTER_BLOCK: usize = 64;
 
-    let mut pos = (write_idx as f32) - delay;
-    pos = pos % len;
-    if pos < 0.0 {
-        pos += len;
+/// delay pos update: read speed = 1 + (ratio - 1) => ratio
+fn advance_delay(delay_pos: f32, ratio: f32, len: f32) -> f32 {
+    let mut d = delay_pos + (1.0 - ratio);
+    while d < 0.0 {
+        d += len;
     }
-
-    let i0 = pos.floor() as usize;
-    let frac = pos - (i0 as f32);
-    let i1 = if i0 + 1 >= buffer.len() { 0 } else { i0 + 1 };
-    let a = buffer[i0];
-    let b = buffer[i1];
-    a + (b - a) * frac
+    while d >= len {
+        d -= len;
+    }
+    d
 }
 
 #[derive(Clone, Debug)]
 struct NoteSpan {
+    // index in the arrays passed to set_notes (before filtering/sorting)
+    index: usize,
+
     start: f32,
     end: f32,
 
-    // absolute pitch reference (MIDI note number)
+    // measured pitch (MIDI note number, A4 = 440 Hz 12-TET regardless of the tuning)
     base_semitone: f32,
 
     // pitch (semitones)
@@ -151,8 +245,61 @@ struct NoteSpan {
 
     // per-note harmonic profile (linear gain, harmonic 1..N)
     harmonic_profile: Vec<f32>,
+
+    // explicit target pitch (MIDI), e.g. from an imported MIDI file; overrides key/scale
+    target_midi: Option<f32>,
+    // scale correction: 0..1 (100% = スケール音ぴったり)
+    correction_strength: f32,
+    // semitones, derived from key/scale + strength (update_corrections)
+    correction_offset: f32,
+    // semitones (cent-level), just intonation on top of the semitone target, scaled by strength
+    just_offset: f32,
+}
+
+// pitch curve inside a note (see NoteSpan::shift_at)
+const MOD_HZ: f32 = 5.5;
+const MOD_AMP_SEMI: f32 = 0.25;
+const DRIFT_AMP_SEMI: f32 = 0.2;
+const TIME_RAMP_BASE_SEC: f32 = 0.03;
+
+impl NoteSpan {
+    /// ノート内の時刻 t（秒）でのシフト量（半音）。
+    /// ピッチセンター + 揺れ（mod）+ ドリフトに、ノート頭/尻のランプを掛けたもの。
+    fn shift_at(&self, t: f32) -> f32 {
+        let dur = (self.end - self.start).max(1.0e-6);
+        let u = ((t - self.start) / dur).clamp(0.0, 1.0);
+
+        let center = self.static_shift();
+        let mod_part = (2.0 * PI * MOD_HZ * (t - self.start)).sin() * (MOD_AMP_SEMI * self.pitch_mod_amount);
+        let drift_part = (u - 0.5) * 2.0 * (DRIFT_AMP_SEMI * self.pitch_drift_amount);
+
+        // time-tool の簡易実装：ノート頭/尻で補正量をランプさせる
+        // （バッファ長は変えず、アタック/リリースの“タイミング感”だけ反映）
+        let ramp_s = TIME_RAMP_BASE_SEC * self.time_stretch_start;
+        let ramp_e = TIME_RAMP_BASE_SEC * self.time_stretch_end;
+        let ramp_s = ramp_s.min(dur * 0.45).max(0.0);
+        let ramp_e = ramp_e.min(dur * 0.45).max(0.0);
+
+        let mut env: f32 = 1.0_f32;
+        if ramp_s > 0.0_f32 {
+            let a = (t - self.start) / ramp_s;
+            env = env.min(a.clamp(0.0_f32, 1.0_f32));
+        }
+        if ramp_e > 0.0_f32 {
+            let b = (self.end - t) / ramp_e;
+            env = env.min(b.clamp(0.0_f32, 1.0_f32));
+        }
+
+        (center + mod_part + drift_part) * env
+    }
+
+    /// 揺れを除いたノート全体のシフト量（半音）
+    fn static_shift(&self) -> f32 {
+        self.pitch_offset + self.pitch_center_offset + self.correction_offset + self.just_offset
+    }
 }
 
+#[derive(Clone)]
 struct HarmonicEQ {
     gains: Vec<f32>, // harmonic 1..N => linear gain (1.0 = 0dB)
 }
@@ -168,10 +315,7 @@ impl HarmonicEQ {
     }
 }
 
-fn midi_to_hz(midi: f32) -> f32 {
-    440.0_f32 * (2.0_f32).powf((midi - 69.0) / 12.0)
-}
-
+/// バイクアッドの係数（状態は FilterBank が持つ）。
 #[derive(Clone, Copy, Debug)]
 struct Biquad {
     b0: f32,
@@ -179,8 +323,6 @@ struct Biquad {
     b2: f32,
     a1: f32,
     a2: f32,
-    z1: f32,
-    z2: f32,
 }
 
 impl Biquad {
@@ -206,17 +348,149 @@ impl Biquad {
             b2: b2 / a0,
             a1: a1 / a0,
             a2: a2 / a0,
-            z1: 0.0,
-            z2: 0.0,
+        }
+    }
+}
+
+// 倍音 EQ で使う倍音（バンドパス）の数の上限
+const MAX_HARMONIC_FILTERS: usize = 24;
+// FilterBank の Lane の数（1 つに LANES 本）
+const FILTER_GROUPS: usize = MAX_HARMONIC_FILTERS.div_ceil(LANES);
+
+/// 倍音ごとのバンドパスの並び。係数と状態を Lane ごとに LANES 本ずつ持ち、まとめて回す
+/// （simd feature なしなら 1 本ずつ）。固定長の配列なので、f0 が変わって作り直しても確保しない。
+#[derive(Clone)]
+struct FilterBank {
+    b0: [Lane; FILTER_GROUPS],
+    b1: [Lane; FILTER_GROUPS],
+    b2: [Lane; FILTER_GROUPS],
+    a1: [Lane; FILTER_GROUPS],
+    a2: [Lane; FILTER_GROUPS],
+    z1: [Lane; FILTER_GROUPS],
+    z2: [Lane; FILTER_GROUPS],
+    // linear, per filter (unused lanes stay 0)
+    gain: [Lane; FILTER_GROUPS],
+    len: usize,
+}
+
+impl FilterBank {
+    fn new() -> Self {
+        let zero = [simd::splat(0.0); FILTER_GROUPS];
+        Self {
+            b0: zero,
+            b1: zero,
+            b2: zero,
+            a1: zero,
+            a2: zero,
+            z1: zero,
+            z2: zero,
+            gain: zero,
+            len: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// f0 の倍音 1..=count（上限 MAX_HARMONIC_FILTERS）に置き直し、状態も消す。
+    fn rebuild(&mut self, sr: f32, f0: f32, count: usize, q: f32) {
+        *self = Self::new();
+        self.len = count.min(MAX_HARMONIC_FILTERS);
+        for h in 0..self.len {
+            let c = Biquad::new_bandpass(sr, f0 * (h + 1) as f32, q);
+            simd::set(&mut self.b0, h, c.b0);
+            simd::set(&mut self.b1, h, c.b1);
+            simd::set(&mut self.b2, h, c.b2);
+            simd::set(&mut self.a1, h, c.a1);
+            simd::set(&mut self.a2, h, c.a2);
+        }
+    }
+
+    /// 倍音 h（0 = 基音）のゲインを gain(h) にする。
+    fn set_gains(&mut self, gain: impl Fn(usize) -> f32) {
+        for h in 0..self.len {
+            simd::set(&mut self.gain, h, gain(h));
         }
     }
 
+    /// x を全フィルタに通し、ゲインを掛けた出力の和を返す。
     fn process(&mut self, x: f32) -> f32 {
-        // Direct Form II Transposed
-        let y = self.b0 * x + self.z1;
-        self.z1 = self.b1 * x - self.a1 * y + self.z2;
-        self.z2 = self.b2 * x - self.a2 * y;
-        y
+        let x = simd::splat(x);
+        let mut acc = simd::splat(0.0);
+        for k in 0..self.len.div_ceil(LANES) {
+            // Direct Form II Transposed
+            let y = self.b0[k] * x + self.z1[k];
+            self.z1[k] = self.b1[k] * x - self.a1[k] * y + self.z2[k];
+            self.z2[k] = self.b2[k] * x - self.a2[k] * y;
+            acc += y * self.gain[k];
+        }
+        simd::sum(acc)
+    }
+}
+
+// process_segments: ノート内パラメータを「時間で変化するピッチ」に反映するため、
+// note.end だけでなく固定ブロックで区切って shifter に渡す。
+const BLOCK_SAMPLES: usize = 128;
+
+/// 1チャンネル分の処理状態（シフタ + 音色フィルタ）。
+#[derive(Clone)]
+struct ChannelState {
+    shifter: MelodyShifter,
+    // input latency_samples ahead of the output, fed to the shifter
+    lookahead: [f32; BLOCK_SAMPLES],
+
+    // stateful timbre processing to avoid clicks at block boundaries
+    timbre_active_note_idx: Option<usize>,
+    timbre_filters: FilterBank,
+    timbre_lp: f32,
+    timbre_last_f0: f32,
+}
+
+impl ChannelState {
+    fn new(sample_rate: f32, interpolation: Interpolation) -> Self {
+        Self {
+            shifter: MelodyShifter::with_reader(sample_rate, interpolation),
+            lookahead: [0.0; BLOCK_SAMPLES],
+            timbre_active_note_idx: None,
+            timbre_filters: FilterBank::new(),
+            timbre_lp: 0.0,
+            timbre_last_f0: 0.0,
+        }
+    }
+
+    fn reset_timbre(&mut self) {
+        self.timbre_active_note_idx = None;
+        self.timbre_filters.clear();
+        self.timbre_lp = 0.0;
+        self.timbre_last_f0 = 0.0;
+    }
+}
+
+/// process_segments の進み具合（次に処理するサンプルと、そこで見ているノート）。
+#[derive(Clone, Copy, Default)]
+struct RenderCursor {
+    sample_idx: usize,
+    note_idx: usize,
+    // whether the previous segment was inside a note
+    in_note: bool,
+}
+
+/// 分割オフラインレンダリング中のバッファ（planar）。
+#[derive(Clone)]
+struct RenderJob {
+    buffer: Vec<f32>,
+    frames: usize,
+    cursor: RenderCursor,
+}
+
+impl RenderJob {
+    fn is_done(&self) -> bool {
+        self.cursor.sample_idx >= self.frames
     }
 }
 
@@ -227,17 +501,44 @@ impl Biquad {
 /// - オフセットが変わる区間ごとに `MelodyShifter` を呼ぶ
 /// とする。後でF0やノート編集に発展させやすい構造だけ先に作る。
 #[wasm_bindgen]
+#[derive(Clone)]
 pub struct MelodyEngine {
     sample_rate: f32,
     notes: Vec<NoteSpan>,
-    shifter: MelodyShifter,
     harmonic_eq: HarmonicEQ,
 
-    // stateful timbre processing to avoid clicks at block boundaries
-    timbre_active_note_idx: Option<usize>,
-    timbre_filters: Vec<Biquad>,
-    timbre_lp: f32,
-    timbre_last_f0: f32,
+    // note number <-> Hz (reference pitch / microtonal tables)
+    tuning: Tuning,
+    // key/scale for pitch correction (None = 補正なし)
+    scale: Option<Scale>,
+    // 0..1, set_notes 直後に各ノートへ入る既定値
+    default_correction_strength: f32,
+    // just intonation relative to tonic / chord roots
+    just_intonation: JustIntonation,
+    just_enabled: bool,
+
+    // backing voices rendered from the lead notes
+    harmony_voices: Vec<HarmonyVoice>,
+    // double-tracking copies of the corrected vocal
+    double_copies: Vec<DoubleCopy>,
+    double_seed: u64,
+
+    // project metadata (saved with save_project, not used for processing)
+    audio_ref: Option<AudioRef>,
+    pitch_frames: Vec<PitchFrame>,
+
+    // delay-line read interpolation of the shifters
+    interpolation: Interpolation,
+    // per-channel shifter/timbre state (index 0 = mono)
+    channels: Vec<ChannelState>,
+    // planar copy for process_interleaved (sized by set_max_buffer)
+    scratch: Vec<f32>,
+    // chunked offline render in progress (start_render .. take_render_output)
+    render_job: Option<RenderJob>,
+    // source audio owned by the engine (load_source); never modified
+    source: Option<SourceAudio>,
+    // per-note render cache for partial re-rendering (build_render_cache)
+    render_cache: CacheSlot,
 }
 
 #[wasm_bindgen]
@@ -247,13 +548,27 @@ impl MelodyEngine {
         MelodyEngine {
             sample_rate,
             notes: Vec::new(),
-            shifter: MelodyShifter::new(sample_rate),
             harmonic_eq: HarmonicEQ::new(),
 
-            timbre_active_note_idx: None,
-            timbre_filters: Vec::new(),
-            timbre_lp: 0.0,
-            timbre_last_f0: 0.0,
+            tuning: Tuning::default(),
+            scale: None,
+            default_correction_strength: 0.0,
+            just_intonation: JustIntonation::default(),
+            just_enabled: false,
+
+            harmony_voices: Vec::new(),
+            double_copies: Vec::new(),
+            double_seed: 0,
+
+            audio_ref: None,
+            pitch_frames: Vec::new(),
+
+            interpolation: Interpolation::Linear,
+            channels: vec![ChannelState::new(sample_rate, Interpolation::Linear)],
+            scratch: Vec::new(),
+            render_job: None,
+            source: None,
+            render_cache: CacheSlot::default(),
         }
     }
 
@@ -265,7 +580,7 @@ impl MelodyEngine {
             if !g.is_finite() {
                 out.push(1.0);
             } else {
-                out.push(g.max(0.0).min(4.0));
+                out.push(g.clamp(0.0, 4.0));
             }
         }
         self.harmonic_eq.gains = out;
@@ -273,12 +588,14 @@ impl MelodyEngine {
 
     /// ノート情報をセットする。
     /// - note_starts / note_ends: 秒
+    /// - base_semitones: 計測ピッチ（A4 = 440 Hz の 12平均律でのノート番号、音律の設定によらない）
     /// - note_offsets: 半音（+で高く、-で低く）
     /// - pitch_center_offsets: 半音（ピッチセンター）
     /// - pitch_mod_amounts / pitch_drift_amounts: 0..2（量）
     /// - time_stretch_starts / time_stretch_ends: 0.5..2.0（倍率、現状未適用）
     /// - formant_shifts: 半音（現状未適用）
     #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
     pub fn set_notes(
         &mut self,
         note_starts: Vec<f32>,
@@ -338,8 +655,8 @@ impl MelodyEngine {
                 continue;
             }
 
-            let clamp_amount_0_2 = |v: f32| v.max(0.0).min(2.0);
-            let clamp_stretch_05_2 = |v: f32| v.max(0.5).min(2.0);
+            let clamp_amount_0_2 = |v: f32| v.clamp(0.0, 2.0);
+            let clamp_stretch_05_2 = |v: f32| v.clamp(0.5, 2.0);
 
             let hp = harmonics_per_note as usize;
             let mut profile: Vec<f32> = Vec::new();
@@ -349,13 +666,14 @@ impl MelodyEngine {
                     profile.reserve(hp);
                     for j in 0..hp {
                         let g = note_harmonics_flat[i * hp + j];
-                        profile.push(if g.is_finite() { g.max(0.0).min(4.0) } else { 1.0 });
+                        profile.push(if g.is_finite() { g.clamp(0.0, 4.0) } else { 1.0 });
                     }
                 } else {
                     profile = vec![1.0; hp];
                 }
             }
             self.notes.push(NoteSpan {
+                index: i,
                 start: s.max(0.0),
                 end: e.max(0.0),
                 base_semitone: base,
@@ -367,252 +685,1658 @@ impl MelodyEngine {
                 time_stretch_end: clamp_stretch_05_2(ts_e),
                 formant_shift: f,
                 harmonic_profile: profile,
+                target_midi: None,
+                correction_strength: self.default_correction_strength,
+                correction_offset: 0.0,
+                just_offset: 0.0,
             });
         }
 
         // まずは単純に start でソート（重なりや包含は未定義）
         self.notes.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap_or(std::cmp::Ordering::Equal));
+        self.update_corrections();
 
         // reset timbre state (note indices/profiles may have changed)
-        self.timbre_active_note_idx = None;
-        self.timbre_filters.clear();
-        self.timbre_lp = 0.0;
-        self.timbre_last_f0 = 0.0;
+        self.reset_timbre_state();
     }
 
-    /// input(モノラル)をノート配列に従って in-place で処理する。
+    /// キー/スケールを名前で設定する。
+    /// - root_pitch_class: 0..12（C=0, C#=1, ...）
+    /// - scale_name: major / minor / dorian / ... / chromatic
     ///
-    /// 「属するノートがあればそのoffsetでピッチシフト、なければバイパス」という仕様。
+    /// 未知のスケール名なら何も変えずに false を返す。
     #[wasm_bindgen]
-    pub fn process_buffer(&mut self, input: &mut [f32]) {
-        if input.is_empty() {
-            return;
-        }
-        if self.notes.is_empty() {
-            return; // 全バイパス
-        }
-
-        let sr = self.sample_rate;
-        if !sr.is_finite() || sr <= 0.0 {
-            return;
+    pub fn set_key(&mut self, root_pitch_class: f32, scale_name: &str) -> bool {
+        match Scale::from_name(root_pitch_class, scale_name) {
+            Some(scale) => {
+                self.scale = Some(scale);
+                self.update_corrections();
+                true
+            }
+            None => false,
         }
+    }
 
-        // ノート内パラメータを「時間で変化するピッチ」に反映するため、
-        // note.end だけでなく固定ブロックで区切って shifter に渡す。
-        const BLOCK_SAMPLES: usize = 128;
-        const MOD_HZ: f32 = 5.5;
-        const MOD_AMP_SEMI: f32 = 0.25;
-        const DRIFT_AMP_SEMI: f32 = 0.2;
-        const TIME_RAMP_BASE_SEC: f32 = 0.03;
+    /// 任意の度数列（ルートからの半音, 0..12）でスケールを設定する。
+    #[wasm_bindgen]
+    pub fn set_custom_scale(&mut self, root_pitch_class: f32, steps: Vec<f32>) {
+        self.scale = Some(Scale::custom(root_pitch_class, steps));
+        self.update_corrections();
+    }
 
-        // 区間ごとに処理：ノート境界で slice を切り替える
-        let mut sample_idx: usize = 0;
-        let mut note_idx: usize = 0;
+    /// スケール補正を無効にする（ノートの補正量は保持）。
+    #[wasm_bindgen]
+    pub fn clear_key(&mut self) {
+        self.scale = None;
+        self.update_corrections();
+    }
 
-        while sample_idx < input.len() {
-            let t = (sample_idx as f32) / sr;
+    /// 全ノートの補正量を一括設定する（0..100 %）。
+    /// 以降の set_notes で入るノートにも同じ値が使われる。
+    #[wasm_bindgen]
+    pub fn set_correction_strength(&mut self, percent: f32) {
+        let strength = percent_to_strength(percent);
+        self.default_correction_strength = strength;
+        for note in self.notes.iter_mut() {
+            note.correction_strength = strength;
+        }
+        self.update_corrections();
+    }
 
-            // t より前のノートを前進して捨てる
-            while note_idx < self.notes.len() && t >= self.notes[note_idx].end {
-                note_idx += 1;
+    /// ノートごとの補正量（0..100 %）を set_notes に渡した順で設定する。
+    /// 足りない分・除外されたノートは無視する。
+    #[wasm_bindgen]
+    pub fn set_note_correction_strengths(&mut self, percents: Vec<f32>) {
+        for note in self.notes.iter_mut() {
+            if let Some(&p) = percents.get(note.index) {
+                note.correction_strength = percent_to_strength(p);
             }
+        }
+        self.update_corrections();
+    }
 
-            // 現在時刻がノート内かどうか
-            let (offset, next_boundary_time, active_note_idx) = if note_idx < self.notes.len() {
-                let note = &self.notes[note_idx];
-                if t >= note.start && t < note.end {
-                    // ブロック内は一定オフセットとして近似
-                    let block_end_sample = (sample_idx + BLOCK_SAMPLES).min(input.len());
-                    let block_end_time = (block_end_sample as f32) / sr;
-                    let next_time = note.end.min(block_end_time);
-
-                    let mid_sample = sample_idx as f32 + ((block_end_sample - sample_idx) as f32) * 0.5;
-                    let t_mid = mid_sample / sr;
-                    let dur = (note.end - note.start).max(1.0e-6);
-                    let mut u = (t_mid - note.start) / dur;
-                    if u < 0.0 {
-                        u = 0.0;
-                    }
-                    if u > 1.0 {
-                        u = 1.0;
-                    }
-
-                    let center = note.pitch_offset + note.pitch_center_offset;
-                    let mod_part = (2.0 * PI * MOD_HZ * (t_mid - note.start)).sin()
-                        * (MOD_AMP_SEMI * note.pitch_mod_amount);
-                    let drift_part = (u - 0.5) * 2.0 * (DRIFT_AMP_SEMI * note.pitch_drift_amount);
-
-                    // time-tool の簡易実装：ノート頭/尻で補正量をランプさせる
-                    // （バッファ長は変えず、アタック/リリースの“タイミング感”だけ反映）
-                    let ramp_s = TIME_RAMP_BASE_SEC * note.time_stretch_start;
-                    let ramp_e = TIME_RAMP_BASE_SEC * note.time_stretch_end;
-                    let ramp_s = ramp_s.min(dur * 0.45).max(0.0);
-                    let ramp_e = ramp_e.min(dur * 0.45).max(0.0);
-
-                    let mut env: f32 = 1.0_f32;
-                    if ramp_s > 0.0_f32 {
-                        let a = (t_mid - note.start) / ramp_s;
-                        env = env.min(a.max(0.0_f32).min(1.0_f32));
-                    }
-                    if ramp_e > 0.0_f32 {
-                        let b = (note.end - t_mid) / ramp_e;
-                        env = env.min(b.max(0.0_f32).min(1.0_f32));
-                    }
+    /// 純正律補正を有効にする。目標ノートをトニック（0..12, C=0）からの純正音程に寄せる。
+    /// コード区間（set_chord_regions）内ではそのルートが基準になる。
+    #[wasm_bindgen]
+    pub fn set_just_intonation(&mut self, tonic_pitch_class: f32) {
+        self.just_intonation.set_tonic(tonic_pitch_class);
+        self.just_enabled = true;
+        self.update_corrections();
+    }
 
-                    (
-                        (center + mod_part + drift_part) * env,
-                        next_time,
-                        Some(note_idx),
-                    )
-                } else {
-                    // 次のノート開始までバイパス（こちらも大きすぎないように固定ブロックで刻む）
-                    let block_end_sample = (sample_idx + BLOCK_SAMPLES).min(input.len());
-                    let block_end_time = (block_end_sample as f32) / sr;
-                    (0.0, note.start.min(block_end_time), None)
-                }
-            } else {
-                // 以降はノートなし
-                let block_end_sample = (sample_idx + BLOCK_SAMPLES).min(input.len());
-                (0.0, (block_end_sample as f32) / sr, None)
-            };
+    /// 純正律補正を無効にして平均律の目標に戻す（コード区間は保持）。
+    #[wasm_bindgen]
+    pub fn disable_just_intonation(&mut self) {
+        self.just_enabled = false;
+        self.update_corrections();
+    }
 
-            let mut end_sample = (next_boundary_time * sr).ceil() as isize;
-            if end_sample < 0 {
-                end_sample = 0;
-            }
-            let end_sample = (end_sample as usize).min(input.len());
-            let end_sample = end_sample.max(sample_idx + 1);
+    /// コード区間をセットする。
+    /// - region_starts / region_ends: 秒
+    /// - chord_roots: ピッチクラス（0..12, C=0）
+    ///
+    /// ノートは中央の時刻が入る区間のルートを基準にする。区間外はトニック。
+    #[wasm_bindgen]
+    pub fn set_chord_regions(&mut self, region_starts: Vec<f32>, region_ends: Vec<f32>, chord_roots: Vec<f32>) {
+        self.just_intonation.set_regions(&region_starts, &region_ends, &chord_roots);
+        self.update_corrections();
+    }
 
-            let slice = &mut input[sample_idx..end_sample];
-            self.shifter.process_block(slice, offset);
-
-            // Apply simple timbre shaping (harmonics + formant) for this note block.
-            if let Some(nidx) = active_note_idx {
-                // note changes => reset state to avoid carrying filter memories across notes
-                if self.timbre_active_note_idx != Some(nidx) {
-                    self.timbre_active_note_idx = Some(nidx);
-                    self.timbre_filters.clear();
-                    self.timbre_lp = 0.0;
-                    self.timbre_last_f0 = 0.0;
-                }
+    /// キー上の度数でハモりパートを追加し、そのインデックスを返す。
+    /// - scale_degrees: +2 = 3度上, +4 = 5度上, -2 = 3度下
+    /// - formant_shift: 半音（各ノートの formant に加算）
+    /// - humanize_ms: ノートごとのタイミングのばらつき（±ms）
+    /// - gain: 線形
+    /// - seed: ばらつきの乱数シード（同じ値なら同じ結果）
+    #[wasm_bindgen]
+    pub fn add_diatonic_harmony_voice(
+        &mut self,
+        scale_degrees: i32,
+        formant_shift: f32,
+        humanize_ms: f32,
+        gain: f32,
+        seed: u32,
+    ) -> usize {
+        self.push_harmony_voice(IntervalRule::Diatonic(scale_degrees), formant_shift, humanize_ms, gain, seed)
+    }
 
-                let note = &self.notes[nidx];
-                apply_harmonic_and_formant_stateful(
-                    slice,
-                    sr,
-                    &self.harmonic_eq,
-                    note,
-                    &mut self.timbre_filters,
-                    &mut self.timbre_lp,
-                    &mut self.timbre_last_f0,
-                );
-            }
+    /// ノートごとの目標ノート（MIDI, set_notes の順）でハモりパートを追加する。
+    /// NaN を入れたノートはそのパートでは歌わない。
+    #[wasm_bindgen]
+    pub fn add_custom_harmony_voice(
+        &mut self,
+        target_midis: Vec<f32>,
+        formant_shift: f32,
+        humanize_ms: f32,
+        gain: f32,
+        seed: u32,
+    ) -> usize {
+        self.push_harmony_voice(IntervalRule::Custom(target_midis), formant_shift, humanize_ms, gain, seed)
+    }
 
-            sample_idx = end_sample;
-        }
+    #[wasm_bindgen]
+    pub fn clear_harmony_voices(&mut self) {
+        self.harmony_voices.clear();
     }
 
     #[wasm_bindgen(getter)]
-    pub fn sample_rate(&self) -> f32 {
-        self.sample_rate
+    pub fn harmony_voice_count(&self) -> usize {
+        self.harmony_voices.len()
     }
-}
 
-fn apply_harmonic_and_formant_stateful(
-    input: &mut [f32],
-    sr: f32,
-    global_eq: &HarmonicEQ,
-    note: &NoteSpan,
-    filters: &mut Vec<Biquad>,
-    lp_state: &mut f32,
-    last_f0: &mut f32,
-) {
-    if input.is_empty() {
-        return;
+    /// input（リードの元音声、モノラル）からハモりパート1本を別バッファに書き出す。
+    /// エンジン自身の処理状態は変えない。
+    #[wasm_bindgen]
+    pub fn render_harmony_voice(&self, voice: usize, input: &[f32]) -> Result<Vec<f32>, String> {
+        let v = self
+            .harmony_voices
+            .get(voice)
+            .ok_or_else(|| format!("harmony: no voice {voice}"))?;
+        v.render(self, input)
     }
-    if !sr.is_finite() || sr <= 0.0 {
-        return;
+
+    /// render_harmony_voice の入力に load_source した元音声（チャンネル平均）を使う版。
+    #[wasm_bindgen]
+    pub fn render_harmony_voice_source(&self, voice: usize) -> Result<Vec<f32>, String> {
+        let source = self.source.as_ref().ok_or("harmony: no source loaded (load_source)")?;
+        self.render_harmony_voice(voice, &source.mono())
     }
 
-    // --- Harmonic EQ (very rough): filter bank around n*f0, then mix back.
-    // Use absolute pitch (base + center) as f0 reference.
-    let f0_midi = note.base_semitone + note.pitch_center_offset;
-    let f0 = midi_to_hz(f0_midi);
-    if !f0.is_finite() || f0 <= 0.0 {
-        return;
+    /// ダブリング用のコピーを追加し、そのインデックスを返す。
+    /// - detune_cents: 一定のデチューン（セント）
+    /// - delay_ms: 遅れ（ms、コピーごとに ±20% ばらつき、さらに ±25% の範囲でゆっくり揺れる）
+    /// - pitch_variation_cents: ノートごとのピッチのばらつき（±セント）
+    /// - formant_variation: ノートごとのフォルマントのばらつき（±半音）
+    /// - pan: -1（左）..1（右）
+    /// - gain: 線形
+    #[wasm_bindgen]
+    pub fn add_double_copy(
+        &mut self,
+        detune_cents: f32,
+        delay_ms: f32,
+        pitch_variation_cents: f32,
+        formant_variation: f32,
+        pan: f32,
+        gain: f32,
+    ) -> usize {
+        // 非有限値はレンダリング時と同じ既定値にしておく（プロジェクトに null を書かないため）
+        let finite = |v: f32, default: f32| if v.is_finite() { v } else { default };
+        self.double_copies.push(DoubleCopy {
+            detune_cents: finite(detune_cents, 0.0),
+            delay_ms: finite(delay_ms, 0.0),
+            pitch_variation_cents: finite(pitch_variation_cents, 0.0),
+            formant_variation: finite(formant_variation, 0.0),
+            pan: finite(pan, 0.0),
+            gain: finite(gain, 1.0),
+        });
+        self.double_copies.len() - 1
     }
 
-    // If f0 changed a lot, rebuild filters (and reset their states).
-    let rel_change = if (*last_f0).is_finite() && *last_f0 > 0.0 {
-        ((f0 - *last_f0) / *last_f0).abs()
-    } else {
-        1.0
-    };
+    #[wasm_bindgen]
+    pub fn clear_double_copies(&mut self) {
+        self.double_copies.clear();
+    }
 
-    let nyq = sr * 0.5;
-    let n_harm = global_eq.gains.len().max(note.harmonic_profile.len()).min(24);
+    #[wasm_bindgen(getter)]
+    pub fn double_copy_count(&self) -> usize {
+        self.double_copies.len()
+    }
 
-    // Ensure filters match current f0/harmonic count.
-    let desired = (1..=n_harm)
-        .map(|h| f0 * (h as f32))
-        .take_while(|&f| f.is_finite() && f < nyq * 0.98)
-        .count();
+    /// ダブリングのばらつきの乱数シード（同じ値なら同じ結果）。
+    #[wasm_bindgen]
+    pub fn set_double_seed(&mut self, seed: u32) {
+        self.double_seed = seed as u64;
+    }
 
-    let need_rebuild = filters.len() != desired || rel_change > 0.08;
-    if need_rebuild {
-        filters.clear();
-        let q = 12.0;
-        for h in 0..desired {
-            let harm_idx = (h + 1) as f32;
-            let f = f0 * harm_idx;
-            filters.push(Biquad::new_bandpass(sr, f, q));
-        }
-        *last_f0 = f0;
+    /// input（元音声、モノラル）から全コピーを補正付きでレンダリングし、
+    /// パンを振ったステレオを planar（先頭 input.length が L、残りが R）で返す。
+    /// ドライ（リード）は含まない。エンジン自身の処理状態は変えない。
+    #[wasm_bindgen]
+    pub fn render_doubles(&self, input: &[f32]) -> Vec<f32> {
+        doubler::render_doubles(self, &self.double_copies, self.double_seed, input)
     }
 
-    // Keep it subtle: this is not a true EQ, it's a rough "timbre feel".
-    let mix = 0.25_f32;
-    let n_filt = filters.len().max(1) as f32;
+    /// render_doubles の入力に load_source した元音声（チャンネル平均）を使う版。
+    #[wasm_bindgen]
+    pub fn render_doubles_source(&self) -> Result<Vec<f32>, String> {
+        let source = self.source.as_ref().ok_or("doubler: no source loaded (load_source)")?;
+        Ok(self.render_doubles(&source.mono()))
+    }
 
-    for i in 0..input.len() {
-        let x = input[i];
-        let mut acc = 0.0_f32;
+    /// 基準ピッチ（既定 A4 = 440 Hz）を変える。.kbm 読み込み時はその基準ノートの周波数になる。
+    #[wasm_bindgen]
+    pub fn set_reference_pitch(&mut self, hz: f32) {
+        self.tuning.set_reference_hz(hz);
+        self.on_tuning_changed();
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn reference_pitch(&self) -> f32 {
+        self.tuning.reference_hz()
+    }
+
+    /// Scala .scl テキストを読み込んで音律を差し替える。
+    #[wasm_bindgen]
+    pub fn load_scala_scale(&mut self, scl_text: &str) -> Result<(), String> {
+        self.tuning.load_scl(scl_text)?;
+        self.on_tuning_changed();
+        Ok(())
+    }
+
+    /// Scala .kbm テキスト（鍵盤マッピング + 基準周波数）を読み込む。
+    #[wasm_bindgen]
+    pub fn load_scala_keyboard_map(&mut self, kbm_text: &str) -> Result<(), String> {
+        self.tuning.load_kbm(kbm_text)?;
+        self.on_tuning_changed();
+        Ok(())
+    }
+
+    /// A4 = 440 Hz の 12平均律に戻す。
+    #[wasm_bindgen]
+    pub fn reset_tuning(&mut self) {
+        self.tuning = Tuning::default();
+        self.on_tuning_changed();
+    }
+
+    /// 現在の音律でのノート番号（小数可）→ Hz。UI側の表示/検出もこれに揃える。
+    #[wasm_bindgen]
+    pub fn midi_to_hz(&self, midi: f32) -> f32 {
+        self.tuning.midi_to_hz(midi)
+    }
+
+    /// 現在の音律での Hz → ノート番号（小数）。
+    #[wasm_bindgen]
+    pub fn hz_to_midi(&self, hz: f32) -> f32 {
+        self.tuning.hz_to_midi(hz)
+    }
+
+    /// input(モノラル)をノート配列に従って in-place で処理する。
+    ///
+    /// 「属するノートがあればそのoffsetでピッチシフト、なければバイパス」という仕様。
+    #[wasm_bindgen]
+    pub fn process_buffer(&mut self, input: &mut [f32]) {
+        let frames = input.len();
+        self.process_channels(input, frames);
+    }
+
+    /// process_* に渡すバッファの最大の大きさ（1チャンネルあたりのサンプル数とチャンネル数）を伝えて、
+    /// 作業領域とチャンネルごとの処理状態を前もって確保する。
+    /// これ以下のバッファなら、process_buffer / process_planar / process_interleaved はメモリを確保しない。
+    #[wasm_bindgen]
+    pub fn set_max_buffer(&mut self, frames: usize, channels: usize) {
+        let channels = channels.max(1);
+        while self.channels.len() < channels {
+            self.channels.push(ChannelState::new(self.sample_rate, self.interpolation));
+        }
+        if channels > 1 {
+            let need = frames.saturating_mul(channels);
+            self.scratch.reserve(need.saturating_sub(self.scratch.len()));
+        }
+    }
+
+    /// インターリーブ（LRLR...）の多チャンネルバッファを in-place で処理する。
+    /// ピッチ補正カーブは全チャンネル共通（リンク）なので、チャンネル間の位相関係が保たれる。
+    #[wasm_bindgen]
+    pub fn process_interleaved(&mut self, input: &mut [f32], channels: usize) {
+        if channels <= 1 {
+            self.process_buffer(input);
+            return;
+        }
+        let frames = input.len() / channels;
+        // set_max_buffer で確保した作業領域を使い回す（足りなければここで広げる）
+        let mut planar = std::mem::take(&mut self.scratch);
+        planar.clear();
+        planar.resize(frames * channels, 0.0);
+        deinterleave(&input[..frames * channels], &mut planar, channels);
+        self.process_planar(&mut planar, channels);
+        interleave(&planar, &mut input[..frames * channels], channels);
+        self.scratch = planar;
+    }
 
-        for (h, filt) in filters.iter_mut().enumerate() {
-            let g_global = global_eq.gain(h);
-            let g_note = note.harmonic_profile.get(h).copied().unwrap_or(1.0);
-            // Cap per-harmonic gain to avoid blowing up when summing many harmonics.
-            let g = (g_global * g_note).max(0.0).min(2.0);
-            acc += filt.process(x) * g;
+    /// planar（チャンネルごとに連続: [ch0..., ch1..., ...]）の多チャンネルバッファを
+    /// in-place で処理する。input.length は channels の倍数であること（余りは無視）。
+    /// 処理状態を前の呼び出しから引き継ぐので、並列にはしない（並列に処理するなら render_source）。
+    #[wasm_bindgen]
+    pub fn process_planar(&mut self, input: &mut [f32], channels: usize) {
+        if channels <= 1 {
+            self.process_buffer(input);
+            return;
         }
+        let frames = input.len() / channels;
+        self.process_channels(&mut input[..frames * channels], frames);
+    }
 
-        // Normalize the summed band outputs.
-        acc /= n_filt;
+    /// 分割オフラインレンダリングを始める。input（planar, channels ch）はエンジン側にコピーされ、
+    /// render_step で少しずつ処理する。結果は process_planar を一度に呼んだ場合と同じになる。
+    /// 処理中のレンダリングがあれば破棄する。process_planar と同じく並列にはしない。
+    #[wasm_bindgen]
+    pub fn start_render(&mut self, input: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        let frames = input.len() / channels;
+        self.render_job = Some(RenderJob {
+            buffer: input[..frames * channels].to_vec(),
+            frames,
+            cursor: RenderCursor::default(),
+        });
+    }
+
+    /// レンダリングを最大 max_frames サンプル（ノート内の区間境界まで切り上げ）進める。
+    /// 完了したら（またはレンダリング中でなければ）true を返す。
+    #[wasm_bindgen]
+    pub fn render_step(&mut self, max_frames: usize) -> bool {
+        let Some(mut job) = self.render_job.take() else {
+            return true;
+        };
+        if !job.is_done() {
+            let until = job.cursor.sample_idx.saturating_add(max_frames.max(1));
+            self.process_segments(&mut job.buffer, job.frames, 0, &mut job.cursor, until);
+        }
+        let done = job.is_done();
+        self.render_job = Some(job);
+        done
+    }
+
+    /// 進み具合 0..1（レンダリング中でなければ 0）
+    #[wasm_bindgen(getter)]
+    pub fn render_progress(&self) -> f32 {
+        match &self.render_job {
+            Some(job) if job.frames > 0 => (job.cursor.sample_idx.min(job.frames) as f64 / job.frames as f64) as f32,
+            Some(_) => 1.0,
+            None => 0.0,
+        }
+    }
 
-        let mut y = x * (1.0 - mix) + acc * mix;
-        // Simple limiter/soft clip to prevent hard digital clipping.
-        y = y.tanh();
-        input[i] = y.max(-1.0).min(1.0);
+    #[wasm_bindgen(getter)]
+    pub fn render_in_progress(&self) -> bool {
+        self.render_job.as_ref().is_some_and(|job| !job.is_done())
+    }
+
+    /// レンダリングを中止してバッファを捨てる。シフタ/フィルタの状態もリセットする
+    /// （途中までの状態が次の処理に残らないように）。
+    #[wasm_bindgen]
+    pub fn cancel_render(&mut self) {
+        if self.render_job.take().is_some() {
+            self.reset_processing_state();
+        }
     }
 
-    // --- Formant shift (very rough): spectral tilt using 1-pole lowpass split.
-    // Positive formant_shift => brighter; negative => darker.
-    let s = note.formant_shift;
+    /// 完了したレンダリング結果（planar）を取り出す。未完了ならエラー。
+    #[wasm_bindgen]
+    pub fn take_render_output(&mut self) -> Result<Vec<f32>, String> {
+        match self.render_job.take() {
+            Some(job) if job.is_done() => Ok(job.buffer),
+            Some(job) => {
+                self.render_job = Some(job);
+                Err("render: not finished".to_string())
+            }
+            None => Err("render: no render in progress".to_string()),
+        }
+    }
+
+    /// 元音声（planar, channels ch）をエンジンに読み込む。以降 render_source / render_region は
+    /// これを入力にして別バッファへ書き出すので、呼び出し側は元音声を持ち直さなくてよい。
+    /// レンダリングキャッシュは作り直しになる。
+    #[wasm_bindgen]
+    pub fn load_source(&mut self, input: &[f32], channels: usize) {
+        self.source = Some(SourceAudio::new(input, channels));
+        self.render_cache.0 = None;
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_source(&mut self) {
+        self.source = None;
+        self.render_cache.0 = None;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn has_source(&self) -> bool {
+        self.source.is_some()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn source_channels(&self) -> usize {
+        self.source.as_ref().map_or(0, |s| s.channels())
+    }
+
+    /// 元音声の長さ（1チャンネルあたりのサンプル数）
+    #[wasm_bindgen(getter)]
+    pub fn source_frames(&self) -> usize {
+        self.source.as_ref().map_or(0, |s| s.frames())
+    }
+
+    /// 読み込んだ元音声を現在のノート/設定でレンダリングし、結果（planar）を返す。
+    /// 元音声は変わらない。前回から変わった範囲だけを処理し直す（キャッシュ）。
+    #[wasm_bindgen]
+    pub fn render_source(&mut self) -> Result<Vec<f32>, String> {
+        self.refresh_render_cache()?;
+        Ok(self.render_cache.0.as_ref().map(|c| c.output().to_vec()).unwrap_or_default())
+    }
+
+    /// render_source の結果のうち [start, end) サンプルだけ（planar, チャンネルごとに end - start 個）。
+    #[wasm_bindgen]
+    pub fn render_region(&mut self, start: usize, end: usize) -> Result<Vec<f32>, String> {
+        self.refresh_render_cache()?;
+        Ok(self.render_cache.0.as_ref().map(|c| c.region(start, end)).unwrap_or_default())
+    }
+
+    /// 読み込んだ元音声（チャンネル平均）のピッチを hop_sec ごとに解析し、
+    /// プロジェクトのピッチ解析結果として保持する。フレーム数を返す。
+    #[wasm_bindgen]
+    pub fn analyze_pitch(&mut self, hop_sec: f32) -> Result<usize, String> {
+        let source = self.source.as_ref().ok_or("analysis: no source loaded (load_source)")?;
+        let mono = source.mono();
+        self.pitch_frames = analysis::detect_pitch(&mono, self.sample_rate, hop_sec);
+        Ok(self.pitch_frames.len())
+    }
+
+    /// ピッチ解析結果（analyze_pitch / set_pitch_analysis）からノートを切り出して set_notes する。
+    /// 各ノートの base_semitone は解析した音高の中央値（小数、A4 = 440 Hz の 12平均律）、他のパラメータは既定値。
+    /// ノート数を返す。
+    #[wasm_bindgen]
+    pub fn detect_notes(&mut self) -> usize {
+        // base_semitone は音律によらず 440 Hz 12平均律で持つ
+        let detected = analysis::detect_notes(&self.pitch_frames, &Tuning::default(), &NoteDetectionConfig::default());
+        let n = detected.len();
+        self.set_notes(
+            detected.iter().map(|d| d.start).collect(),
+            detected.iter().map(|d| d.end).collect(),
+            detected.iter().map(|d| d.midi).collect(),
+            vec![0.0; n],
+            vec![0.0; n],
+            vec![1.0; n],
+            vec![1.0; n],
+            vec![1.0; n],
+            vec![1.0; n],
+            vec![0.0; n],
+            0,
+            Vec::new(),
+        );
+        self.notes.len()
+    }
+
+    /// 部分再レンダリング用のキャッシュを作る。input（planar, channels ch）を元音声として読み込み、
+    /// 新しい処理状態で全体をレンダリングしておく。
+    #[wasm_bindgen]
+    pub fn build_render_cache(&mut self, input: &[f32], channels: usize) {
+        self.load_source(input, channels);
+        let _ = self.refresh_render_cache();
+    }
+
+    /// 前回から変わったノート（とその設定）の範囲だけを再レンダリングする。
+    /// 書き換えたサンプル範囲を [start0, end0, start1, end1, ...]（end は含まない）で返す。
+    #[wasm_bindgen]
+    pub fn update_render_cache(&mut self) -> Result<Vec<u32>, String> {
+        if self.render_cache.0.is_none() {
+            return Err("render cache: not built".to_string());
+        }
+        let ranges = self.refresh_render_cache()?;
+        Ok(ranges.into_iter().flat_map(|r| [r.start as u32, r.end as u32]).collect())
+    }
+
+    /// キャッシュのレンダリング結果（planar）全体。
+    #[wasm_bindgen]
+    pub fn cached_render(&self) -> Result<Vec<f32>, String> {
+        let cache = self.render_cache.0.as_ref().ok_or("render cache: not built")?;
+        Ok(cache.output().to_vec())
+    }
+
+    /// キャッシュのレンダリング結果のうち [start, end) サンプル（planar, チャンネルごとに end - start 個）。
+    #[wasm_bindgen]
+    pub fn cached_render_region(&self, start: usize, end: usize) -> Result<Vec<f32>, String> {
+        let cache = self.render_cache.0.as_ref().ok_or("render cache: not built")?;
+        Ok(cache.region(start, end))
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_render_cache(&mut self) {
+        self.render_cache.0 = None;
+    }
+
+    /// 補正後のノート列を Standard MIDI File（format 0, 1トラック）で書き出す。
+    /// ノート番号は現在の音律の鍵（import_midi_targets で読み戻すと同じ目標になる）。
+    /// - tempo_bpm: テンポ（秒 → tick の換算に使う）
+    /// - ticks_per_quarter: 分解能（例: 480）
+    /// - source: 元音声（モノラル）。ノート区間の音量をベロシティにする（空なら一定）
+    /// - bend_range_semitones: > 0 ならノート内のピッチカーブをピッチベンドで書き出す
+    #[wasm_bindgen]
+    pub fn export_midi(&self, tempo_bpm: f32, ticks_per_quarter: u16, source: &[f32], bend_range_semitones: f32) -> Vec<u8> {
+        let opts = MidiExportOptions {
+            tempo_bpm,
+            ticks_per_quarter,
+            channel: 0,
+            bend_range_semitones: (bend_range_semitones > 0.0).then_some(bend_range_semitones),
+        };
+        midi::write_smf(&self.notes, &self.tuning, source, self.sample_rate, &opts)
+    }
+
+    /// export_midi のベロシティに load_source した元音声（チャンネル平均）を使う版。
+    #[wasm_bindgen]
+    pub fn export_midi_source(&self, tempo_bpm: f32, ticks_per_quarter: u16, bend_range_semitones: f32) -> Result<Vec<u8>, String> {
+        let source = self.source.as_ref().ok_or("midi: no source loaded (load_source)")?;
+        Ok(self.export_midi(tempo_bpm, ticks_per_quarter, &source.mono(), bend_range_semitones))
+    }
+
+    /// Standard MIDI File を読み、歌のノートと開始時刻で対応付けて目標ピッチにする。
+    /// - tolerance_sec: 開始時刻の差がこれ以内なら対応させる
+    /// - midi_offset_sec: MIDI 側の時刻に足すずれ（秒）。テンポマップ適用後に足す
+    /// - strength_percent: 対応が付いたノートの補正量（0..100 %）
+    ///
+    /// 対応したノートの pitch_offset は 0 に戻す（目標ピッチにそのまま合わせるため）。
+    /// start/end は動かさない（レンダラは音声を時間方向に動かさないので、補正範囲だけずれてしまう）。
+    /// MIDI 側のタイミングはレポートの target_starts/target_ends で返す。
+    /// 対応表と、対応しなかった MIDI ノート/歌のノートを返す。
+    #[wasm_bindgen]
+    pub fn import_midi_targets(
+        &mut self,
+        smf: &[u8],
+        tolerance_sec: f32,
+        midi_offset_sec: f32,
+        strength_percent: f32,
+    ) -> Result<MidiAlignment, String> {
+        let midi_notes = midi::read_smf(smf)?;
+        let (pairs, report) = midi::align(&self.notes, &midi_notes, tolerance_sec, midi_offset_sec);
+
+        let strength = percent_to_strength(strength_percent);
+        for &(i, j) in pairs.iter() {
+            let m = &midi_notes[j];
+            let note = &mut self.notes[i];
+            note.target_midi = Some(m.key as f32);
+            note.correction_strength = strength;
+            note.pitch_offset = 0.0;
+        }
+        self.update_corrections();
+        Ok(report)
+    }
+
+    /// MIDI 読み込みなどで決めた目標ピッチを消して、キー/スケール補正に戻す。
+    #[wasm_bindgen]
+    pub fn clear_note_targets(&mut self) {
+        for note in self.notes.iter_mut() {
+            note.target_midi = None;
+        }
+        self.update_corrections();
+    }
+
+    /// エンジンと違うレートの音声を処理する。input（planar, channels ch）を
+    /// input_rate → エンジンのレートに変換して処理し、output_rate に変換して返す。
+    /// - quality: fast / medium / high / best
+    #[wasm_bindgen]
+    pub fn render_resampled(
+        &mut self,
+        input: &[f32],
+        channels: usize,
+        input_rate: f32,
+        output_rate: f32,
+        quality: &str,
+    ) -> Result<Vec<f32>, String> {
+        let q = ResampleQuality::from_name(quality).ok_or_else(|| format!("resample: unknown quality {quality:?}"))?;
+        let channels = channels.max(1);
+
+        let mut buf = resample::resample_planar(input, channels, input_rate, self.sample_rate, q);
+        self.process_planar(&mut buf, channels);
+        Ok(resample::resample_planar(&buf, channels, self.sample_rate, output_rate, q))
+    }
+
+    /// プロジェクトに記録する元音声の情報（音声データ自体は保存しない）。
+    #[wasm_bindgen]
+    pub fn set_audio_reference(&mut self, name: &str, sample_rate: f32, channels: u32, frames: u32) {
+        self.audio_ref = Some(AudioRef {
+            name: name.to_string(),
+            sample_rate,
+            channels,
+            frames: frames as u64,
+        });
+    }
+
+    /// プロジェクトに記録するピッチ解析結果。
+    /// - times: 秒
+    /// - f0s: Hz（NaN / 0 以下 = 無声）
+    /// - confidences: 0..1
+    #[wasm_bindgen]
+    pub fn set_pitch_analysis(&mut self, times: Vec<f32>, f0s: Vec<f32>, confidences: Vec<f32>) {
+        let n = times.len().min(f0s.len()).min(confidences.len());
+        self.pitch_frames = (0..n)
+            .filter(|&i| times[i].is_finite())
+            .map(|i| PitchFrame {
+                time: times[i],
+                f0: (f0s[i].is_finite() && f0s[i] > 0.0).then_some(f0s[i]),
+                confidence: if confidences[i].is_finite() { confidences[i].clamp(0.0, 1.0) } else { 0.0 },
+            })
+            .collect();
+    }
+
+    /// ノート・倍音 EQ・設定・解析結果をプロジェクト JSON（バージョン付き）で書き出す。
+    #[wasm_bindgen]
+    pub fn save_project(&self) -> String {
+        project::save(self)
+    }
+
+    /// save_project の JSON（古い版や UI の NoteTrack JSON も可）を読み込んで状態を置き換える。
+    /// load_source した元音声はそのまま残る（レンダリングキャッシュは作り直しになる）。
+    /// 失敗した場合は何も変えない。
+    #[wasm_bindgen]
+    pub fn load_project(&mut self, json: &str) -> Result<(), String> {
+        project::load(self, json)
+    }
+
+    /// エンジンが保持しているノート列（検証・クランプ・並べ替え後、補正量込み）。
+    #[wasm_bindgen]
+    pub fn note_list(&self) -> NoteList {
+        NoteList::from_notes(&self.notes)
+    }
+
+    /// 倍音 EQ（線形、0..4 にクランプ済み。未設定なら空）
+    #[wasm_bindgen(getter)]
+    pub fn harmonic_gains(&self) -> Vec<f32> {
+        self.harmonic_eq.gains.clone()
+    }
+
+    /// set_correction_strength の値（0..100 %）
+    #[wasm_bindgen(getter)]
+    pub fn correction_strength(&self) -> f32 {
+        self.default_correction_strength * 100.0
+    }
+
+    /// キーのルート（0..12）。キー未設定なら undefined
+    #[wasm_bindgen(getter)]
+    pub fn key_root_pitch_class(&self) -> Option<f32> {
+        self.scale.as_ref().map(|s| s.root_pc())
+    }
+
+    /// キーの度数列（ルートからの半音、正規化済み）。キー未設定なら空
+    #[wasm_bindgen(getter)]
+    pub fn key_steps(&self) -> Vec<f32> {
+        self.scale.as_ref().map(|s| s.steps().to_vec()).unwrap_or_default()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn just_intonation_enabled(&self) -> bool {
+        self.just_enabled
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn just_tonic_pitch_class(&self) -> f32 {
+        self.just_intonation.tonic_pc()
+    }
+
+    /// コード区間（開始時刻順、不正な区間は除外済み）
+    #[wasm_bindgen(getter)]
+    pub fn chord_region_starts(&self) -> Vec<f32> {
+        self.just_intonation.regions().map(|(s, _, _)| s).collect()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn chord_region_ends(&self) -> Vec<f32> {
+        self.just_intonation.regions().map(|(_, e, _)| e).collect()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn chord_region_roots(&self) -> Vec<f32> {
+        self.just_intonation.regions().map(|(_, _, r)| r).collect()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn double_seed(&self) -> u32 {
+        self.double_seed as u32
+    }
+
+    /// 現在の音律の1周期あたりの鍵数（12平均律なら 12）
+    #[wasm_bindgen(getter)]
+    pub fn keys_per_period(&self) -> u32 {
+        self.tuning.keys_per_period().max(0) as u32
+    }
+
+    /// .scl / .kbm を読み込んでいるか
+    #[wasm_bindgen(getter)]
+    pub fn has_scala_scale(&self) -> bool {
+        self.tuning.scl_source().is_some()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn has_scala_keyboard_map(&self) -> bool {
+        self.tuning.kbm_source().is_some()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// 出力の遅れ（サンプル）。常に 0。
+    /// process_* / render_* はノート内で入力をシフタの遅れぶん先読みして補償するので、
+    /// 出力は入力（とノートの時刻）にサンプル単位で揃っている（ホスト側で補償し直さないこと）。
+    /// 遅れのあるストリーミング処理は MelodyShifter / LiveTuner の latency_samples を見る。
+    #[wasm_bindgen(getter)]
+    pub fn latency_samples(&self) -> usize {
+        0
+    }
+
+    /// 先読みするぶん（シフタの遅れ、サンプル）
+    pub(crate) fn shifter_latency(&self) -> usize {
+        self.channels[0].shifter.latency_samples()
+    }
+
+    /// シフタのディレイの読み出しの補間方式を名前で設定する（既定は linear）。
+    /// - name: linear / hermite（cubic）/ lagrange / sinc
+    ///
+    /// 処理状態はリセットされる。未知の名前なら何も変えずに false を返す。
+    #[wasm_bindgen]
+    pub fn set_interpolation(&mut self, name: &str) -> bool {
+        match Interpolation::from_name(name) {
+            Some(interpolation) => {
+                self.interpolation = interpolation;
+                self.reset_processing_state();
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn interpolation(&self) -> String {
+        self.interpolation.name().to_string()
+    }
+}
+
+impl MelodyEngine {
+    /// 同じ長さのチャンネル群を、共通のピッチ補正カーブで処理する（リンク処理）。
+    /// シフタ/フィルタの状態はチャンネルごとに持つ。
+    /// planar（1チャンネル frames サンプル）を先頭から全部処理する。
+    fn process_channels(&mut self, planar: &mut [f32], frames: usize) {
+        let mut cursor = RenderCursor::default();
+        self.process_segments(planar, frames, 0, &mut cursor, usize::MAX);
+    }
+
+    /// cursor の位置から、until サンプルに達するまで区間単位で処理して cursor を進める。
+    /// 区間の区切りは全体の長さとノートだけで決まるので、途中で止めて続きから呼んでも
+    /// 一括で処理した場合とビット単位で同じ結果になる。
+    ///
+    /// planar はチャンネルごとに len サンプルずつ並んだ、全体のうち origin サンプル目からの部分
+    /// （cursor / until は全体での位置）。スライスの配列を作らずに直接区切るので確保しない。
+    ///
+    /// ノートの外（バイパス）からノートに入るときはシフタの読み出し位相を 0 に戻す。
+    /// バイパス中の出力は位相によらないので聞こえ方は変わらず、
+    /// 離れたノート同士が互いの処理結果に依存しなくなる（部分再レンダリングの前提）。
+    ///
+    /// シフタには出力位置よりシフタの遅れ（shifter_latency）ぶん先の入力を入れる（planes の外は無音）。
+    /// 位相 0 のシフタはちょうどその遅れぶん前を読むので、シフトした区間も入力と時間が揃い、
+    /// バイパス区間（入力そのまま）との境目でずれない。先の入力はまだ処理していない位置なので in-place でよい。
+    fn process_segments(
+        &mut self,
+        planar: &mut [f32],
+        len: usize,
+        origin: usize,
+        cursor: &mut RenderCursor,
+        until: usize,
+    ) {
+        if len == 0 || planar.len() < len {
+            return;
+        }
+        let channel_count = planar.len() / len;
+        let planar = &mut planar[..channel_count * len];
+        let frames = origin + len;
+        if self.notes.is_empty() {
+            cursor.sample_idx = frames;
+            return; // 全バイパス
+        }
+
+        let sr = self.sample_rate;
+        if !sr.is_finite() || sr <= 0.0 {
+            cursor.sample_idx = frames;
+            return;
+        }
+
+        while self.channels.len() < channel_count {
+            self.channels.push(ChannelState::new(sr, self.interpolation));
+        }
+        let latency = self.shifter_latency();
+        if cursor.sample_idx <= origin {
+            // 出力の先頭より前に、先読みぶんの入力をディレイに入れておく
+            for (plane, state) in planar.chunks(len).zip(self.channels.iter_mut()) {
+                for chunk in plane[..latency.min(len)].chunks(BLOCK_SAMPLES) {
+                    let ahead = &mut state.lookahead[..chunk.len()];
+                    ahead.copy_from_slice(chunk);
+                    state.shifter.process(ahead, 0.0, true);
+                }
+            }
+        }
+
+        // 区間ごとに処理：ノート境界で slice を切り替える
+        let mut sample_idx = cursor.sample_idx.max(origin);
+        let mut note_idx = cursor.note_idx;
+        let mut in_note = cursor.in_note;
+
+        while sample_idx < frames && sample_idx < until {
+            let t = (sample_idx as f32) / sr;
+
+            // t より前のノートを前進して捨てる
+            while note_idx < self.notes.len() && t >= self.notes[note_idx].end {
+                note_idx += 1;
+            }
+
+            // 現在時刻がノート内かどうか
+            let (offset, next_boundary_time, active_note_idx) = if note_idx < self.notes.len() {
+                let note = &self.notes[note_idx];
+                if t >= note.start && t < note.end {
+                    // ブロック内は一定オフセットとして近似
+                    let block_end_sample = (sample_idx + BLOCK_SAMPLES).min(frames);
+                    let block_end_time = (block_end_sample as f32) / sr;
+                    let next_time = note.end.min(block_end_time);
+
+                    let mid_sample = sample_idx as f32 + ((block_end_sample - sample_idx) as f32) * 0.5;
+                    let t_mid = mid_sample / sr;
+
+                    (note.shift_at(t_mid), next_time, Some(note_idx))
+                } else {
+                    // 次のノート開始までバイパス（こちらも大きすぎないように固定ブロックで刻む）
+                    let block_end_sample = (sample_idx + BLOCK_SAMPLES).min(frames);
+                    let block_end_time = (block_end_sample as f32) / sr;
+                    (0.0, note.start.min(block_end_time), None)
+                }
+            } else {
+                // 以降はノートなし
+                let block_end_sample = (sample_idx + BLOCK_SAMPLES).min(frames);
+                (0.0, (block_end_sample as f32) / sr, None)
+            };
+
+            let mut end_sample = (next_boundary_time * sr).ceil() as isize;
+            if end_sample < 0 {
+                end_sample = 0;
+            }
+            let end_sample = (end_sample as usize).min(frames);
+            let end_sample = end_sample.max(sample_idx + 1);
+
+            let entering_note = active_note_idx.is_some() && !in_note;
+            in_note = active_note_idx.is_some();
+            for (plane, state) in planar.chunks_mut(len).zip(self.channels.iter_mut()) {
+                if entering_note {
+                    state.shifter.delay_pos = 0.0;
+                }
+                let mut pos = sample_idx - origin;
+                while pos < end_sample - origin {
+                    let n = BLOCK_SAMPLES.min(end_sample - origin - pos);
+                    let ahead = &mut state.lookahead[..n];
+                    for (i, a) in ahead.iter_mut().enumerate() {
+                        *a = plane.get(pos + latency + i).copied().unwrap_or(0.0);
+                    }
+                    // バイパスなら plane はそのまま（入力）
+                    if state.shifter.process(ahead, offset, true) {
+                        plane[pos..pos + n].copy_from_slice(ahead);
+                    }
+                    pos += n;
+                }
+                let slice = &mut plane[sample_idx - origin..end_sample - origin];
+
+                // Apply simple timbre shaping (harmonics + formant) for this note block.
+                if let Some(nidx) = active_note_idx {
+                    // note changes => reset state to avoid carrying filter memories across notes
+                    if state.timbre_active_note_idx != Some(nidx) {
+                        state.reset_timbre();
+                        state.timbre_active_note_idx = Some(nidx);
+                    }
+
+                    let note = &self.notes[nidx];
+                    apply_harmonic_and_formant_stateful(
+                        slice,
+                        sr,
+                        &self.harmonic_eq,
+                        note,
+                        &mut state.timbre_filters,
+                        &mut state.timbre_lp,
+                        &mut state.timbre_last_f0,
+                    );
+                }
+            }
+
+            sample_idx = end_sample;
+        }
+
+        cursor.sample_idx = sample_idx;
+        cursor.note_idx = note_idx;
+        cursor.in_note = in_note;
+    }
+
+    /// 計測ピッチ（base_semitone）から最寄りのスケール音までの差に strength を掛けて
+    /// 各ノートの correction_offset を作り直す。
+    /// 目標ピッチ（target_midi）が決まっているノートはスケールよりそちらを優先する。
+    ///
+    /// 計測ピッチを Hz に戻し、現在の音律での鍵（小数）にしてからスケール音に合わせる。
+    /// 差はその鍵の周波数との比から求める（shifter に渡すのは物理的な半音）。
+    /// 1周期が12鍵でない音律ではキー/スケールは使わず、最寄りの鍵に合わせる。
+    ///
+    /// 純正律が有効なら、目標の半音（スケール音 or base + pitch_offset）に対する
+    /// セント単位のずれに strength を掛けて just_offset に入れる（12鍵の音律のみ）。
+    fn update_corrections(&mut self) {
+        let twelve_keys = self.tuning.keys_per_period() == 12;
+        for note in self.notes.iter_mut() {
+            let from_hz = equal_440_hz(note.base_semitone);
+            let key = self.tuning.hz_to_midi(from_hz);
+            let mut target_key = key + note.pitch_offset;
+
+            let target = match (note.target_midi, &self.scale) {
+                (Some(target), _) => Some(target),
+                (None, Some(scale)) => {
+                    let target = if twelve_keys { scale.nearest(key) } else { key.round() };
+                    Some(self.tuning.nearest_mapped_key(target))
+                }
+                (None, None) => None,
+            };
+
+            note.correction_offset = match target {
+                Some(target) => {
+                    target_key = target + note.pitch_offset;
+
+                    let to_hz = self.tuning.midi_to_hz(target);
+                    let semis = 12.0 * (to_hz / from_hz).log2();
+                    if semis.is_finite() {
+                        semis * note.correction_strength
+                    } else {
+                        0.0
+                    }
+                }
+                None => 0.0,
+            };
+
+            note.just_offset = if self.just_enabled && twelve_keys {
+                let t_mid = 0.5 * (note.start + note.end);
+                self.just_intonation.offset_semitones(target_key, t_mid) * note.correction_strength
+            } else {
+                0.0
+            };
+        }
+    }
+
+    fn push_harmony_voice(
+        &mut self,
+        rule: IntervalRule,
+        formant_shift: f32,
+        humanize_ms: f32,
+        gain: f32,
+        seed: u32,
+    ) -> usize {
+        self.harmony_voices.push(HarmonyVoice {
+            rule,
+            formant_shift: if formant_shift.is_finite() { formant_shift } else { 0.0 },
+            humanize_ms: if humanize_ms.is_finite() { humanize_ms } else { 0.0 },
+            gain: if gain.is_finite() { gain } else { 1.0 },
+            seed: seed as u64,
+        });
+        self.harmony_voices.len() - 1
+    }
+
+    fn reset_timbre_state(&mut self) {
+        for state in self.channels.iter_mut() {
+            state.reset_timbre();
+        }
+    }
+
+    /// 元音声からキャッシュを作る（作ってあれば変わった範囲だけ更新する）。
+    fn refresh_render_cache(&mut self) -> Result<Vec<std::ops::Range<usize>>, String> {
+        if let Some(mut cache) = self.render_cache.0.take() {
+            let ranges = cache.update(self);
+            self.render_cache.0 = Some(cache);
+            return Ok(ranges);
+        }
+        let source = self.source.clone().ok_or("render: no source loaded (load_source)")?;
+        let cache = RenderCache::build(self, source);
+        let frames = cache.frames();
+        self.render_cache.0 = Some(cache);
+        Ok(std::iter::once(0..frames).collect())
+    }
+
+    /// シフタ/音色フィルタの内部状態をまっさらにする（設定とノートは保持）。
+    fn reset_processing_state(&mut self) {
+        // set_max_buffer で用意したチャンネル数は保つ
+        let count = self.channels.len().max(1);
+        self.channels = (0..count).map(|_| ChannelState::new(self.sample_rate, self.interpolation)).collect();
+    }
+
+    fn on_tuning_changed(&mut self) {
+        self.update_corrections();
+    }
+}
+
+fn deinterleave(interleaved: &[f32], planar: &mut [f32], channels: usize) {
+    let frames = interleaved.len() / channels;
+    for (i, frame) in interleaved.chunks_exact(channels).enumerate() {
+        for (ch, &x) in frame.iter().enumerate() {
+            planar[ch * frames + i] = x;
+        }
+    }
+}
+
+fn interleave(planar: &[f32], interleaved: &mut [f32], channels: usize) {
+    let frames = interleaved.len() / channels;
+    for (i, frame) in interleaved.chunks_exact_mut(channels).enumerate() {
+        for (ch, x) in frame.iter_mut().enumerate() {
+            *x = planar[ch * frames + i];
+        }
+    }
+}
+
+fn percent_to_strength(percent: f32) -> f32 {
+    if percent.is_finite() {
+        (percent / 100.0).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_harmonic_and_formant_stateful(
+    input: &mut [f32],
+    sr: f32,
+    global_eq: &HarmonicEQ,
+    note: &NoteSpan,
+    filters: &mut FilterBank,
+    lp_state: &mut f32,
+    last_f0: &mut f32,
+) {
+    if input.is_empty() {
+        return;
+    }
+    if !sr.is_finite() || sr <= 0.0 {
+        return;
+    }
+
+    // --- Harmonic EQ (very rough): filter bank around n*f0, then mix back.
+    // Use absolute pitch (base + center) as f0 reference.
+    let f0_midi = note.base_semitone + note.pitch_center_offset;
+    let f0 = equal_440_hz(f0_midi);
+    if !f0.is_finite() || f0 <= 0.0 {
+        return;
+    }
+
+    // If f0 changed a lot, rebuild filters (and reset their states).
+    let rel_change = if (*last_f0).is_finite() && *last_f0 > 0.0 {
+        ((f0 - *last_f0) / *last_f0).abs()
+    } else {
+        1.0
+    };
+
+    let nyq = sr * 0.5;
+    let n_harm = global_eq.gains.len().max(note.harmonic_profile.len()).min(MAX_HARMONIC_FILTERS);
+
+    // Ensure filters match current f0/harmonic count.
+    let desired = (1..=n_harm)
+        .map(|h| f0 * (h as f32))
+        .take_while(|&f| f.is_finite() && f < nyq * 0.98)
+        .count();
+
+    let need_rebuild = filters.len() != desired || rel_change > 0.08;
+    if need_rebuild {
+        filters.rebuild(sr, f0, desired, 12.0);
+        *last_f0 = f0;
+    }
+
+    // Keep it subtle: this is not a true EQ, it's a rough "timbre feel".
+    let mix = 0.25_f32;
+    let n_filt = filters.len().max(1) as f32;
+
+    filters.set_gains(|h| {
+        let g_global = global_eq.gain(h);
+        let g_note = note.harmonic_profile.get(h).copied().unwrap_or(1.0);
+        // Cap per-harmonic gain to avoid blowing up when summing many harmonics.
+        (g_global * g_note).clamp(0.0, 2.0)
+    });
+
+    for sample in input.iter_mut() {
+        let x = *sample;
+        // Normalize the summed band outputs.
+        let acc = filters.process(x) / n_filt;
+        *sample = x * (1.0 - mix) + acc * mix;
+    }
+    // Simple limiter/soft clip to prevent hard digital clipping.
+    simd::soft_clip(input);
+
+    apply_formant_tilt(input, sr, note.formant_shift, lp_state);
+}
+
+/// Formant shift (very rough): spectral tilt using 1-pole lowpass split.
+/// Positive formant_shift => brighter; negative => darker.
+fn apply_formant_tilt(input: &mut [f32], sr: f32, formant_shift: f32, lp_state: &mut f32) {
+    let s = formant_shift;
     if s.is_finite() && s.abs() > 1.0e-3 {
         let tilt = (2.0_f32).powf(s / 12.0);
-        let gain_hi = tilt.powf(0.5).max(0.5).min(2.0);
-        let gain_lo = (1.0 / tilt).powf(0.5).max(0.5).min(2.0);
+        let gain_hi = tilt.powf(0.5).clamp(0.5, 2.0);
+        let gain_lo = (1.0 / tilt).powf(0.5).clamp(0.5, 2.0);
 
+        let nyq = sr * 0.5;
         let fc = 900.0_f32.min(nyq * 0.9).max(80.0);
         let a = (-2.0 * PI * fc / sr).exp();
-        for i in 0..input.len() {
-            let x = input[i];
+        for sample in input.iter_mut() {
+            let x = *sample;
             *lp_state = a * (*lp_state) + (1.0 - a) * x;
             let low = *lp_state;
             let high = x - low;
             let mut y = low * gain_lo + high * gain_hi;
             y = y.tanh();
-            input[i] = y.max(-1.0).min(1.0);
+            *sample = y.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48000.0;
+
+    /// base_semitone（440 Hz 12平均律）の値を持つノートを [start, end) 秒に並べる。
+    fn set_simple_notes(engine: &mut MelodyEngine, notes: &[(f32, f32, f32)]) {
+        let n = notes.len();
+        engine.set_notes(
+            notes.iter().map(|n| n.0).collect(),
+            notes.iter().map(|n| n.1).collect(),
+            notes.iter().map(|n| n.2).collect(),
+            vec![0.0; n],
+            vec![0.0; n],
+            vec![1.0; n],
+            vec![1.0; n],
+            vec![1.0; n],
+            vec![1.0; n],
+            vec![0.0; n],
+            0,
+            Vec::new(),
+        );
+    }
+
+    fn hz_to_base(hz: f32) -> f32 {
+        69.0 + 12.0 * (hz / 440.0).log2()
+    }
+
+    #[test]
+    fn note_at_reference_pitch_stays_put() {
+        let mut engine = MelodyEngine::new(SR);
+        set_simple_notes(&mut engine, &[(0.0, 1.0, hz_to_base(415.0))]);
+        engine.set_key(9.0, "minor");
+        engine.set_correction_strength(100.0);
+
+        // A4 = 440 では 415 Hz は G#4 付近。A マイナーにない音なので G4 へ寄る
+        let offset = engine.notes[0].correction_offset;
+        assert!((offset - 12.0 * (392.0_f32 / 415.0).log2()).abs() < 0.01, "{offset}");
+
+        // A4 = 415 では 415 Hz がちょうど A4 なので動かない
+        engine.set_reference_pitch(415.0);
+        let offset = engine.notes[0].correction_offset;
+        assert!(offset.abs() < 1.0e-3, "{offset}");
+
+        // 少しずれた音は 415 基準の A4 に合う
+        set_simple_notes(&mut engine, &[(0.0, 1.0, hz_to_base(420.0))]);
+        let offset = engine.notes[0].correction_offset;
+        assert!((offset - 12.0 * (415.0_f32 / 420.0).log2()).abs() < 1.0e-3, "{offset}");
+    }
+
+    #[test]
+    fn just_intonation_follows_correction_strength() {
+        let mut engine = MelodyEngine::new(SR);
+        // E4: 純正の長3度は平均律より約 14 セント低い
+        set_simple_notes(&mut engine, &[(0.0, 1.0, 64.0)]);
+        engine.set_key(0.0, "major");
+        engine.set_just_intonation(0.0);
+
+        engine.set_correction_strength(0.0);
+        assert_eq!(engine.notes[0].just_offset, 0.0);
+
+        engine.set_correction_strength(100.0);
+        let full = engine.notes[0].just_offset;
+        assert!((full + 0.137).abs() < 0.01, "{full}");
+
+        engine.set_correction_strength(50.0);
+        assert!((engine.notes[0].just_offset - full * 0.5).abs() < 1.0e-6);
+    }
+
+    /// MIDI ノート (start, end, key) だけの SMF を作る。
+    fn smf_of(notes: &[(f32, f32, f32)]) -> Vec<u8> {
+        let mut engine = MelodyEngine::new(SR);
+        set_simple_notes(&mut engine, notes);
+        engine.export_midi(120.0, 960, &[], 0.0)
+    }
+
+    #[test]
+    fn imported_target_ignores_previous_pitch_offset() {
+        let smf = smf_of(&[(0.0, 0.5, 62.0)]);
+        let mut engine = MelodyEngine::new(SR);
+        set_simple_notes(&mut engine, &[(0.0, 0.4, 60.3)]);
+        engine.notes[0].pitch_offset = 2.0;
+
+        let report = engine.import_midi_targets(&smf, 0.1, 0.0, 100.0).unwrap();
+        assert_eq!(report.matched_notes(), vec![0]);
+        let note = &engine.notes[0];
+        assert_eq!(note.pitch_offset, 0.0);
+        let sounding = note.base_semitone + note.static_shift();
+        assert!((sounding - 62.0).abs() < 1.0e-4, "{sounding}");
+    }
+
+    #[test]
+    fn midi_timing_is_reported_but_not_applied() {
+        let smf = smf_of(&[(0.05, 0.55, 62.0), (1.0, 1.5, 67.0)]);
+        let sung = [(0.0, 0.4, 60.0), (1.02, 1.3, 65.0)];
+
+        let mut engine = MelodyEngine::new(SR);
+        set_simple_notes(&mut engine, &sung);
+        let report = engine.import_midi_targets(&smf, 0.1, 0.02, 100.0).unwrap();
+        assert_eq!(report.matched_notes(), vec![0, 1]);
+        for (note, &(start, end, _)) in engine.notes.iter().zip(&sung) {
+            assert_eq!((note.start, note.end), (start, end));
+            assert_eq!((note.time_stretch_start, note.time_stretch_end), (1.0, 1.0));
+        }
+        let expected = [(0.07, 0.57), (1.02, 1.52)];
+        for ((&start, &end), &(s, e)) in report.target_starts().iter().zip(&report.target_ends()).zip(&expected) {
+            assert!((start - s).abs() < 1.0e-3 && (end - e).abs() < 1.0e-3, "{start} {end}");
         }
     }
+
+    #[test]
+    fn midi_targets_correct_the_sung_samples() {
+        // MIDI が歌より遅れていても、補正は歌った区間にかかる
+        let smf = smf_of(&[(0.25, 0.65, 62.0)]);
+        let mut engine = MelodyEngine::new(SR);
+        let base = hz_to_base(261.63);
+        set_simple_notes(&mut engine, &[(0.1, 0.5, base)]);
+        engine.import_midi_targets(&smf, 0.2, 0.0, 100.0).unwrap();
+
+        let frames = (0.7 * SR) as usize;
+        let input: Vec<f32> = (0..frames).map(|i| 0.5 * (std::f32::consts::TAU * 261.63 * i as f32 / SR).sin()).collect();
+        engine.load_source(&input, 1);
+        let output = engine.render_source().unwrap();
+        let zero_crossing_hz = |from: f32, to: f32| {
+            let seg = &output[(from * SR) as usize..(to * SR) as usize];
+            let crossings = seg.windows(2).filter(|w| w[0] <= 0.0 && w[1] > 0.0).count();
+            crossings as f32 / (to - from)
+        };
+        // 歌の区間の中ほどは D4、MIDI だけの区間（歌の後）は元の C4 のまま
+        let sung = zero_crossing_hz(0.2, 0.45);
+        assert!((sung - 293.66).abs() < 8.0, "{sung}");
+        let after = zero_crossing_hz(0.56, 0.68);
+        assert!((after - 261.63).abs() < 12.0, "{after}");
+    }
+
+    /// 分割レンダリングのテスト用: 補正がかかるノートと、その下の planar 入力
+    fn render_fixture(channels: usize) -> (MelodyEngine, Vec<f32>) {
+        let mut engine = MelodyEngine::new(SR);
+        set_simple_notes(&mut engine, &[(0.05, 0.3, 60.4), (0.31, 0.5, 63.7), (0.52, 0.6, 66.2)]);
+        engine.set_key(0.0, "major");
+        engine.set_correction_strength(100.0);
+        // BLOCK_SAMPLES の倍数にならない長さ
+        let frames = (0.65 * SR) as usize + 37;
+        let mut input = Vec::with_capacity(frames * channels);
+        for ch in 0..channels {
+            let hz = 261.6 * (1.0 + 0.01 * ch as f32);
+            input.extend((0..frames).map(|i| (std::f32::consts::TAU * hz * i as f32 / SR).sin() * 0.5));
+        }
+        (engine, input)
+    }
+
+    fn assert_same_bits(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        if let Some(i) = a.iter().zip(b).position(|(x, y)| x.to_bits() != y.to_bits()) {
+            panic!("differs at {i}: {} vs {}", a[i], b[i]);
+        }
+    }
+
+    #[test]
+    fn chunked_render_matches_process_planar() {
+        for channels in [1, 2] {
+            let (mut engine, input) = render_fixture(channels);
+            let mut expected = input.clone();
+            engine.process_planar(&mut expected, channels);
+            let frames = input.len() / channels;
+
+            for max_frames in [1, 100, BLOCK_SAMPLES + 1, 1000, 4097, frames + 1000] {
+                let (mut engine, input) = render_fixture(channels);
+                engine.start_render(&input, channels);
+                assert_eq!(engine.render_progress(), 0.0);
+                assert!(engine.render_in_progress());
+
+                let mut last = 0.0;
+                while !engine.render_step(max_frames) {
+                    let progress = engine.render_progress();
+                    assert!(progress > last && progress < 1.0, "{progress} after {last}");
+                    last = progress;
+                    assert_eq!(
+                        engine.take_render_output().unwrap_err(),
+                        "render: not finished",
+                        "max_frames {max_frames}"
+                    );
+                }
+                assert_eq!(engine.render_progress(), 1.0);
+                assert!(!engine.render_in_progress());
+                // 終わったあとの render_step は何もしない
+                assert!(engine.render_step(max_frames));
+
+                let output = engine.take_render_output().unwrap();
+                assert_same_bits(&output, &expected);
+                assert_eq!(engine.render_progress(), 0.0);
+                assert_eq!(engine.take_render_output().unwrap_err(), "render: no render in progress");
+            }
+        }
+    }
+
+    #[test]
+    fn cancel_render_discards_the_job_and_state() {
+        let channels = 2;
+        let (mut fresh, input) = render_fixture(channels);
+        let mut expected = input.clone();
+        fresh.process_planar(&mut expected, channels);
+
+        let cancelled = |input: &[f32]| {
+            let (mut engine, _) = render_fixture(channels);
+            engine.start_render(input, channels);
+            assert!(!engine.render_step(input.len() / channels / 2));
+            let progress = engine.render_progress();
+            assert!(progress > 0.0 && progress < 1.0, "{progress}");
+
+            engine.cancel_render();
+            assert!(!engine.render_in_progress());
+            assert_eq!(engine.render_progress(), 0.0);
+            assert!(engine.render_step(1));
+            assert_eq!(engine.take_render_output().unwrap_err(), "render: no render in progress");
+            engine
+        };
+
+        // 途中までの状態が残らない
+        let mut engine = cancelled(&input);
+        let mut output = input.clone();
+        engine.process_planar(&mut output, channels);
+        assert_same_bits(&output, &expected);
+
+        // やり直したレンダリングも一括処理と同じ
+        let mut engine = cancelled(&input);
+        engine.start_render(&input, channels);
+        while !engine.render_step(777) {}
+        assert_same_bits(&engine.take_render_output().unwrap(), &expected);
+    }
+
+    #[test]
+    fn project_load_keeps_the_loaded_source() {
+        let (mut engine, input) = render_fixture(2);
+        engine.load_source(&input, 2);
+        let before = engine.render_source().unwrap();
+        let json = engine.save_project();
+
+        engine.set_correction_strength(0.0);
+        engine.load_project(&json).unwrap();
+        assert!(engine.has_source());
+        assert_eq!((engine.source_channels(), engine.source_frames()), (2, input.len() / 2));
+        assert!(engine.render_cache.0.is_none());
+        assert_same_bits(&engine.render_source().unwrap(), &before);
+    }
+
+    #[test]
+    fn non_finite_voice_settings_survive_a_project_round_trip() {
+        let mut engine = MelodyEngine::new(SR);
+        set_simple_notes(&mut engine, &[(0.0, 0.5, 60.0), (0.6, 1.0, 62.0)]);
+        engine.add_diatonic_harmony_voice(2, f32::NAN, f32::NAN, f32::INFINITY, 3);
+        engine.add_custom_harmony_voice(vec![64.0, f32::NAN], 0.0, 5.0, f32::NAN, 4);
+        engine.add_double_copy(f32::NAN, f32::NAN, f32::NAN, f32::NAN, f32::NAN, f32::NAN);
+        let json = engine.save_project();
+
+        let mut loaded = MelodyEngine::new(SR);
+        loaded.load_project(&json).unwrap();
+        let v = &loaded.harmony_voices;
+        assert_eq!((v[0].formant_shift, v[0].humanize_ms, v[0].gain), (0.0, 0.0, 1.0));
+        assert_eq!((v[1].humanize_ms, v[1].gain), (5.0, 1.0));
+        // 歌わないノートの NaN はそのまま戻る
+        let IntervalRule::Custom(targets) = &v[1].rule else { panic!("custom rule expected") };
+        assert_eq!(targets[0], 64.0);
+        assert!(targets[1].is_nan());
+        let c = &loaded.double_copies[0];
+        assert_eq!((c.detune_cents, c.delay_ms, c.pitch_variation_cents), (0.0, 0.0, 0.0));
+        assert_eq!((c.formant_variation, c.pan, c.gain), (0.0, 0.0, 1.0));
+        assert_eq!(loaded.save_project(), json);
+    }
+
+    #[test]
+    fn v0_note_tracks_are_migrated() {
+        let track = r#"{
+            "sampleRate": 44100, "duration": 2.0,
+            "notes": [
+                { "startTime": 0.1, "endTime": 0.5, "baseSemitone": 60.2, "pitchOffset": 1.0, "enabled": true },
+                { "startTime": 0.5, "endTime": 0.6, "baseSemitone": 61.0, "enabled": false },
+                { "startTime": 0.7, "endTime": 0.9, "baseSemitone": 62.0, "formantShift": -2.0 }
+            ]
+        }"#;
+        let mut engine = MelodyEngine::new(SR);
+        engine.load_project(track).unwrap();
+
+        let notes = &engine.notes;
+        assert_eq!(notes.iter().map(|n| n.index).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!((notes[0].start, notes[0].end, notes[0].base_semitone), (0.1, 0.5, 60.2));
+        assert_eq!((notes[0].pitch_offset, notes[0].pitch_mod_amount), (1.0, 1.0));
+        assert_eq!(notes[1].formant_shift, -2.0);
+        // v0 には補正がなかったので、移行後も補正しない
+        assert!(notes.iter().all(|n| n.correction_strength == 0.0 && n.target_midi.is_none()));
+        let audio = engine.audio_ref.as_ref().unwrap();
+        assert_eq!((audio.sample_rate, audio.channels, audio.frames), (44100.0, 1, 88200));
+        assert!(engine.save_project().starts_with(r#"{"version":1,"#));
+    }
+
+    #[test]
+    fn unknown_project_versions_are_rejected() {
+        let mut engine = MelodyEngine::new(SR);
+        set_simple_notes(&mut engine, &[(0.0, 0.5, 60.0)]);
+        let current = engine.save_project();
+
+        let newer = current.replacen(r#""version":1"#, r#""version":2"#, 1);
+        let err = engine.load_project(&newer).unwrap_err();
+        assert!(err.contains("newer than supported"), "{err}");
+        let err = engine.load_project(&current.replacen(r#""version":1"#, r#""version":-1"#, 1)).unwrap_err();
+        assert!(err.contains("invalid version"), "{err}");
+        // 失敗してもエンジンは変わらない
+        assert_eq!(engine.notes.len(), 1);
+        assert_eq!(engine.save_project(), current);
+    }
+
+    #[test]
+    fn source_variants_use_the_channel_average() {
+        let (mut engine, input) = render_fixture(2);
+        engine.add_diatonic_harmony_voice(2, 0.0, 10.0, 1.0, 1);
+        engine.add_double_copy(8.0, 20.0, 5.0, 0.2, 0.5, 0.7);
+        assert!(engine.render_harmony_voice_source(0).is_err());
+        assert!(engine.render_doubles_source().is_err());
+        assert!(engine.export_midi_source(120.0, 480, 0.0).is_err());
+
+        engine.load_source(&input, 2);
+        let frames = input.len() / 2;
+        let mono: Vec<f32> = (0..frames).map(|i| (input[i] + input[frames + i]) / 2.0).collect();
+        assert_eq!(engine.render_harmony_voice_source(0).unwrap(), engine.render_harmony_voice(0, &mono).unwrap());
+        assert_eq!(engine.render_doubles_source().unwrap(), engine.render_doubles(&mono));
+        assert_eq!(engine.export_midi_source(120.0, 480, 2.0).unwrap(), engine.export_midi(120.0, 480, &mono, 2.0));
+    }
+
+    #[test]
+    fn only_streaming_processors_report_latency() {
+        let (mut engine, input) = render_fixture(1);
+        let delay = MelodyShifter::new(SR).latency_samples();
+        assert!(delay > 0);
+        assert_eq!(LiveTuner::new(SR, 2).latency_samples(), delay);
+
+        // オフラインの処理は先読みで補償済み: 遅れは 0 で、ノートの外はそのまま
+        assert_eq!(engine.latency_samples(), 0);
+        assert_eq!(engine.shifter_latency(), delay);
+        let mut output = input.clone();
+        engine.process_buffer(&mut output);
+        let note_start = (0.05 * SR) as usize;
+        assert_same_bits(&output[..note_start], &input[..note_start]);
+    }
+
+    #[test]
+    fn impulses_stay_aligned_with_the_input() {
+        let (start, end) = ((0.1 * SR) as usize, (0.5 * SR) as usize);
+        let latency = MelodyShifter::new(SR).latency_samples();
+        let render = |shift: f32, click: usize| {
+            let mut engine = MelodyEngine::new(SR);
+            set_simple_notes(&mut engine, &[(0.1, 0.5, 60.0)]);
+            // 揺れ/ドリフトなしの一定シフト
+            engine.notes[0].pitch_offset = shift;
+            engine.notes[0].pitch_mod_amount = 0.0;
+            engine.notes[0].pitch_drift_amount = 0.0;
+            let mut input = vec![0.0_f32; (0.6 * SR) as usize];
+            input[click] = 1.0;
+
+            let mut planar = input.clone();
+            engine.process_planar(&mut planar, 1);
+            engine.load_source(&input, 1);
+            assert_same_bits(&engine.render_source().unwrap(), &planar);
+            planar
+        };
+        let loudest = |out: &[f32]| (0..out.len()).max_by(|&a, &b| out[a].abs().total_cmp(&out[b].abs())).unwrap();
+
+        // ノート頭の子音（アタック）は、シフトしてもしなくても入力と同じサンプルに出る
+        for shift in [0.0, 3.0, -5.0, 12.0] {
+            let click = start + 10;
+            let out = render(shift, click);
+            assert!(loudest(&out).abs_diff(click) <= 1, "shift {shift}: {}", loudest(&out));
+            assert!(out[..start].iter().all(|&x| x == 0.0), "shift {shift}");
+        }
+
+        // ノートの途中では 2 タップの読み出し位置しだいで前後にずれるが、
+        // シフタの遅れぶん先読みしているので ±遅れの範囲に収まる（遅れたままにはならない）
+        for shift in [3.0, -5.0, 12.0] {
+            let click = (0.3 * SR) as usize;
+            let out = render(shift, click);
+            let heard: Vec<usize> = (0..out.len()).filter(|&i| out[i].abs() > 1.0e-4).collect();
+            let (first, last) = (heard[0], heard[heard.len() - 1]);
+            assert!(first >= click - latency && last <= click + latency, "shift {shift}: {first}..{last}");
+            assert!(first >= start && last < end);
+        }
+        // シフトなしならシフタは素通しで、途中でもずれない
+        let click = (0.3 * SR) as usize;
+        let out = render(0.0, click);
+        assert_eq!((0..out.len()).filter(|&i| out[i] != 0.0).collect::<Vec<_>>(), vec![click]);
+    }
+
+    #[test]
+    fn grouped_render_matches_process_planar() {
+        // ノートの間にすき間があるので 3 グループに分かれる（parallel feature ならスレッドに分かれる）
+        let (mut engine, input) = render_fixture(2);
+        let mut expected = input.clone();
+        engine.process_planar(&mut expected, 2);
+
+        let (mut engine, input) = render_fixture(2);
+        engine.load_source(&input, 2);
+        assert_same_bits(&engine.render_source().unwrap(), &expected);
+    }
+
+    #[test]
+    fn updated_cache_matches_a_full_render() {
+        let (mut engine, input) = render_fixture(2);
+        engine.build_render_cache(&input, 2);
+        let before = engine.cached_render().unwrap();
+        let frames = input.len() / 2;
+
+        // 2 つ目のノートだけ音を変える
+        engine.notes[1].pitch_offset = 1.5;
+        let ranges = engine.update_render_cache().unwrap();
+        assert_eq!(ranges.len(), 2, "{ranges:?}");
+        let (start, end) = (ranges[0] as usize, ranges[1] as usize);
+        assert!(start > (0.3 * SR) as usize && start <= (0.31 * SR) as usize, "{start}");
+        assert!(end >= (0.5 * SR) as usize && end < (0.52 * SR) as usize, "{end}");
+
+        let updated = engine.cached_render().unwrap();
+        let mut full = engine.clone();
+        full.clear_render_cache();
+        assert_same_bits(&updated, &full.render_source().unwrap());
+        // 範囲外は前回のまま
+        for ch in 0..2 {
+            let outside = |buf: &[f32]| {
+                let plane = &buf[ch * frames..(ch + 1) * frames];
+                [plane[..start].to_vec(), plane[end..].to_vec()].concat()
+            };
+            assert_same_bits(&outside(&updated), &outside(&before));
+        }
+
+        // 何も変えなければ何も作り直さない
+        assert!(engine.update_render_cache().unwrap().is_empty());
+    }
+
+    #[test]
+    fn correction_strength_scales_the_offset_to_the_scale_degree() {
+        let mut engine = MelodyEngine::new(SR);
+        set_simple_notes(&mut engine, &[(0.0, 0.5, 63.3), (0.5, 1.0, 60.2)]);
+        assert_eq!(engine.notes[0].correction_offset, 0.0);
+
+        engine.set_key(2.0, "dorian");
+        engine.set_correction_strength(80.0);
+        assert!((engine.notes[0].correction_offset - 0.8 * 0.7).abs() < 1.0e-3);
+        assert!((engine.notes[1].correction_offset + 0.8 * 0.2).abs() < 1.0e-3);
+
+        engine.set_custom_scale(2.0, vec![0.0, 7.0]);
+        assert!((engine.notes[1].correction_offset - 0.8 * 1.8).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn interleaved_matches_planar() {
+        for channels in [2, 3] {
+            let (mut engine, planar_in) = render_fixture(channels);
+            let frames = planar_in.len() / channels;
+            let mut planar = planar_in.clone();
+            engine.process_planar(&mut planar, channels);
+
+            let (mut engine, _) = render_fixture(channels);
+            let mut interleaved = vec![0.0; planar_in.len()];
+            interleave(&planar_in, &mut interleaved, channels);
+            engine.process_interleaved(&mut interleaved, channels);
+
+            let mut back = vec![0.0; planar.len()];
+            deinterleave(&interleaved, &mut back, channels);
+            assert_same_bits(&back, &planar);
+            // 補正がかかっている
+            assert_ne!(&planar[..frames], &planar_in[..frames]);
+        }
+    }
+
+    #[test]
+    fn linked_channels_stay_identical() {
+        let (mut engine, mono) = render_fixture(1);
+        let mut stereo = [mono.clone(), mono.clone()].concat();
+        engine.process_planar(&mut stereo, 2);
+        let frames = mono.len();
+        assert_same_bits(&stereo[..frames], &stereo[frames..]);
+
+        let (mut engine, _) = render_fixture(1);
+        let mut single = mono.clone();
+        engine.process_buffer(&mut single);
+        assert_same_bits(&stereo[..frames], &single);
+    }
+
+    #[test]
+    fn midi_export_round_trips_through_read_smf() {
+        let mut engine = MelodyEngine::new(SR);
+        set_simple_notes(&mut engine, &[(0.1, 0.4, 60.3), (0.5, 0.9, 63.8), (1.0, 1.25, 67.0)]);
+        engine.set_key(0.0, "major");
+        engine.set_correction_strength(100.0);
+
+        for bend in [0.0, 2.0] {
+            let smf = engine.export_midi(120.0, 480, &[], bend);
+            let notes = midi::read_smf(&smf).unwrap();
+            let keys: Vec<u8> = notes.iter().map(|n| n.key).collect();
+            assert_eq!(keys, vec![60, 64, 67]);
+            // 120 BPM, 480 tick = 1/960 秒単位
+            for (m, n) in notes.iter().zip(engine.notes.iter()) {
+                assert!((m.start - n.start).abs() <= 0.5 / 960.0 + 1.0e-6, "{} {}", m.start, n.start);
+                assert!((m.end - n.end).abs() <= 0.5 / 960.0 + 1.0e-6, "{} {}", m.end, n.end);
+            }
+            // ピッチベンド（と感度の RPN）はベンドを有効にしたときだけ
+            assert_eq!(smf.windows(2).any(|w| w == [0xB0, 101]), bend > 0.0);
+        }
+    }
+
+    #[test]
+    fn midi_export_and_import_use_the_same_keys() {
+        // A4 = 432 Hz で歌った A4 は、書き出すと 69、読み戻すと補正なし
+        let mut engine = MelodyEngine::new(SR);
+        engine.set_reference_pitch(432.0);
+        set_simple_notes(&mut engine, &[(0.0, 0.5, hz_to_base(432.0)), (0.6, 1.0, hz_to_base(432.0 * 1.5))]);
+        let smf = engine.export_midi(120.0, 480, &[], 0.0);
+        let keys: Vec<u8> = midi::read_smf(&smf).unwrap().iter().map(|n| n.key).collect();
+        assert_eq!(keys, vec![69, 76]);
+
+        engine.import_midi_targets(&smf, 0.05, 0.0, 100.0).unwrap();
+        assert_eq!(engine.notes[0].target_midi, Some(69.0));
+        assert!(engine.notes[0].correction_offset.abs() < 1.0e-3, "{}", engine.notes[0].correction_offset);
+        // 純正の5度は平均律の 76 より 2 セント高いので、わずかに下げるだけ
+        assert!((engine.notes[1].correction_offset + 0.0196).abs() < 1.0e-3, "{}", engine.notes[1].correction_offset);
+    }
 }
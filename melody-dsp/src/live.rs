@@ -0,0 +1,363 @@
+//! リアルタイム用の自動ピッチ補正（ストリーミング処理）。
+//!
+//! ノート列は使わず、入力（全チャンネルの平均）の f0 を YIN で追いかけて最寄りのスケール音へ寄せる。
+//! 目標への追従は retune speed（時定数）で滑らかにし、補正量は全チャンネル共通（リンク）。
+//! シフタは常にディレイを通して鳴らすので出力は一定の遅れ（latency_samples）を持ち、
+//! ドライ音も同じだけ遅らせてからミックスする。
+//!
+//! 状態はすべて new で確保し、process_* はチャンネル数が増えたとき以外は確保を行わない。
+
+use wasm_bindgen::prelude::*;
+
+use crate::analysis::{yin_frame, yin_lags};
+use crate::scale::Scale;
+use crate::tuning::Tuning;
+use crate::{apply_formant_tilt, MelodyShifter};
+
+// 補正量を更新する単位（サンプル）
+const MAX_BLOCK: usize = 64;
+// ピッチ解析の間隔（秒）
+const HOP_SEC: f32 = 0.01;
+// これより小さい補正（半音）はシフタを通さずドライ音（遅延済み）を使う
+const BYPASS_SEMITONES: f32 = 1.0e-3;
+
+/// 1 チャンネル分の状態。
+#[derive(Clone)]
+struct LiveChannel {
+    shifter: MelodyShifter,
+    // dry signal delayed by the shifter latency (ring buffer)
+    dry_line: Vec<f32>,
+    dry_pos: usize,
+    formant_lp: f32,
+    // whether the previous block bypassed the shifter
+    bypassed: bool,
+    dry: [f32; MAX_BLOCK],
+    wet: [f32; MAX_BLOCK],
+}
+
+impl LiveChannel {
+    fn new(sample_rate: f32, latency: usize) -> Self {
+        Self {
+            shifter: MelodyShifter::new(sample_rate),
+            dry_line: vec![0.0; latency.max(1)],
+            dry_pos: 0,
+            formant_lp: 0.0,
+            bypassed: true,
+            dry: [0.0; MAX_BLOCK],
+            wet: [0.0; MAX_BLOCK],
+        }
+    }
+
+    /// block（MAX_BLOCK 以下）を in-place で処理する。
+    fn process(&mut self, block: &mut [f32], shift: f32, formant_shift: f32, mix: f32, sr: f32) {
+        let n = block.len();
+        let latency = self.dry_line.len();
+        for (d, &x) in self.dry[..n].iter_mut().zip(block.iter()) {
+            *d = self.dry_line[self.dry_pos];
+            self.dry_line[self.dry_pos] = x;
+            self.dry_pos += 1;
+            if self.dry_pos >= latency {
+                self.dry_pos = 0;
+            }
+        }
+
+        let wet = &mut self.wet[..n];
+        wet.copy_from_slice(block);
+        // ディレイには常に書き込む（バイパス中も）
+        self.shifter.process(wet, shift, false);
+        let bypass = shift.abs() < BYPASS_SEMITONES;
+        if bypass {
+            if self.bypassed {
+                wet.copy_from_slice(&self.dry[..n]);
+            } else {
+                // シフタの出力からドライ音へ、このブロックでつなぐ
+                for (i, (w, &d)) in wet.iter_mut().zip(self.dry[..n].iter()).enumerate() {
+                    let t = (i + 1) as f32 / n as f32;
+                    *w = *w * (1.0 - t) + d * t;
+                }
+            }
+            // 位相 0 ではシフタの出力はちょうど latency 遅れのドライ音になるので、次に補正を始めても途切れない
+            self.shifter.delay_pos = 0.0;
+        }
+        self.bypassed = bypass;
+
+        apply_formant_tilt(wet, sr, formant_shift, &mut self.formant_lp);
+        for ((y, &d), &w) in block.iter_mut().zip(self.dry[..n].iter()).zip(wet.iter()) {
+            *y = d * (1.0 - mix) + w * mix;
+        }
+    }
+}
+
+/// ストリーミングの自動ピッチ補正。
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct LiveTuner {
+    sample_rate: f32,
+    tuning: Tuning,
+    scale: Scale,
+    // time constant of the glide to the target (ms), 0 = immediate
+    retune_ms: f32,
+    // semitones (spectral tilt, see apply_formant_tilt)
+    formant_shift: f32,
+    // 0..1 (wet)
+    mix: f32,
+    latency: usize,
+    channels: Vec<LiveChannel>,
+
+    // channel average of the current block
+    mono: [f32; MAX_BLOCK],
+    // pitch tracking on the channel average: the newest window + max_lag samples
+    history: Vec<f32>,
+    diff: Vec<f32>,
+    yin_window: usize,
+    yin_min_lag: usize,
+    yin_max_lag: usize,
+    hop: usize,
+    since_hop: usize,
+
+    // MIDI, NaN while unvoiced
+    detected_midi: f32,
+    // semitones
+    target_shift: f32,
+    shift: f32,
+}
+
+#[wasm_bindgen]
+impl LiveTuner {
+    /// channels ぶんの状態を先に確保する（既定は C chromatic、即時補正、ミックス 100 %）。
+    #[wasm_bindgen(constructor)]
+    pub fn new(sample_rate: f32, channels: usize) -> LiveTuner {
+        let latency = MelodyShifter::new(sample_rate).latency_samples();
+        let (yin_window, yin_min_lag, yin_max_lag) = yin_lags(sample_rate);
+        let hop = if sample_rate.is_finite() && sample_rate > 0.0 {
+            ((HOP_SEC * sample_rate).round() as usize).max(1)
+        } else {
+            1
+        };
+        LiveTuner {
+            sample_rate,
+            tuning: Tuning::default(),
+            scale: Scale::from_name(0.0, "chromatic").unwrap_or_else(|| Scale::custom(0.0, Vec::new())),
+            retune_ms: 0.0,
+            formant_shift: 0.0,
+            mix: 1.0,
+            latency,
+            channels: (0..channels.max(1)).map(|_| LiveChannel::new(sample_rate, latency)).collect(),
+            mono: [0.0; MAX_BLOCK],
+            history: vec![0.0; yin_window + yin_max_lag],
+            diff: vec![0.0; yin_max_lag + 1],
+            yin_window,
+            yin_min_lag,
+            yin_max_lag,
+            hop,
+            since_hop: 0,
+            detected_midi: f32::NAN,
+            target_shift: 0.0,
+            shift: 0.0,
+        }
+    }
+
+    /// キー/スケールを名前で設定する（MelodyEngine::set_key と同じ名前）。未知の名前なら false。
+    #[wasm_bindgen]
+    pub fn set_key(&mut self, root_pitch_class: f32, scale_name: &str) -> bool {
+        match Scale::from_name(root_pitch_class, scale_name) {
+            Some(scale) => {
+                self.scale = scale;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 前もって作っておいたスケールを root_pitch_class に移して設定する（オーディオスレッド用）。
+    /// 初期のクロマチック（12 音）以下の音数なら確保しない。
+    #[cfg(feature = "clap")]
+    pub(crate) fn set_scale(&mut self, root_pitch_class: f32, scale: &Scale) {
+        self.scale.assign(root_pitch_class, scale);
+    }
+
+    /// 任意の度数列（ルートからの半音, 0..12）でスケールを設定する。
+    #[wasm_bindgen]
+    pub fn set_custom_scale(&mut self, root_pitch_class: f32, steps: Vec<f32>) {
+        self.scale = Scale::custom(root_pitch_class, steps);
+    }
+
+    #[wasm_bindgen]
+    pub fn set_reference_pitch(&mut self, hz: f32) {
+        self.tuning.set_reference_hz(hz);
+    }
+
+    /// 目標ピッチへの追従の速さ（時定数 ms、0 = 即座）
+    #[wasm_bindgen]
+    pub fn set_retune_speed(&mut self, ms: f32) {
+        self.retune_ms = if ms.is_finite() { ms.clamp(0.0, 2000.0) } else { 0.0 };
+    }
+
+    /// 半音（+ で明るく、- で暗く）
+    #[wasm_bindgen]
+    pub fn set_formant_shift(&mut self, semitones: f32) {
+        self.formant_shift = if semitones.is_finite() { semitones.clamp(-24.0, 24.0) } else { 0.0 };
+    }
+
+    /// 補正後の音の割合（0..100 %）
+    #[wasm_bindgen]
+    pub fn set_mix(&mut self, percent: f32) {
+        self.mix = if percent.is_finite() { (percent / 100.0).clamp(0.0, 1.0) } else { 1.0 };
+    }
+
+    /// 出力の遅れ（サンプル）。ドライ音にも同じ遅れが入る。
+    #[wasm_bindgen(getter)]
+    pub fn latency_samples(&self) -> usize {
+        self.latency
+    }
+
+    /// 直近に検出したピッチ（MIDI）。無声なら NaN
+    #[wasm_bindgen(getter)]
+    pub fn detected_midi(&self) -> f32 {
+        self.detected_midi
+    }
+
+    /// いま掛けている補正（半音）
+    #[wasm_bindgen(getter)]
+    pub fn current_shift(&self) -> f32 {
+        self.shift
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// 処理状態（ディレイ・ピッチ追跡）を消す。設定は残す。
+    #[wasm_bindgen]
+    pub fn reset(&mut self) {
+        let (sr, latency) = (self.sample_rate, self.latency);
+        for ch in self.channels.iter_mut() {
+            *ch = LiveChannel::new(sr, latency);
+        }
+        self.history.fill(0.0);
+        self.since_hop = 0;
+        self.detected_midi = f32::NAN;
+        self.target_shift = 0.0;
+        self.shift = 0.0;
+    }
+
+    /// モノラルのバッファを in-place で処理する。
+    #[wasm_bindgen]
+    pub fn process_buffer(&mut self, input: &mut [f32]) {
+        self.process_channels(&mut [input]);
+    }
+
+    /// planar（[ch0..., ch1..., ...]）の多チャンネルバッファを in-place で処理する。
+    #[wasm_bindgen]
+    pub fn process_planar(&mut self, input: &mut [f32], channels: usize) {
+        let channels = channels.max(1);
+        let frames = input.len() / channels;
+        if frames == 0 {
+            return;
+        }
+        self.ensure_channels(channels);
+
+        let mut pos = 0;
+        while pos < frames {
+            let n = self.block_len(frames - pos);
+            let scale = 1.0 / channels as f32;
+            for (i, m) in self.mono[..n].iter_mut().enumerate() {
+                *m = (0..channels).map(|c| input[c * frames + pos + i]).sum::<f32>() * scale;
+            }
+            self.advance(n);
+            for (c, ch) in self.channels[..channels].iter_mut().enumerate() {
+                let block = &mut input[c * frames + pos..c * frames + pos + n];
+                ch.process(block, self.shift, self.formant_shift, self.mix, self.sample_rate);
+            }
+            pos += n;
+        }
+    }
+}
+
+impl LiveTuner {
+    /// 同じ長さのチャンネル群を in-place で処理する（ホストのチャンネルごとのバッファ用）。
+    pub(crate) fn process_channels(&mut self, planes: &mut [&mut [f32]]) {
+        let len = planes.iter().map(|p| p.len()).min().unwrap_or(0);
+        if len == 0 {
+            return;
+        }
+        self.ensure_channels(planes.len());
+
+        let mut pos = 0;
+        while pos < len {
+            let n = self.block_len(len - pos);
+            let scale = 1.0 / planes.len() as f32;
+            for (i, m) in self.mono[..n].iter_mut().enumerate() {
+                *m = planes.iter().map(|p| p[pos + i]).sum::<f32>() * scale;
+            }
+            self.advance(n);
+            for (plane, ch) in planes.iter_mut().zip(self.channels.iter_mut()) {
+                ch.process(&mut plane[pos..pos + n], self.shift, self.formant_shift, self.mix, self.sample_rate);
+            }
+            pos += n;
+        }
+    }
+
+    fn ensure_channels(&mut self, channels: usize) {
+        while self.channels.len() < channels {
+            self.channels.push(LiveChannel::new(self.sample_rate, self.latency));
+        }
+    }
+
+    /// 残り remaining サンプルのうち次に処理する長さ（MAX_BLOCK 以下、解析の hop をまたがない）。
+    fn block_len(&self, remaining: usize) -> usize {
+        MAX_BLOCK.min(remaining).min(self.hop - self.since_hop)
+    }
+
+    /// mono に入れた n サンプルを解析に回し、補正量を進める。
+    fn advance(&mut self, n: usize) {
+        self.track(n);
+        self.glide(n);
+    }
+
+    /// mono の n サンプルを解析用の履歴に足し、hop ごとに f0 を求めて目標の補正量を決める。
+    fn track(&mut self, n: usize) {
+        let keep = self.history.len() - n;
+        self.history.copy_within(n.., 0);
+        self.history[keep..].copy_from_slice(&self.mono[..n]);
+
+        self.since_hop += n;
+        if self.since_hop < self.hop {
+            return;
+        }
+        self.since_hop = 0;
+
+        let found = yin_frame(
+            &self.history,
+            0,
+            self.yin_window,
+            self.yin_min_lag,
+            self.yin_max_lag,
+            self.sample_rate,
+            &mut self.diff,
+        );
+        let Some((f0, _confidence)) = found else {
+            // 無声の間は補正を外していく
+            self.detected_midi = f32::NAN;
+            self.target_shift = 0.0;
+            return;
+        };
+        let midi = self.tuning.hz_to_midi(f0);
+        let target_hz = self.tuning.midi_to_hz(self.scale.nearest(midi));
+        let shift = 12.0 * (target_hz / f0).log2();
+        self.detected_midi = midi;
+        self.target_shift = if shift.is_finite() { shift } else { 0.0 };
+    }
+
+    /// n サンプルぶん、補正量を目標へ近づける。
+    fn glide(&mut self, n: usize) {
+        let tau = self.retune_ms * 0.001 * self.sample_rate;
+        if tau <= 1.0 {
+            self.shift = self.target_shift;
+        } else {
+            let k = 1.0 - (-(n as f32) / tau).exp();
+            self.shift += (self.target_shift - self.shift) * k;
+        }
+    }
+}
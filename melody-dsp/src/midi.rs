@@ -0,0 +1,540 @@
+//! Standard MIDI File（SMF）の読み書き。
+//!
+//! 書き出し：補正後のノート列を format 0 の1トラックにする。
+//! - テンポは一定（秒 → tick の変換に使う）
+//! - ベロシティは元音声のノート区間の音量から決める
+//! - ピッチベンドを有効にすると、ノート内の細かいピッチカーブ（揺れ/ドリフト/ランプ）を載せる
+//! - ノート番号は現在の音律の鍵（import_midi_targets の目標ノートと同じ単位）。
+//!   A4 = 432 Hz で歌った A4 は 69 になる
+//!
+//! 読み込み：format 0/1 のノートをテンポマップ込みで秒に直し、歌のノートと時間で対応付ける。
+
+use wasm_bindgen::prelude::*;
+
+use crate::tuning::{equal_440_hz, Tuning};
+use crate::NoteSpan;
+
+// ピッチベンドを打つ間隔
+const BEND_INTERVAL_SEC: f32 = 0.01;
+// 音量 → ベロシティの対応（dBFS）
+const VELOCITY_FLOOR_DB: f32 = -60.0;
+const DEFAULT_VELOCITY: u8 = 100;
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MidiExportOptions {
+    pub(crate) tempo_bpm: f32,
+    pub(crate) ticks_per_quarter: u16,
+    pub(crate) channel: u8,
+    // None = ピッチベンドなし
+    pub(crate) bend_range_semitones: Option<f32>,
+}
+
+struct Event {
+    tick: u64,
+    // 同じ tick 内での並び順（note off → 設定 → bend → note on）
+    order: u8,
+    bytes: Vec<u8>,
+}
+
+fn seconds_to_ticks(t: f32, opts: &MidiExportOptions) -> u64 {
+    let beats = (t.max(0.0) as f64) * (opts.tempo_bpm as f64) / 60.0;
+    (beats * opts.ticks_per_quarter as f64).round() as u64
+}
+
+/// source（元音声）のノート区間 RMS からベロシティを決める。source が空なら固定値。
+fn note_velocity(note: &NoteSpan, source: &[f32], sample_rate: f32) -> u8 {
+    let s = ((note.start * sample_rate) as usize).min(source.len());
+    let e = ((note.end * sample_rate) as usize).min(source.len());
+    if e <= s {
+        return DEFAULT_VELOCITY;
+    }
+    let mean_sq = source[s..e].iter().map(|x| x * x).sum::<f32>() / (e - s) as f32;
+    let db = 10.0 * mean_sq.max(1.0e-12).log10();
+    let u = ((db - VELOCITY_FLOOR_DB) / -VELOCITY_FLOOR_DB).clamp(0.0, 1.0);
+    (1.0 + u * 126.0).round() as u8
+}
+
+fn bend_value(semitones: f32, range: f32) -> u16 {
+    let v = 8192.0 + (semitones / range) * 8192.0;
+    if v.is_finite() {
+        v.round().clamp(0.0, 16383.0) as u16
+    } else {
+        8192
+    }
+}
+
+fn write_vlq(out: &mut Vec<u8>, mut v: u64) {
+    let mut buf = [0u8; 10];
+    let mut n = 0;
+    loop {
+        buf[n] = (v & 0x7F) as u8;
+        n += 1;
+        v >>= 7;
+        if v == 0 {
+            break;
+        }
+    }
+    for i in (0..n).rev() {
+        out.push(if i > 0 { buf[i] | 0x80 } else { buf[i] });
+    }
+}
+
+pub(crate) fn write_smf(
+    notes: &[NoteSpan],
+    tuning: &Tuning,
+    source: &[f32],
+    sample_rate: f32,
+    opts: &MidiExportOptions,
+) -> Vec<u8> {
+    let ch = opts.channel & 0x0F;
+    let bend_range = opts
+        .bend_range_semitones
+        .filter(|r| r.is_finite() && *r > 0.0)
+        .map(|r| r.min(24.0));
+
+    let bpm = if opts.tempo_bpm.is_finite() && opts.tempo_bpm > 0.0 { opts.tempo_bpm } else { 120.0 };
+    let opts = &MidiExportOptions {
+        tempo_bpm: bpm,
+        ticks_per_quarter: opts.ticks_per_quarter.max(1),
+        ..*opts
+    };
+
+    let mut events: Vec<Event> = Vec::new();
+
+    // tempo (µs / quarter)
+    let us_per_quarter = (60_000_000.0 / bpm as f64).round() as u32;
+    let t = us_per_quarter.to_be_bytes();
+    events.push(Event {
+        tick: 0,
+        order: 1,
+        bytes: vec![0xFF, 0x51, 0x03, t[1], t[2], t[3]],
+    });
+
+    if let Some(range) = bend_range {
+        // RPN 0,0 = pitch bend sensitivity
+        let semis = range.floor() as u8;
+        let cents = ((range - range.floor()) * 100.0).round() as u8;
+        for (cc, v) in [(101u8, 0u8), (100, 0), (6, semis), (38, cents), (101, 127), (100, 127)] {
+            events.push(Event {
+                tick: 0,
+                order: 1,
+                bytes: vec![0xB0 | ch, cc, v],
+            });
+        }
+    }
+
+    // ノートの音高（440 Hz 12平均律）→ 現在の音律の鍵
+    let to_key = |semitone: f32| tuning.hz_to_midi(equal_440_hz(semitone));
+
+    for note in notes.iter() {
+        let target = to_key(note.base_semitone + note.static_shift());
+        if !target.is_finite() {
+            continue;
+        }
+        let key = target.round().clamp(0.0, 127.0);
+        let on = seconds_to_ticks(note.start, opts);
+        let off = seconds_to_ticks(note.end, opts).max(on + 1);
+        let velocity = note_velocity(note, source, sample_rate);
+
+        if let Some(range) = bend_range {
+            let mut last: Option<u16> = None;
+            let mut tt = note.start;
+            while tt < note.end {
+                let value = bend_value(to_key(note.base_semitone + note.shift_at(tt)) - key, range);
+                if last != Some(value) {
+                    let [lsb, msb] = [(value & 0x7F) as u8, (value >> 7) as u8];
+                    events.push(Event {
+                        tick: seconds_to_ticks(tt, opts).max(on),
+                        order: 2,
+                        bytes: vec![0xE0 | ch, lsb, msb],
+                    });
+                    last = Some(value);
+                }
+                tt += BEND_INTERVAL_SEC;
+            }
+        }
+
+        events.push(Event {
+            tick: on,
+            order: 3,
+            bytes: vec![0x90 | ch, key as u8, velocity.max(1)],
+        });
+        events.push(Event {
+            tick: off,
+            order: 0,
+            bytes: vec![0x80 | ch, key as u8, 0],
+        });
+    }
+
+    if bend_range.is_some() {
+        // 最後にセンターへ戻す
+        let end = events.iter().map(|e| e.tick).max().unwrap_or(0);
+        events.push(Event {
+            tick: end,
+            order: 1,
+            bytes: vec![0xE0 | ch, 0x00, 0x40],
+        });
+    }
+
+    events.sort_by_key(|e| (e.tick, e.order));
+
+    let mut track = Vec::new();
+    let mut prev = 0u64;
+    for e in events.iter() {
+        write_vlq(&mut track, e.tick - prev);
+        track.extend_from_slice(&e.bytes);
+        prev = e.tick;
+    }
+    // end of track
+    track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+    let mut out = Vec::with_capacity(22 + track.len());
+    out.extend_from_slice(b"MThd");
+    out.extend_from_slice(&6u32.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes()); // format 0
+    out.extend_from_slice(&1u16.to_be_bytes()); // 1 track
+    out.extend_from_slice(&opts.ticks_per_quarter.to_be_bytes());
+    out.extend_from_slice(b"MTrk");
+    out.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    out.extend_from_slice(&track);
+    out
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MidiNote {
+    // seconds
+    pub(crate) start: f32,
+    pub(crate) end: f32,
+    pub(crate) key: u8,
+}
+
+struct SmfReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SmfReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        match self.pos.checked_add(n).filter(|&e| e <= self.bytes.len()) {
+            Some(end) => {
+                let out = &self.bytes[self.pos..end];
+                self.pos = end;
+                Ok(out)
+            }
+            None => Err("midi: unexpected end of data".to_string()),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn vlq(&mut self) -> Result<u64, String> {
+        let mut v = 0u64;
+        for _ in 0..8 {
+            let b = self.u8()?;
+            v = (v << 7) | (b & 0x7F) as u64;
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err("midi: variable-length quantity too long".to_string())
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+}
+
+/// tick 単位のノート（テンポマップ適用前）
+struct TickNote {
+    on: u64,
+    off: u64,
+    key: u8,
+}
+
+fn read_track(data: &[u8], notes: &mut Vec<TickNote>, tempos: &mut Vec<(u64, u32)>) -> Result<(), String> {
+    let mut r = SmfReader { bytes: data, pos: 0 };
+    let mut tick = 0u64;
+    let mut running: Option<u8> = None;
+    // (channel, key) ごとの鳴っているノート（開始 tick、FIFO）
+    let mut open: Vec<(u8, u8, u64)> = Vec::new();
+
+    while r.remaining() > 0 {
+        tick += r.vlq()?;
+        let mut status = r.u8()?;
+        let first_data = if status < 0x80 {
+            // running status
+            let s = running.ok_or("midi: data byte without status")?;
+            let d = status;
+            status = s;
+            Some(d)
+        } else {
+            None
+        };
+
+        match status {
+            0xFF => {
+                let kind = r.u8()?;
+                let len = r.vlq()? as usize;
+                let body = r.take(len)?;
+                match kind {
+                    0x51 if len >= 3 => {
+                        let us = u32::from_be_bytes([0, body[0], body[1], body[2]]);
+                        tempos.push((tick, us));
+                    }
+                    0x2F => break,
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                let len = r.vlq()? as usize;
+                r.take(len)?;
+            }
+            0x80..=0xEF => {
+                running = Some(status);
+                let d1 = match first_data {
+                    Some(d) => d,
+                    None => r.u8()?,
+                };
+                let kind = status & 0xF0;
+                let ch = status & 0x0F;
+                // program change / channel pressure は1バイト
+                let d2 = if kind == 0xC0 || kind == 0xD0 { 0 } else { r.u8()? };
+
+                let is_on = kind == 0x90 && d2 > 0;
+                let is_off = kind == 0x80 || (kind == 0x90 && d2 == 0);
+                if is_on {
+                    open.push((ch, d1, tick));
+                } else if is_off {
+                    if let Some(i) = open.iter().position(|&(c, k, _)| c == ch && k == d1) {
+                        let (_, key, on) = open.remove(i);
+                        notes.push(TickNote { on, off: tick, key });
+                    }
+                }
+            }
+            _ => return Err(format!("midi: unexpected status byte {status:#04x}")),
+        }
+    }
+
+    // 閉じていないノートはトラック末尾で切る
+    for (_, key, on) in open {
+        notes.push(TickNote { on, off: tick.max(on), key });
+    }
+    Ok(())
+}
+
+/// SMF を読み、全トラックのノートを秒単位で返す（開始時刻順）。
+pub(crate) fn read_smf(bytes: &[u8]) -> Result<Vec<MidiNote>, String> {
+    let mut r = SmfReader { bytes, pos: 0 };
+    if r.take(4)? != b"MThd" {
+        return Err("midi: not a Standard MIDI File".to_string());
+    }
+    let header_len = r.u32()? as usize;
+    let header = r.take(header_len)?;
+    if header.len() < 6 {
+        return Err("midi: short header".to_string());
+    }
+    let format = u16::from_be_bytes([header[0], header[1]]);
+    if format > 1 {
+        return Err(format!("midi: format {format} is not supported"));
+    }
+    let division = u16::from_be_bytes([header[4], header[5]]);
+
+    let mut tick_notes = Vec::new();
+    let mut tempos: Vec<(u64, u32)> = Vec::new();
+    while r.remaining() >= 8 {
+        let id = r.take(4)?;
+        let len = r.u32()? as usize;
+        let body = r.take(len.min(r.remaining()))?;
+        if id == b"MTrk" {
+            read_track(body, &mut tick_notes, &mut tempos)?;
+        }
+    }
+
+    let to_sec = tick_converter(division, tempos);
+    let mut notes: Vec<MidiNote> = tick_notes
+        .iter()
+        .map(|n| MidiNote {
+            start: to_sec(n.on) as f32,
+            end: to_sec(n.off) as f32,
+            key: n.key,
+        })
+        .collect();
+    notes.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(notes)
+}
+
+/// tick → 秒。division の最上位ビットが立っていれば SMPTE（テンポ無関係）。
+fn tick_converter(division: u16, mut tempos: Vec<(u64, u32)>) -> impl Fn(u64) -> f64 {
+    let smpte = if division & 0x8000 != 0 {
+        let fps = -((division >> 8) as u8 as i8) as f64;
+        let fps = if fps == 29.0 { 29.97 } else { fps };
+        let per_frame = (division & 0xFF).max(1) as f64;
+        Some(fps * per_frame)
+    } else {
+        None
+    };
+    let tpq = (division & 0x7FFF).max(1) as f64;
+
+    tempos.sort_by_key(|&(t, _)| t);
+    if tempos.first().map(|&(t, _)| t) != Some(0) {
+        tempos.insert(0, (0, 500_000)); // 既定 120 BPM
+    }
+    // 各テンポ変更点の開始秒を前計算
+    let mut segments: Vec<(u64, f64, f64)> = Vec::with_capacity(tempos.len());
+    let mut sec = 0.0;
+    for (i, &(t, us)) in tempos.iter().enumerate() {
+        if i > 0 {
+            let (pt, _, pspt) = segments[i - 1];
+            sec += (t - pt) as f64 * pspt;
+        }
+        segments.push((t, sec, us as f64 / 1.0e6 / tpq));
+    }
+
+    move |tick: u64| match smpte {
+        Some(ticks_per_sec) => tick as f64 / ticks_per_sec,
+        None => {
+            let i = segments.partition_point(|&(t, _, _)| t <= tick).saturating_sub(1);
+            let (t, s, spt) = segments[i];
+            s + (tick - t) as f64 * spt
+        }
+    }
+}
+
+/// 歌のノートと MIDI ノートの対応付け結果。ノートの番号は set_notes に渡した順。
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct MidiAlignment {
+    matched_notes: Vec<u32>,
+    matched_midi: Vec<u32>,
+    target_midis: Vec<f32>,
+    target_starts: Vec<f32>,
+    target_ends: Vec<f32>,
+    unmatched_midi: Vec<u32>,
+    unmatched_midi_starts: Vec<f32>,
+    unmatched_midi_keys: Vec<f32>,
+    unmatched_notes: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl MidiAlignment {
+    /// 対応が付いた歌のノート（set_notes の順の番号）
+    #[wasm_bindgen(getter)]
+    pub fn matched_notes(&self) -> Vec<u32> {
+        self.matched_notes.clone()
+    }
+
+    /// matched_notes と同じ並びの MIDI ノート番号（ファイル内の開始時刻順）
+    #[wasm_bindgen(getter)]
+    pub fn matched_midi(&self) -> Vec<u32> {
+        self.matched_midi.clone()
+    }
+
+    /// matched_notes と同じ並びの目標ピッチ（MIDI）
+    #[wasm_bindgen(getter)]
+    pub fn target_midis(&self) -> Vec<f32> {
+        self.target_midis.clone()
+    }
+
+    /// matched_notes と同じ並びの目標タイミング（秒、オフセット適用後）
+    #[wasm_bindgen(getter)]
+    pub fn target_starts(&self) -> Vec<f32> {
+        self.target_starts.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn target_ends(&self) -> Vec<f32> {
+        self.target_ends.clone()
+    }
+
+    /// 歌のノートに対応しなかった MIDI ノート
+    #[wasm_bindgen(getter)]
+    pub fn unmatched_midi(&self) -> Vec<u32> {
+        self.unmatched_midi.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn unmatched_midi_starts(&self) -> Vec<f32> {
+        self.unmatched_midi_starts.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn unmatched_midi_keys(&self) -> Vec<f32> {
+        self.unmatched_midi_keys.clone()
+    }
+
+    /// MIDI ノートに対応しなかった歌のノート（set_notes の順の番号）
+    #[wasm_bindgen(getter)]
+    pub fn unmatched_notes(&self) -> Vec<u32> {
+        self.unmatched_notes.clone()
+    }
+}
+
+/// 開始時刻の差が tolerance 以内の組を、差の小さい順に1対1で対応付ける。
+/// 戻り値は (notes 内の位置, midi 内の位置) の組と、レポート。
+pub(crate) fn align(
+    notes: &[NoteSpan],
+    midi: &[MidiNote],
+    tolerance_sec: f32,
+    midi_offset_sec: f32,
+) -> (Vec<(usize, usize)>, MidiAlignment) {
+    let tol = if tolerance_sec.is_finite() { tolerance_sec.max(0.0) } else { 0.0 };
+    let offset = if midi_offset_sec.is_finite() { midi_offset_sec } else { 0.0 };
+
+    let mut candidates: Vec<(f32, usize, usize)> = Vec::new();
+    for (i, n) in notes.iter().enumerate() {
+        for (j, m) in midi.iter().enumerate() {
+            let d = (m.start + offset - n.start).abs();
+            if d <= tol {
+                candidates.push((d, i, j));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut note_used = vec![false; notes.len()];
+    let mut midi_used = vec![false; midi.len()];
+    let mut pairs = Vec::new();
+    for (_, i, j) in candidates {
+        if note_used[i] || midi_used[j] {
+            continue;
+        }
+        note_used[i] = true;
+        midi_used[j] = true;
+        pairs.push((i, j));
+    }
+    pairs.sort_by_key(|&(i, _)| notes[i].index);
+
+    let mut report = MidiAlignment::default();
+    for &(i, j) in pairs.iter() {
+        let m = &midi[j];
+        report.matched_notes.push(notes[i].index as u32);
+        report.matched_midi.push(j as u32);
+        report.target_midis.push(m.key as f32);
+        report.target_starts.push(m.start + offset);
+        report.target_ends.push(m.end + offset);
+    }
+    for (j, m) in midi.iter().enumerate() {
+        if !midi_used[j] {
+            report.unmatched_midi.push(j as u32);
+            report.unmatched_midi_starts.push(m.start + offset);
+            report.unmatched_midi_keys.push(m.key as f32);
+        }
+    }
+    let mut unmatched: Vec<u32> = notes
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| !note_used[i])
+        .map(|(_, n)| n.index as u32)
+        .collect();
+    unmatched.sort_unstable();
+    report.unmatched_notes = unmatched;
+
+    (pairs, report)
+}
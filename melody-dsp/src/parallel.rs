@@ -0,0 +1,22 @@
+//! 独立した仕事（テイク、ノートのグループ）をスレッドに分ける。
+//!
+//! parallel feature があれば rayon のスレッドプール（wasm では wasm-bindgen-rayon の Web Worker、
+//! JS から initThreadPool を呼んでおく）。なければ順番に処理する。
+//! どの場合も結果は items の順に並び、順番に処理した場合と同じになる。
+//!
+//! Batch::run（テイクごと）の中で render_source がさらにグループごとに呼ぶので入れ子になるが、
+//! rayon は内側の仕事も同じプールで分け合うので、スレッドがコア数より増えることはない。
+
+/// items を順に f に通した結果（順序は items と同じ）。
+#[cfg(feature = "parallel")]
+pub(crate) fn map_parallel<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync + Send) -> Vec<R> {
+    use rayon::prelude::*;
+
+    items.par_iter().map(f).collect()
+}
+
+/// items を順に f に通した結果（順序は items と同じ）。
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn map_parallel<T, R>(items: &[T], f: impl Fn(&T) -> R) -> Vec<R> {
+    items.iter().map(f).collect()
+}
@@ -0,0 +1,458 @@
+//! プロジェクトファイル（JSON）の保存/読み込み。
+//!
+//! 元音声への参照、ピッチ解析結果、ノート（補正用の目標/補正量を含む）、
+//! 倍音 EQ とエンジン設定（音律・キー・純正律・ハモり・ダブリング・シフタの補間方式）をまとめて持つ。
+//! 音声データそのものは含めない。
+//!
+//! フォーマットは `version` で管理し、古い版は読み込み時に現行版へ移行する。
+//! - 0: バージョン番号なし。UI の NoteTrack（{ sampleRate, duration, notes }）そのもの
+//! - 1: 現行
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::doubler::DoubleCopy;
+use crate::harmony::{HarmonyVoice, IntervalRule};
+use crate::interp::Interpolation;
+use crate::scale::Scale;
+use crate::tuning::Tuning;
+use crate::MelodyEngine;
+
+pub(crate) const CURRENT_VERSION: u32 = 1;
+
+/// 元音声への参照（データ本体はプロジェクトに入れない）
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AudioRef {
+    pub(crate) name: String,
+    pub(crate) sample_rate: f32,
+    pub(crate) channels: u32,
+    pub(crate) frames: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PitchFrame {
+    // seconds
+    pub(crate) time: f32,
+    // Hz (None = unvoiced)
+    pub(crate) f0: Option<f32>,
+    // 0..1
+    pub(crate) confidence: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectFile {
+    version: u32,
+    #[serde(default)]
+    audio: Option<AudioRef>,
+    #[serde(default)]
+    pitch_frames: Vec<PitchFrame>,
+    #[serde(default)]
+    notes: Vec<ProjectNote>,
+    #[serde(default)]
+    harmonic_gains: Vec<f32>,
+    #[serde(default)]
+    settings: EngineSettings,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectNote {
+    // index in the arrays passed to set_notes
+    index: usize,
+    start_time: f32,
+    end_time: f32,
+    base_semitone: f32,
+    pitch_offset: f32,
+    pitch_center_offset: f32,
+    pitch_mod_amount: f32,
+    pitch_drift_amount: f32,
+    time_stretch_start: f32,
+    time_stretch_end: f32,
+    formant_shift: f32,
+    #[serde(default)]
+    harmonic_profile: Vec<f32>,
+    #[serde(default)]
+    target_midi: Option<f32>,
+    // 0..1
+    correction_strength: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct EngineSettings {
+    tuning: TuningSettings,
+    key: Option<KeySettings>,
+    // 0..1
+    default_correction_strength: f32,
+    just_intonation: JustSettings,
+    harmony_voices: Vec<HarmonyVoiceSettings>,
+    double_copies: Vec<DoubleCopySettings>,
+    double_seed: u64,
+    // linear / hermite / lagrange / sinc
+    interpolation: String,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        Self {
+            tuning: TuningSettings::default(),
+            key: None,
+            default_correction_strength: 0.0,
+            just_intonation: JustSettings::default(),
+            harmony_voices: Vec::new(),
+            double_copies: Vec::new(),
+            double_seed: 0,
+            interpolation: Interpolation::Linear.name().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct TuningSettings {
+    reference_hz: f32,
+    // .scl / .kbm の元テキスト（None = 12平均律 / 標準マッピング）
+    scl: Option<String>,
+    kbm: Option<String>,
+}
+
+impl Default for TuningSettings {
+    fn default() -> Self {
+        Self {
+            reference_hz: 440.0,
+            scl: None,
+            kbm: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeySettings {
+    root_pitch_class: f32,
+    steps: Vec<f32>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct JustSettings {
+    enabled: bool,
+    tonic_pitch_class: f32,
+    regions: Vec<ChordRegionSettings>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChordRegionSettings {
+    start: f32,
+    end: f32,
+    root_pitch_class: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase", rename_all_fields = "camelCase")]
+enum IntervalRuleSettings {
+    Diatonic { scale_degrees: i32 },
+    // None = そのノートは歌わない（JSON に NaN は書けないので null）
+    Custom { target_midis: Vec<Option<f32>> },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarmonyVoiceSettings {
+    rule: IntervalRuleSettings,
+    formant_shift: f32,
+    humanize_ms: f32,
+    gain: f32,
+    seed: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DoubleCopySettings {
+    detune_cents: f32,
+    delay_ms: f32,
+    pitch_variation_cents: f32,
+    formant_variation: f32,
+    pan: f32,
+    gain: f32,
+}
+
+/// エンジンの状態をプロジェクト JSON にする。
+pub(crate) fn save(engine: &MelodyEngine) -> String {
+    let notes = engine
+        .notes
+        .iter()
+        .map(|n| ProjectNote {
+            index: n.index,
+            start_time: n.start,
+            end_time: n.end,
+            base_semitone: n.base_semitone,
+            pitch_offset: n.pitch_offset,
+            pitch_center_offset: n.pitch_center_offset,
+            pitch_mod_amount: n.pitch_mod_amount,
+            pitch_drift_amount: n.pitch_drift_amount,
+            time_stretch_start: n.time_stretch_start,
+            time_stretch_end: n.time_stretch_end,
+            formant_shift: n.formant_shift,
+            harmonic_profile: n.harmonic_profile.clone(),
+            target_midi: n.target_midi,
+            correction_strength: n.correction_strength,
+        })
+        .collect();
+
+    let ji = &engine.just_intonation;
+    let settings = EngineSettings {
+        tuning: TuningSettings {
+            reference_hz: engine.tuning.reference_hz(),
+            scl: engine.tuning.scl_source().map(str::to_string),
+            kbm: engine.tuning.kbm_source().map(str::to_string),
+        },
+        key: engine.scale.as_ref().map(|s| KeySettings {
+            root_pitch_class: s.root_pc(),
+            steps: s.steps().to_vec(),
+        }),
+        default_correction_strength: engine.default_correction_strength,
+        just_intonation: JustSettings {
+            enabled: engine.just_enabled,
+            tonic_pitch_class: ji.tonic_pc(),
+            regions: ji
+                .regions()
+                .map(|(start, end, root)| ChordRegionSettings {
+                    start,
+                    end,
+                    root_pitch_class: root,
+                })
+                .collect(),
+        },
+        harmony_voices: engine
+            .harmony_voices
+            .iter()
+            .map(|v| HarmonyVoiceSettings {
+                rule: match &v.rule {
+                    IntervalRule::Diatonic(steps) => IntervalRuleSettings::Diatonic { scale_degrees: *steps },
+                    IntervalRule::Custom(targets) => IntervalRuleSettings::Custom {
+                        target_midis: targets.iter().map(|&t| t.is_finite().then_some(t)).collect(),
+                    },
+                },
+                formant_shift: v.formant_shift,
+                humanize_ms: v.humanize_ms,
+                gain: v.gain,
+                seed: v.seed,
+            })
+            .collect(),
+        double_copies: engine
+            .double_copies
+            .iter()
+            .map(|c| DoubleCopySettings {
+                detune_cents: c.detune_cents,
+                delay_ms: c.delay_ms,
+                pitch_variation_cents: c.pitch_variation_cents,
+                formant_variation: c.formant_variation,
+                pan: c.pan,
+                gain: c.gain,
+            })
+            .collect(),
+        double_seed: engine.double_seed,
+        interpolation: engine.interpolation.name().to_string(),
+    };
+
+    let project = ProjectFile {
+        version: CURRENT_VERSION,
+        audio: engine.audio_ref.clone(),
+        pitch_frames: engine.pitch_frames.clone(),
+        notes,
+        harmonic_gains: engine.harmonic_eq.gains.clone(),
+        settings,
+    };
+    // 非有限の f32 は null になるだけで失敗しない
+    serde_json::to_string(&project).unwrap_or_default()
+}
+
+/// プロジェクト JSON を読み、エンジンの状態を置き換える。
+/// 失敗した場合はエンジンを変えない。処理状態（シフタ/フィルタ）はリセットされる。
+pub(crate) fn load(engine: &mut MelodyEngine, text: &str) -> Result<(), String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| format!("project: {e}"))?;
+    let value = migrate(value)?;
+    let project: ProjectFile = serde_json::from_value(value).map_err(|e| format!("project: {e}"))?;
+
+    let mut next = MelodyEngine::new(engine.sample_rate);
+    let s = project.settings;
+
+    // 音律: .scl → .kbm → 基準ピッチの順（.kbm の基準周波数を上書きしうる）
+    let mut tuning = Tuning::default();
+    if let Some(scl) = &s.tuning.scl {
+        tuning.load_scl(scl)?;
+    }
+    if let Some(kbm) = &s.tuning.kbm {
+        tuning.load_kbm(kbm)?;
+    }
+    tuning.set_reference_hz(s.tuning.reference_hz);
+    next.tuning = tuning;
+
+    next.scale = s.key.map(|k| Scale::custom(k.root_pitch_class, k.steps));
+    next.default_correction_strength = s.default_correction_strength.clamp(0.0, 1.0);
+
+    let ji = s.just_intonation;
+    next.just_intonation.set_tonic(ji.tonic_pitch_class);
+    let starts: Vec<f32> = ji.regions.iter().map(|r| r.start).collect();
+    let ends: Vec<f32> = ji.regions.iter().map(|r| r.end).collect();
+    let roots: Vec<f32> = ji.regions.iter().map(|r| r.root_pitch_class).collect();
+    next.just_intonation.set_regions(&starts, &ends, &roots);
+    next.just_enabled = ji.enabled;
+
+    next.harmony_voices = s
+        .harmony_voices
+        .into_iter()
+        .map(|v| HarmonyVoice {
+            rule: match v.rule {
+                IntervalRuleSettings::Diatonic { scale_degrees } => IntervalRule::Diatonic(scale_degrees),
+                IntervalRuleSettings::Custom { target_midis } => {
+                    IntervalRule::Custom(target_midis.into_iter().map(|t| t.unwrap_or(f32::NAN)).collect())
+                }
+            },
+            formant_shift: v.formant_shift,
+            humanize_ms: v.humanize_ms,
+            gain: v.gain,
+            seed: v.seed,
+        })
+        .collect();
+    next.double_copies = s
+        .double_copies
+        .into_iter()
+        .map(|c| DoubleCopy {
+            detune_cents: c.detune_cents,
+            delay_ms: c.delay_ms,
+            pitch_variation_cents: c.pitch_variation_cents,
+            formant_variation: c.formant_variation,
+            pan: c.pan,
+            gain: c.gain,
+        })
+        .collect();
+    next.double_seed = s.double_seed;
+    next.interpolation = Interpolation::from_name(&s.interpolation)
+        .ok_or_else(|| format!("project: unknown interpolation {:?}", s.interpolation))?;
+    next.reset_processing_state();
+
+    next.set_harmonic_gains(project.harmonic_gains);
+    set_project_notes(&mut next, &project.notes);
+
+    next.audio_ref = project.audio;
+    next.pitch_frames = project.pitch_frames;
+
+    // 元音声はプロジェクトに入らないので引き継ぐ（レンダリングキャッシュは作り直し）
+    next.source = engine.source.take();
+    *engine = next;
+    Ok(())
+}
+
+/// set_notes と同じ検証/クランプを通してノートを入れ、保存時の index と補正設定を戻す。
+fn set_project_notes(engine: &mut MelodyEngine, notes: &[ProjectNote]) {
+    let col = |f: fn(&ProjectNote) -> f32| notes.iter().map(f).collect::<Vec<f32>>();
+
+    // 倍音プロファイルは最長のものに揃える（足りない分は 1.0）
+    let hp = notes.iter().map(|n| n.harmonic_profile.len()).max().unwrap_or(0);
+    let mut flat = Vec::with_capacity(notes.len() * hp);
+    for n in notes {
+        flat.extend((0..hp).map(|j| n.harmonic_profile.get(j).copied().unwrap_or(1.0)));
+    }
+
+    engine.set_notes(
+        col(|n| n.start_time),
+        col(|n| n.end_time),
+        col(|n| n.base_semitone),
+        col(|n| n.pitch_offset),
+        col(|n| n.pitch_center_offset),
+        col(|n| n.pitch_mod_amount),
+        col(|n| n.pitch_drift_amount),
+        col(|n| n.time_stretch_start),
+        col(|n| n.time_stretch_end),
+        col(|n| n.formant_shift),
+        hp as u32,
+        flat,
+    );
+
+    // set_notes の index はこの配列での位置なので、保存時の値に戻す
+    for note in engine.notes.iter_mut() {
+        let saved = &notes[note.index];
+        note.index = saved.index;
+        note.target_midi = saved.target_midi.filter(|t| t.is_finite());
+        note.correction_strength = if saved.correction_strength.is_finite() {
+            saved.correction_strength.clamp(0.0, 1.0)
+        } else {
+            engine.default_correction_strength
+        };
+    }
+    engine.update_corrections();
+}
+
+/// 古い版の JSON を現行版の形に直す。
+fn migrate(mut value: Value) -> Result<Value, String> {
+    let version = match value.get("version") {
+        None => 0,
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| format!("project: invalid version {v}"))? as u32,
+    };
+    if version > CURRENT_VERSION {
+        return Err(format!(
+            "project: version {version} is newer than supported ({CURRENT_VERSION})"
+        ));
+    }
+
+    if version == 0 {
+        value = migrate_v0(value)?;
+    }
+    Ok(value)
+}
+
+/// v0（UI の NoteTrack）→ v1。無効（enabled: false）のノートは捨てる。
+fn migrate_v0(value: Value) -> Result<Value, String> {
+    let track = value.as_object().ok_or("project: expected an object")?;
+    let num = |obj: &Map<String, Value>, key: &str, default: f64| obj.get(key).and_then(Value::as_f64).unwrap_or(default);
+
+    let sample_rate = num(track, "sampleRate", 0.0);
+    let duration = num(track, "duration", 0.0);
+
+    let mut notes = Vec::new();
+    if let Some(list) = track.get("notes").and_then(Value::as_array) {
+        for (i, n) in list.iter().enumerate() {
+            let Some(n) = n.as_object() else { continue };
+            if n.get("enabled").and_then(Value::as_bool) == Some(false) {
+                continue;
+            }
+            notes.push(json!({
+                "index": i,
+                "startTime": num(n, "startTime", 0.0),
+                "endTime": num(n, "endTime", 0.0),
+                "baseSemitone": num(n, "baseSemitone", 60.0),
+                "pitchOffset": num(n, "pitchOffset", 0.0),
+                "pitchCenterOffset": num(n, "pitchCenterOffset", 0.0),
+                "pitchModAmount": num(n, "pitchModAmount", 1.0),
+                "pitchDriftAmount": num(n, "pitchDriftAmount", 1.0),
+                "timeStretchStart": num(n, "timeStretchStart", 1.0),
+                "timeStretchEnd": num(n, "timeStretchEnd", 1.0),
+                "formantShift": num(n, "formantShift", 0.0),
+                "correctionStrength": 0.0,
+            }));
+        }
+    }
+
+    let audio = (sample_rate > 0.0).then(|| {
+        json!({
+            "name": "",
+            "sampleRate": sample_rate,
+            "channels": 1,
+            "frames": (duration.max(0.0) * sample_rate).round() as u64,
+        })
+    });
+
+    Ok(json!({
+        "version": 1,
+        "audio": audio,
+        "notes": notes,
+    }))
+}
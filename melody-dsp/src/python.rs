@@ -0,0 +1,369 @@
+//! Python バインディング（feature "python"、pyo3 + numpy）。
+//!
+//! ノートブックからピッチ解析・ノート分割・レンダリングを使うためのもの。音声は numpy 配列で受け渡す：
+//! 1 次元ならモノラル、2 次元なら (channels, frames)。float32 以外の配列は変換して受け取る。
+//! 重い処理（解析・レンダリング）の間は GIL を外す。
+//!
+//! ```python
+//! import melody_dsp
+//! times, f0, conf = melody_dsp.detect_pitch(audio, 44100)
+//! engine = melody_dsp.Engine(44100)
+//! engine.load_source(audio)
+//! engine.analyze_pitch()
+//! engine.detect_notes()
+//! engine.set_key(9, "minor")
+//! out = engine.render()
+//! ```
+
+use numpy::ndarray::{Array1, Array2, ArrayD, ArrayViewD};
+use numpy::{AllowTypeChange, IntoPyArray, PyArray1, PyArrayDyn, PyArrayLike, PyArrayLike1};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::analysis::{self, NoteDetectionConfig};
+use crate::project::PitchFrame;
+use crate::tuning::Tuning;
+use crate::MelodyEngine;
+
+type AudioIn<'py> = PyArrayLike<'py, f32, numpy::IxDyn, AllowTypeChange>;
+type FloatsIn<'py> = PyArrayLike1<'py, f32, AllowTypeChange>;
+type Floats3<'py> = (Bound<'py, PyArray1<f32>>, Bound<'py, PyArray1<f32>>, Bound<'py, PyArray1<f32>>);
+
+/// numpy の音声を (planar のサンプル, チャンネル数) にする。
+fn planar_of(audio: &AudioIn<'_>) -> PyResult<(Vec<f32>, usize)> {
+    planar_of_view(audio.as_array()).map_err(PyValueError::new_err)
+}
+
+fn planar_of_view(view: ArrayViewD<'_, f32>) -> Result<(Vec<f32>, usize), String> {
+    match view.ndim() {
+        1 => Ok((view.iter().copied().collect(), 1)),
+        // (channels, frames) の論理順 = planar（転置ビューなどメモリ上の並びによらない）
+        2 => Ok((view.iter().copied().collect(), view.shape()[0].max(1))),
+        n => Err(format!("audio must be 1-D or 2-D (channels, frames), got {n}-D")),
+    }
+}
+
+/// planar のサンプルを入力と同じ形（1 次元 or (channels, frames)）の配列にする。
+fn audio_out<'py>(py: Python<'py>, samples: Vec<f32>, channels: usize, mono: bool) -> PyResult<Bound<'py, PyArrayDyn<f32>>> {
+    Ok(audio_array(samples, channels, mono).map_err(PyValueError::new_err)?.into_pyarray(py))
+}
+
+fn audio_array(samples: Vec<f32>, channels: usize, mono: bool) -> Result<ArrayD<f32>, String> {
+    if mono {
+        return Ok(Array1::from(samples).into_dyn());
+    }
+    let frames = samples.len() / channels.max(1);
+    let array = Array2::from_shape_vec((channels, frames), samples).map_err(|e| e.to_string())?;
+    Ok(array.into_dyn())
+}
+
+fn floats(v: &FloatsIn<'_>) -> Vec<f32> {
+    v.as_array().iter().copied().collect()
+}
+
+/// mono（1 次元、2 次元ならチャンネル平均）の f0 を hop_sec ごとに求める。
+/// 戻り値は (時刻 秒, f0 Hz（無声は NaN）, 信頼度 0..1)。
+#[pyfunction]
+#[pyo3(signature = (audio, sample_rate, hop_sec = 0.01))]
+fn detect_pitch<'py>(
+    py: Python<'py>,
+    audio: AudioIn<'py>,
+    sample_rate: f32,
+    hop_sec: f32,
+) -> PyResult<Floats3<'py>> {
+    let (samples, channels) = planar_of(&audio)?;
+    let frames = py.detach(move || {
+        let n = samples.len() / channels;
+        let mono: Vec<f32> = if channels == 1 {
+            samples
+        } else {
+            (0..n)
+                .map(|i| (0..channels).map(|ch| samples[ch * n + i]).sum::<f32>() / channels as f32)
+                .collect()
+        };
+        analysis::detect_pitch(&mono, sample_rate, hop_sec)
+    });
+    Ok((
+        frames.iter().map(|f| f.time).collect::<Vec<f32>>().into_pyarray(py),
+        frames.iter().map(|f| f.f0.unwrap_or(f32::NAN)).collect::<Vec<f32>>().into_pyarray(py),
+        frames.iter().map(|f| f.confidence).collect::<Vec<f32>>().into_pyarray(py),
+    ))
+}
+
+/// detect_pitch の結果をノートに分ける（UI のノート分割と同じ手順・既定値）。
+/// 戻り値は (開始 秒, 終了 秒, 音高 MIDI（小数、フレームの中央値）)。
+#[pyfunction]
+#[pyo3(signature = (
+    times,
+    f0,
+    confidence,
+    reference_hz = 440.0,
+    min_confidence = 0.3,
+    max_gap_sec = 0.05,
+    max_jump_semitones = 1.2,
+    max_std_dev_semitones = 0.6,
+    min_note_sec = 0.06,
+    min_frames_per_note = 3,
+))]
+#[allow(clippy::too_many_arguments)]
+fn detect_notes<'py>(
+    py: Python<'py>,
+    times: FloatsIn<'py>,
+    f0: FloatsIn<'py>,
+    confidence: FloatsIn<'py>,
+    reference_hz: f32,
+    min_confidence: f32,
+    max_gap_sec: f32,
+    max_jump_semitones: f32,
+    max_std_dev_semitones: f32,
+    min_note_sec: f32,
+    min_frames_per_note: usize,
+) -> Floats3<'py> {
+    let (times, f0, confidence) = (floats(&times), floats(&f0), floats(&confidence));
+    let frames: Vec<PitchFrame> = times
+        .iter()
+        .zip(f0.iter())
+        .zip(confidence.iter())
+        .map(|((&time, &f0), &confidence)| PitchFrame {
+            time,
+            f0: (f0.is_finite() && f0 > 0.0).then_some(f0),
+            confidence,
+        })
+        .collect();
+    let mut tuning = Tuning::default();
+    tuning.set_reference_hz(reference_hz);
+    let cfg = NoteDetectionConfig {
+        min_frame_confidence: min_confidence,
+        max_gap_sec,
+        max_jump_semitones,
+        max_std_dev_semitones,
+        min_note_sec,
+        min_frames_per_note,
+    };
+    let notes = analysis::detect_notes(&frames, &tuning, &cfg);
+    (
+        notes.iter().map(|n| n.start).collect::<Vec<f32>>().into_pyarray(py),
+        notes.iter().map(|n| n.end).collect::<Vec<f32>>().into_pyarray(py),
+        notes.iter().map(|n| n.midi).collect::<Vec<f32>>().into_pyarray(py),
+    )
+}
+
+/// MelodyEngine（ノート列に従う補正とレンダリング）。
+#[pyclass(name = "Engine", module = "melody_dsp")]
+struct PyEngine {
+    engine: MelodyEngine,
+}
+
+#[pymethods]
+impl PyEngine {
+    #[new]
+    fn new(sample_rate: f32) -> Self {
+        Self {
+            engine: MelodyEngine::new(sample_rate),
+        }
+    }
+
+    #[getter]
+    fn sample_rate(&self) -> f32 {
+        self.engine.sample_rate()
+    }
+
+    /// 出力の遅れ（サンプル）。render の出力は補償済みで入力と揃っているので常に 0
+    #[getter]
+    fn latency_samples(&self) -> usize {
+        self.engine.latency_samples()
+    }
+
+    /// ノート列を置き換える。省略した配列は UI と同じ既定値（オフセット 0、揺れ/ドリフト 1.0、伸縮 1.0）。
+    #[pyo3(signature = (
+        starts,
+        ends,
+        base_semitones,
+        pitch_offsets = None,
+        pitch_center_offsets = None,
+        pitch_mod_amounts = None,
+        pitch_drift_amounts = None,
+        time_stretch_starts = None,
+        time_stretch_ends = None,
+        formant_shifts = None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn set_notes(
+        &mut self,
+        starts: FloatsIn<'_>,
+        ends: FloatsIn<'_>,
+        base_semitones: FloatsIn<'_>,
+        pitch_offsets: Option<FloatsIn<'_>>,
+        pitch_center_offsets: Option<FloatsIn<'_>>,
+        pitch_mod_amounts: Option<FloatsIn<'_>>,
+        pitch_drift_amounts: Option<FloatsIn<'_>>,
+        time_stretch_starts: Option<FloatsIn<'_>>,
+        time_stretch_ends: Option<FloatsIn<'_>>,
+        formant_shifts: Option<FloatsIn<'_>>,
+    ) {
+        let n = starts.as_array().len();
+        let or = |v: Option<FloatsIn<'_>>, default: f32| v.map_or_else(|| vec![default; n], |v| floats(&v));
+        self.engine.set_notes(
+            floats(&starts),
+            floats(&ends),
+            floats(&base_semitones),
+            or(pitch_offsets, 0.0),
+            or(pitch_center_offsets, 0.0),
+            or(pitch_mod_amounts, 1.0),
+            or(pitch_drift_amounts, 1.0),
+            or(time_stretch_starts, 1.0),
+            or(time_stretch_ends, 1.0),
+            or(formant_shifts, 0.0),
+            0,
+            Vec::new(),
+        );
+    }
+
+    /// エンジンが保持しているノート列（開始時刻順）。値は配列の dict。
+    fn notes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let list = self.engine.note_list();
+        let d = PyDict::new(py);
+        d.set_item("index", list.indices().into_pyarray(py))?;
+        d.set_item("start", list.starts().into_pyarray(py))?;
+        d.set_item("end", list.ends().into_pyarray(py))?;
+        d.set_item("base_semitone", list.base_semitones().into_pyarray(py))?;
+        d.set_item("pitch_offset", list.pitch_offsets().into_pyarray(py))?;
+        d.set_item("formant_shift", list.formant_shifts().into_pyarray(py))?;
+        d.set_item("target_midi", list.target_midis().into_pyarray(py))?;
+        d.set_item("correction_strength", list.correction_strengths().into_pyarray(py))?;
+        d.set_item("correction_offset", list.correction_offsets().into_pyarray(py))?;
+        d.set_item("just_offset", list.just_offsets().into_pyarray(py))?;
+        Ok(d)
+    }
+
+    /// キー/スケールを名前で設定する（major / minor / ... / chromatic）。
+    fn set_key(&mut self, root_pitch_class: f32, scale_name: &str) -> PyResult<()> {
+        if self.engine.set_key(root_pitch_class, scale_name) {
+            Ok(())
+        } else {
+            Err(PyValueError::new_err(format!("unknown scale: {scale_name}")))
+        }
+    }
+
+    fn clear_key(&mut self) {
+        self.engine.clear_key();
+    }
+
+    /// 全ノートの補正量（0..100 %）
+    fn set_correction_strength(&mut self, percent: f32) {
+        self.engine.set_correction_strength(percent);
+    }
+
+    fn set_reference_pitch(&mut self, hz: f32) {
+        self.engine.set_reference_pitch(hz);
+    }
+
+    /// シフタのディレイの補間方式（linear / hermite / lagrange / sinc）。
+    fn set_interpolation(&mut self, name: &str) -> PyResult<()> {
+        if self.engine.set_interpolation(name) {
+            Ok(())
+        } else {
+            Err(PyValueError::new_err(format!("unknown interpolation: {name}")))
+        }
+    }
+
+    fn load_project(&mut self, json: &str) -> PyResult<()> {
+        self.engine.load_project(json).map_err(PyValueError::new_err)
+    }
+
+    fn save_project(&self) -> String {
+        self.engine.save_project()
+    }
+
+    /// 元音声を読み込む（analyze_pitch / render の対象）。
+    fn load_source(&mut self, audio: AudioIn<'_>) -> PyResult<()> {
+        let (samples, channels) = planar_of(&audio)?;
+        self.engine.load_source(&samples, channels);
+        Ok(())
+    }
+
+    /// 元音声のピッチを解析して保持する。フレーム数を返す。
+    #[pyo3(signature = (hop_sec = 0.01))]
+    fn analyze_pitch(&mut self, py: Python<'_>, hop_sec: f32) -> PyResult<usize> {
+        let engine = &mut self.engine;
+        py.detach(|| engine.analyze_pitch(hop_sec)).map_err(PyValueError::new_err)
+    }
+
+    /// 解析結果からノートを切り出して set_notes する。ノート数を返す。
+    fn detect_notes(&mut self) -> usize {
+        self.engine.detect_notes()
+    }
+
+    /// audio を渡せばそれを、省略すれば load_source した元音声をレンダリングする。
+    /// 戻り値は入力と同じ形。
+    #[pyo3(signature = (audio = None))]
+    fn render<'py>(&mut self, py: Python<'py>, audio: Option<AudioIn<'py>>) -> PyResult<Bound<'py, PyArrayDyn<f32>>> {
+        let engine = &mut self.engine;
+        match audio {
+            Some(audio) => {
+                let mono = audio.as_array().ndim() == 1;
+                let (mut samples, channels) = planar_of(&audio)?;
+                py.detach(|| {
+                    engine.reset_processing_state();
+                    engine.process_planar(&mut samples, channels);
+                });
+                audio_out(py, samples, channels, mono)
+            }
+            None => {
+                let channels = engine.source_channels();
+                let samples = py.detach(|| engine.render_source()).map_err(PyValueError::new_err)?;
+                audio_out(py, samples, channels, channels == 1)
+            }
+        }
+    }
+}
+
+#[pymodule]
+fn melody_dsp(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(detect_pitch, m)?)?;
+    m.add_function(wrap_pyfunction!(detect_notes, m)?)?;
+    m.add_class::<PyEngine>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use numpy::ndarray::{arr1, arr2, Array3};
+
+    use super::*;
+
+    #[test]
+    fn audio_is_read_as_planar() {
+        let mono = arr1(&[0.1_f32, 0.2, 0.3]);
+        assert_eq!(planar_of_view(mono.view().into_dyn()).unwrap(), (vec![0.1, 0.2, 0.3], 1));
+
+        let stereo = arr2(&[[1.0_f32, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        assert_eq!(planar_of_view(stereo.view().into_dyn()).unwrap(), (vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 2));
+        // (frames, channels) の配列を .T で渡しても planar になる
+        let interleaved = arr2(&[[1.0_f32, 4.0], [2.0, 5.0], [3.0, 6.0]]);
+        assert_eq!(planar_of_view(interleaved.t().into_dyn()).unwrap(), (vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 2));
+
+        let cube = Array3::<f32>::zeros((1, 2, 3));
+        let err = planar_of_view(cube.view().into_dyn()).unwrap_err();
+        assert_eq!(err, "audio must be 1-D or 2-D (channels, frames), got 3-D");
+    }
+
+    #[test]
+    fn output_has_the_input_shape() {
+        let samples = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let mono = audio_array(samples.clone(), 1, true).unwrap();
+        assert_eq!(mono.shape(), &[6]);
+        let stereo = audio_array(samples.clone(), 2, false).unwrap();
+        assert_eq!(stereo.shape(), &[2, 3]);
+        let stereo = stereo.into_dimensionality::<numpy::ndarray::Ix2>().unwrap();
+        assert_eq!(stereo, arr2(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]));
+        // 1 チャンネルの 2 次元入力は (1, frames) のまま返す
+        assert_eq!(audio_array(samples.clone(), 1, false).unwrap().shape(), &[1, 6]);
+
+        // 入れて出すと同じ形と並び
+        let input = arr2(&[[0.5_f32, -0.5], [0.25, -0.25], [0.0, 1.0]]);
+        let (planar, channels) = planar_of_view(input.view().into_dyn()).unwrap();
+        assert_eq!(audio_array(planar, channels, false).unwrap(), input.into_dyn());
+    }
+}
@@ -0,0 +1,276 @@
+//! 帯域制限サンプルレート変換（Kaiser 窓付き sinc）。
+//!
+//! カーネルはオーバーサンプリングしたテーブルに前計算し、位相は線形補間で引く。
+//! ダウンサンプル時はカットオフを出力側のナイキストまで下げてエイリアスを抑える。
+
+use wasm_bindgen::prelude::*;
+
+use std::f64::consts::PI;
+
+// テーブルの分解能（ゼロ交差 1 つあたり）
+const TABLE_PHASES: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ResampleQuality {
+    Fast,
+    Medium,
+    High,
+    Best,
+}
+
+impl ResampleQuality {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "fast" => Some(Self::Fast),
+            "medium" => Some(Self::Medium),
+            "high" => Some(Self::High),
+            "best" => Some(Self::Best),
+            _ => None,
+        }
+    }
+
+    /// (片側のゼロ交差数, Kaiser β, 通過帯域の割合)
+    fn params(self) -> (usize, f64, f64) {
+        match self {
+            Self::Fast => (8, 5.0, 0.85),
+            Self::Medium => (16, 7.0, 0.9),
+            Self::High => (32, 9.0, 0.94),
+            Self::Best => (64, 12.0, 0.96),
+        }
+    }
+}
+
+pub(crate) struct Resampler {
+    ratio: f64, // out / in
+    half_taps: usize,
+    cutoff: f64, // relative to the input Nyquist-normalised sinc (1.0 = no band limit)
+    table: Vec<f32>,
+}
+
+impl Resampler {
+    pub(crate) fn new(from_rate: f32, to_rate: f32, quality: ResampleQuality) -> Self {
+        let ratio = if from_rate > 0.0 && to_rate > 0.0 {
+            to_rate as f64 / from_rate as f64
+        } else {
+            1.0
+        };
+        let (zero_crossings, beta, passband) = quality.params();
+        let cutoff = ratio.min(1.0) * passband;
+
+        // sinc(x) * kaiser(x / zero_crossings), x in [0, zero_crossings]
+        let len = zero_crossings * TABLE_PHASES + 2;
+        let i0_beta = bessel_i0(beta);
+        let table = (0..len)
+            .map(|i| {
+                let x = i as f64 / TABLE_PHASES as f64;
+                let r = x / zero_crossings as f64;
+                if r >= 1.0 {
+                    return 0.0;
+                }
+                let w = bessel_i0(beta * (1.0 - r * r).sqrt()) / i0_beta;
+                (sinc(x) * w) as f32
+            })
+            .collect();
+
+        // 入力サンプル上でのカーネル片側幅（ダウンサンプル時は広がる）
+        let half_taps = (zero_crossings as f64 / cutoff).ceil() as usize;
+
+        Self {
+            ratio,
+            half_taps,
+            cutoff,
+            table,
+        }
+    }
+
+    /// 入力長 len に対する出力長
+    pub(crate) fn output_len(&self, len: usize) -> usize {
+        ((len as f64) * self.ratio).round() as usize
+    }
+
+    fn kernel(&self, d: f64) -> f64 {
+        let x = (d * self.cutoff).abs() * TABLE_PHASES as f64;
+        let i = x as usize;
+        if i + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let frac = x - i as f64;
+        let a = self.table[i] as f64;
+        let b = self.table[i + 1] as f64;
+        a + (b - a) * frac
+    }
+
+    /// 1チャンネル分をまとめて変換する（範囲外は 0 とみなす）。
+    pub(crate) fn process(&self, input: &[f32]) -> Vec<f32> {
+        let out_len = self.output_len(input.len());
+        if (self.ratio - 1.0).abs() < 1.0e-12 {
+            return input.to_vec();
+        }
+
+        let step = 1.0 / self.ratio;
+        let n = input.len() as isize;
+        let half = self.half_taps as isize;
+        let mut out = Vec::with_capacity(out_len);
+        for j in 0..out_len {
+            let t = j as f64 * step;
+            let center = t.floor() as isize;
+            let mut acc = 0.0_f64;
+            for k in (center - half + 1).max(0)..=(center + half).min(n - 1) {
+                acc += input[k as usize] as f64 * self.kernel(t - k as f64);
+            }
+            out.push((acc * self.cutoff) as f32);
+        }
+        out
+    }
+}
+
+/// planar（[ch0..., ch1..., ...]）の多チャンネルを変換する。
+pub(crate) fn resample_planar(input: &[f32], channels: usize, from_rate: f32, to_rate: f32, quality: ResampleQuality) -> Vec<f32> {
+    let channels = channels.max(1);
+    let frames = input.len() / channels;
+    let r = Resampler::new(from_rate, to_rate, quality);
+    let mut out = Vec::with_capacity(r.output_len(frames) * channels);
+    for ch in 0..channels {
+        out.extend(r.process(&input[ch * frames..(ch + 1) * frames]));
+    }
+    out
+}
+
+/// planar の多チャンネル音声（チャンネル数 1 ならモノラル）を from_rate → to_rate に変換する。
+/// - quality: fast / medium / high / best
+#[wasm_bindgen]
+pub fn resample(input: &[f32], channels: usize, from_rate: f32, to_rate: f32, quality: &str) -> Result<Vec<f32>, String> {
+    let q = ResampleQuality::from_name(quality).ok_or_else(|| format!("resample: unknown quality {quality:?}"))?;
+    Ok(resample_planar(input, channels, from_rate, to_rate, q))
+}
+
+pub(crate) fn sinc(x: f64) -> f64 {
+    if x.abs() < 1.0e-12 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// 第1種変形ベッセル関数 I0（級数展開）
+pub(crate) fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let q = x * x / 4.0;
+    for k in 1..64 {
+        term *= q / ((k * k) as f64);
+        sum += term;
+        if term < sum * 1.0e-16 {
+            break;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUALITIES: [ResampleQuality; 4] = [
+        ResampleQuality::Fast,
+        ResampleQuality::Medium,
+        ResampleQuality::High,
+        ResampleQuality::Best,
+    ];
+
+    /// 阻止域の目標（dB）。Kaiser 窓の β からの減衰量（A ≈ β / 0.1102 + 8.7）より少し甘くとる
+    fn stopband_db(quality: ResampleQuality) -> f64 {
+        match quality {
+            ResampleQuality::Fast => -50.0,
+            ResampleQuality::Medium => -70.0,
+            ResampleQuality::High => -85.0,
+            ResampleQuality::Best => -110.0,
+        }
+    }
+
+    fn sine(hz: f64, rate: f64, n: usize) -> Vec<f32> {
+        (0..n).map(|i| (2.0 * PI * hz * i as f64 / rate).sin() as f32).collect()
+    }
+
+    /// f0 → f1 Hz の対数スイープ（振幅 0.5）
+    fn sweep(rate: f64, n: usize, f0: f64, f1: f64) -> Vec<f32> {
+        let t1 = n as f64 / rate;
+        let k = (f1 / f0).ln();
+        (0..n)
+            .map(|i| {
+                let t = i as f64 / rate;
+                (2.0 * PI * f0 * t1 / k * ((k * t / t1).exp() - 1.0)).sin() as f32 * 0.5
+            })
+            .collect()
+    }
+
+    fn rms(x: &[f32]) -> f64 {
+        (x.iter().map(|&v| (v as f64).powi(2)).sum::<f64>() / x.len() as f64).sqrt()
+    }
+
+    /// b - a の大きさ（a に対する dB）
+    fn error_db(a: &[f32], b: &[f32]) -> f64 {
+        let err: Vec<f32> = a.iter().zip(b).map(|(x, y)| x - y).collect();
+        20.0 * (rms(&err) / rms(a)).log10()
+    }
+
+    #[test]
+    fn sweep_round_trip_44k_48k() {
+        let n = 44100;
+        let x = sweep(44100.0, n, 20.0, 16000.0);
+        // 端はカーネルが範囲外にかかるので見ない
+        let edge = 2000;
+        for q in QUALITIES {
+            let up = resample_planar(&x, 1, 44100.0, 48000.0, q);
+            let back = resample_planar(&up, 1, 48000.0, 44100.0, q);
+            assert_eq!(up.len(), 48000);
+            assert_eq!(back.len(), n);
+            let e = error_db(&x[edge..n - edge], &back[edge..n - edge]);
+            assert!(e < stopband_db(q), "{q:?}: round trip error {e:.1} dB");
+        }
+    }
+
+    #[test]
+    fn tone_above_new_nyquist_is_attenuated() {
+        // 48k → 44.1k で 23 kHz は新しいナイキスト（22.05 kHz）より上
+        let tone = sine(23000.0, 48000.0, 48000);
+        for q in QUALITIES {
+            let down = resample_planar(&tone, 1, 48000.0, 44100.0, q);
+            let level = 20.0 * (rms(&down[2000..42000]) / rms(&tone)).log10();
+            assert!(level < stopband_db(q), "{q:?}: alias {level:.1} dB");
+        }
+    }
+
+    #[test]
+    fn output_is_aligned_with_input() {
+        // 遅れなし: 44.1k の 1 kHz を 48k にしたものは 48k で作った 1 kHz と揃う
+        let up = resample_planar(&sine(1000.0, 44100.0, 44100), 1, 44100.0, 48000.0, ResampleQuality::Medium);
+        let expected = sine(1000.0, 48000.0, 48000);
+        let e = error_db(&expected[2000..46000], &up[2000..46000]);
+        assert!(e < -60.0, "{e:.1} dB");
+
+        // インパルスの位置は比で移る
+        let mut impulse = vec![0.0_f32; 4410];
+        impulse[1000] = 1.0;
+        let up = resample_planar(&impulse, 1, 44100.0, 48000.0, ResampleQuality::High);
+        let peak = up
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .unwrap();
+        assert_eq!(peak, (1000.0_f64 * 48000.0 / 44100.0).round() as usize);
+    }
+
+    #[test]
+    fn planar_channels_keep_their_length() {
+        let left = sine(440.0, 44100.0, 1000);
+        let right = sine(660.0, 44100.0, 1000);
+        let planar: Vec<f32> = left.iter().chain(&right).copied().collect();
+        let out = resample(&planar, 2, 44100.0, 48000.0, "high").unwrap();
+        let frames = (1000.0_f64 * 48000.0 / 44100.0).round() as usize;
+        assert_eq!(out.len(), frames * 2);
+        assert_eq!(out[frames..], resample_planar(&right, 1, 44100.0, 48000.0, ResampleQuality::High)[..]);
+        assert!(resample(&planar, 2, 44100.0, 48000.0, "ultra").is_err());
+    }
+}
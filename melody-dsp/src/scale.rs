@@ -0,0 +1,181 @@
+//! キー/スケール定義と「最寄りのスケール音」探索。
+//!
+//! TS側の `snapMidiToScale` 相当をエンジン内に持たせ、
+//! ノートごとの補正量（strength）と組み合わせて目標ピッチを決める。
+
+/// スケール名 → オクターブ内の度数（半音, 0..12）
+fn scale_steps_by_name(name: &str) -> Option<&'static [f32]> {
+    let steps: &'static [f32] = match name.trim().to_ascii_lowercase().as_str() {
+        "chromatic" => &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0],
+        "major" | "ionian" => &[0.0, 2.0, 4.0, 5.0, 7.0, 9.0, 11.0],
+        "dorian" => &[0.0, 2.0, 3.0, 5.0, 7.0, 9.0, 10.0],
+        "phrygian" => &[0.0, 1.0, 3.0, 5.0, 7.0, 8.0, 10.0],
+        "lydian" => &[0.0, 2.0, 4.0, 6.0, 7.0, 9.0, 11.0],
+        "mixolydian" => &[0.0, 2.0, 4.0, 5.0, 7.0, 9.0, 10.0],
+        "minor" | "aeolian" | "natural_minor" => &[0.0, 2.0, 3.0, 5.0, 7.0, 8.0, 10.0],
+        "locrian" => &[0.0, 1.0, 3.0, 5.0, 6.0, 8.0, 10.0],
+        "harmonic_minor" => &[0.0, 2.0, 3.0, 5.0, 7.0, 8.0, 11.0],
+        "melodic_minor" => &[0.0, 2.0, 3.0, 5.0, 7.0, 9.0, 11.0],
+        "major_pentatonic" => &[0.0, 2.0, 4.0, 7.0, 9.0],
+        "minor_pentatonic" => &[0.0, 3.0, 5.0, 7.0, 10.0],
+        "blues" => &[0.0, 3.0, 5.0, 6.0, 7.0, 10.0],
+        _ => return None,
+    };
+    Some(steps)
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Scale {
+    // 0..12 (C=0)
+    root_pc: f32,
+    // sorted, unique, 0..12
+    steps: Vec<f32>,
+}
+
+impl Scale {
+    pub(crate) fn from_name(root_pc: f32, name: &str) -> Option<Self> {
+        scale_steps_by_name(name).map(|steps| Self::custom(root_pc, steps.to_vec()))
+    }
+
+    /// 任意の度数列からスケールを作る。範囲外は 0..12 に折り返し、重複は除く。
+    pub(crate) fn custom(root_pc: f32, steps: Vec<f32>) -> Self {
+        let mut out: Vec<f32> = steps
+            .into_iter()
+            .filter(|s| s.is_finite())
+            .map(|s| s.rem_euclid(12.0))
+            .collect();
+        out.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        out.dedup_by(|a, b| (*a - *b).abs() < 1.0e-4);
+        if out.is_empty() {
+            out.push(0.0);
+        }
+
+        let root_pc = if root_pc.is_finite() { root_pc.rem_euclid(12.0) } else { 0.0 };
+        Self { root_pc, steps: out }
+    }
+
+    /// other の度数とルート root_pc に置き換える。steps の容量が足りていればメモリを確保しない。
+    #[cfg(feature = "clap")]
+    pub(crate) fn assign(&mut self, root_pc: f32, other: &Scale) {
+        self.root_pc = if root_pc.is_finite() { root_pc.rem_euclid(12.0) } else { 0.0 };
+        self.steps.clear();
+        self.steps.extend_from_slice(&other.steps);
+    }
+
+    pub(crate) fn root_pc(&self) -> f32 {
+        self.root_pc
+    }
+
+    pub(crate) fn steps(&self) -> &[f32] {
+        &self.steps
+    }
+
+    /// midi（小数可）に最も近いスケール音（MIDI）を返す。
+    pub(crate) fn nearest(&self, midi: f32) -> f32 {
+        if !midi.is_finite() {
+            return midi;
+        }
+
+        let rel = midi - self.root_pc;
+        let octave = (rel / 12.0).floor();
+        let base = self.root_pc + octave * 12.0;
+
+        // 前後のオクターブも候補に入れて境界をまたぐケースを拾う
+        let mut best = midi;
+        let mut best_dist = f32::INFINITY;
+        for o in -1..=1 {
+            for &s in self.steps.iter() {
+                let cand = base + (o as f32) * 12.0 + s;
+                let d = (cand - midi).abs();
+                if d < best_dist {
+                    best_dist = d;
+                    best = cand;
+                }
+            }
+        }
+        best
+    }
+
+    /// midi を最寄りのスケール音に合わせてから、スケール上で degrees 度ぶん移動する。
+    /// 例: +2 = 3度上, +4 = 5度上, -2 = 3度下
+    pub(crate) fn transpose_degrees(&self, midi: f32, degrees: i32) -> f32 {
+        if !midi.is_finite() {
+            return midi;
+        }
+
+        let snapped = self.nearest(midi);
+        let rel = snapped - self.root_pc;
+        let octave = (rel / 12.0).floor();
+        let within = rel - octave * 12.0;
+
+        let mut idx = 0usize;
+        let mut best_dist = f32::INFINITY;
+        for (i, &s) in self.steps.iter().enumerate() {
+            let d = (s - within).abs().min((s + 12.0 - within).abs());
+            if d < best_dist {
+                best_dist = d;
+                idx = i;
+            }
+        }
+        // within が 12 近くに丸まったケース（次のオクターブの主音）
+        let octave = if within > 11.99 && idx == 0 { octave + 1.0 } else { octave };
+
+        let n = self.steps.len() as i32;
+        let total = idx as i32 + degrees;
+        let oct2 = total.div_euclid(n) as f32;
+        let idx2 = total.rem_euclid(n) as usize;
+        self.root_pc + (octave + oct2) * 12.0 + self.steps[idx2]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_snap_to_their_own_degrees() {
+        // D dorian = C major の音、D phrygian は Eb を含む
+        let dorian = Scale::from_name(2.0, "dorian").unwrap();
+        assert_eq!(dorian.nearest(63.3), 64.0);
+        assert_eq!(dorian.nearest(65.6), 65.0);
+        let phrygian = Scale::from_name(2.0, "phrygian").unwrap();
+        assert_eq!(phrygian.nearest(63.3), 63.0);
+        // A minor に G# はないので、近いほうの G か A に寄る
+        let minor = Scale::from_name(9.0, "aeolian").unwrap();
+        assert_eq!(minor.nearest(67.9), 67.0);
+        assert_eq!(minor.nearest(68.1), 69.0);
+        // オクターブ境界をまたぐ: B（11）の少し上は次の C
+        let major = Scale::from_name(0.0, "major").unwrap();
+        assert_eq!(major.nearest(71.6), 72.0);
+        assert!(Scale::from_name(0.0, "no-such-mode").is_none());
+    }
+
+    #[test]
+    fn custom_scales_are_wrapped_and_deduplicated() {
+        let scale = Scale::custom(14.0, vec![0.0, 7.0, 19.0, f32::NAN, -5.0]);
+        assert_eq!(scale.root_pc(), 2.0);
+        assert_eq!(scale.steps(), &[0.0, 7.0]);
+        assert_eq!(scale.nearest(66.0), 69.0);
+        assert_eq!(scale.nearest(65.4), 62.0);
+        // 何も残らなければ主音だけ
+        assert_eq!(Scale::custom(0.0, vec![f32::INFINITY]).steps(), &[0.0]);
+    }
+
+    #[test]
+    fn chromatic_rounds_to_the_nearest_semitone() {
+        let scale = Scale::from_name(5.0, "chromatic").unwrap();
+        for midi in [40.2, 59.7, 60.49, 71.51] {
+            assert_eq!(scale.nearest(midi), midi.round(), "{midi}");
+        }
+        assert_eq!(scale.transpose_degrees(60.0, 3), 63.0);
+    }
+
+    #[test]
+    fn diatonic_transposition() {
+        let major = Scale::from_name(0.0, "major").unwrap();
+        assert_eq!(major.transpose_degrees(60.0, 2), 64.0); // C → E
+        assert_eq!(major.transpose_degrees(64.0, 2), 67.0); // E → G（短3度）
+        assert_eq!(major.transpose_degrees(71.0, 2), 74.0); // B → D（オクターブをまたぐ）
+        assert_eq!(major.transpose_degrees(60.0, -2), 57.0); // C → A
+    }
+}
@@ -0,0 +1,174 @@
+//! ホットループ用の SIMD（feature "simd"）。
+//!
+//! `Lane` は feature があれば `wide::f32x4`（wasm32 では `-C target-feature=+simd128` で simd128、
+//! ネイティブでは SSE/NEON）、なければ f32。倍音 EQ のフィルタバンクは Lane 単位で書いてあるので、
+//! feature なしは 1 本ずつのスカラー処理（以前と同じ結果）になる。
+//! cos / tanh は SIMD 版が近似なので、feature の有無で結果は許容誤差の範囲で一致する
+//! （examples/render_bench.rs の --compare で確かめる）。
+
+#[cfg(feature = "simd")]
+pub(crate) type Lane = wide::f32x4;
+#[cfg(not(feature = "simd"))]
+pub(crate) type Lane = f32;
+
+/// Lane 1 つに入る f32 の数
+pub(crate) const LANES: usize = std::mem::size_of::<Lane>() / std::mem::size_of::<f32>();
+
+#[inline]
+pub(crate) fn splat(v: f32) -> Lane {
+    #[cfg(feature = "simd")]
+    {
+        Lane::splat(v)
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        v
+    }
+}
+
+/// 全レーンの和
+#[inline]
+pub(crate) fn sum(v: Lane) -> f32 {
+    #[cfg(feature = "simd")]
+    {
+        v.reduce_add()
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        v
+    }
+}
+
+/// Lane の並びを f32 の並びとみて i 番目を書き換える
+#[inline]
+pub(crate) fn set(lanes: &mut [Lane], i: usize, x: f32) {
+    #[cfg(feature = "simd")]
+    {
+        lanes[i / LANES].as_array_mut()[i % LANES] = x;
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        lanes[i] = x;
+    }
+}
+
+/// out[i] = 0.5 - 0.5 cos(2π d[i] / period)（シフタの 2 タップのクロスフェード）
+pub(crate) fn raised_cosine(d: &[f32], period: f32, out: &mut [f32]) {
+    #[cfg(feature = "simd")]
+    {
+        let scale = Lane::splat(2.0 * std::f32::consts::PI / period);
+        let mut d_chunks = d.chunks_exact(4);
+        let mut out_chunks = out.chunks_exact_mut(4);
+        for (d, out) in (&mut d_chunks).zip(&mut out_chunks) {
+            let x = Lane::new([d[0], d[1], d[2], d[3]]) * scale;
+            out.copy_from_slice(&(Lane::splat(0.5) - Lane::splat(0.5) * x.cos()).to_array());
+        }
+        for (&d, out) in d_chunks.remainder().iter().zip(out_chunks.into_remainder()) {
+            *out = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * d / period).cos();
+        }
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        for (&d, out) in d.iter().zip(out.iter_mut()) {
+            *out = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * d / period).cos();
+        }
+    }
+}
+
+/// y = tanh(y) を -1..1 に収める（倍音 EQ の後のソフトクリップ）
+pub(crate) fn soft_clip(buf: &mut [f32]) {
+    #[cfg(feature = "simd")]
+    {
+        let mut chunks = buf.chunks_exact_mut(4);
+        for chunk in &mut chunks {
+            let x = Lane::new([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            // tanh|x| = 1 - 2 / (e^{2|x|} + 1)。|x| > 9 では 1 に丸まるので exp の範囲内に収める
+            let e = (x.abs().min(Lane::splat(9.0)) * 2.0).exp();
+            let t = (Lane::ONE - Lane::splat(2.0) / (e + Lane::ONE)).copysign(x);
+            chunk.copy_from_slice(&t.max(Lane::splat(-1.0)).min(Lane::ONE).to_array());
+        }
+        for y in chunks.into_remainder() {
+            *y = y.tanh().clamp(-1.0, 1.0);
+        }
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        for y in buf.iter_mut() {
+            *y = y.tanh().clamp(-1.0, 1.0);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "simd"))]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+    use crate::{Biquad, FilterBank};
+
+    // README の「1e-6 程度」。cos / tanh の近似と、フィルタバンクの和の順序の違いぶん
+    const TOLERANCE: f32 = 2.0e-6;
+
+    fn max_error(a: &[f32], b: &[f32]) -> f32 {
+        assert_eq!(a.len(), b.len());
+        a.iter().zip(b).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn raised_cosine_matches_the_scalar_formula() {
+        let period = 1920.0;
+        // 4 の倍数でない長さで、端数の処理も通す
+        let d: Vec<f32> = (0..1923).map(|i| i as f32 * 0.999).collect();
+        let mut out = vec![0.0; d.len()];
+        raised_cosine(&d, period, &mut out);
+        let scalar: Vec<f32> = d.iter().map(|&d| 0.5 - 0.5 * (2.0 * PI * d / period).cos()).collect();
+        let err = max_error(&out, &scalar);
+        assert!(err < TOLERANCE, "{err}");
+    }
+
+    #[test]
+    fn soft_clip_matches_tanh() {
+        let mut buf: Vec<f32> = (-2003..=2003).map(|i| i as f32 * 0.006).collect();
+        buf.extend([30.0, -30.0, 1.0e6, -1.0e6, 0.0]);
+        let scalar: Vec<f32> = buf.iter().map(|y| y.tanh().clamp(-1.0, 1.0)).collect();
+        soft_clip(&mut buf);
+        let err = max_error(&buf, &scalar);
+        assert!(err < TOLERANCE, "{err}");
+        assert!(buf.iter().all(|y| (-1.0..=1.0).contains(y)));
+    }
+
+    #[test]
+    fn filter_bank_matches_scalar_biquads() {
+        let (sr, f0, count) = (48000.0, 196.0, 23);
+        let gains: Vec<f32> = (0..count).map(|h| 0.5 + 0.1 * (h % 7) as f32).collect();
+        let mut bank = FilterBank::new();
+        bank.rebuild(sr, f0, count, 12.0);
+        bank.set_gains(|h| gains[h]);
+
+        // 1 本ずつ Direct Form II Transposed で回したもの
+        let coeffs: Vec<Biquad> = (0..count).map(|h| Biquad::new_bandpass(sr, f0 * (h + 1) as f32, 12.0)).collect();
+        let mut state = vec![(0.0_f32, 0.0_f32); count];
+        let input: Vec<f32> = (0..4800)
+            .map(|i| {
+                let t = i as f32 / sr;
+                0.5 * (2.0 * PI * f0 * t).sin() + 0.2 * (2.0 * PI * 3.0 * f0 * t).sin()
+            })
+            .collect();
+        let mut out = Vec::with_capacity(input.len());
+        let mut scalar = Vec::with_capacity(input.len());
+        for &x in &input {
+            out.push(bank.process(x));
+            let mut acc = 0.0;
+            for ((c, (z1, z2)), g) in coeffs.iter().zip(state.iter_mut()).zip(&gains) {
+                let y = c.b0 * x + *z1;
+                *z1 = c.b1 * x - c.a1 * y + *z2;
+                *z2 = c.b2 * x - c.a2 * y;
+                acc += y * g;
+            }
+            scalar.push(acc);
+        }
+        let err = max_error(&out, &scalar);
+        assert!(err < TOLERANCE, "{err}");
+        assert!(scalar.iter().any(|y| y.abs() > 0.1));
+    }
+}
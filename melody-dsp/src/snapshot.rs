@@ -0,0 +1,252 @@
+//! エンジンが実際に保持しているノート列の読み出し。
+//!
+//! set_notes は不正なノートを捨て、量や倍率をクランプし、開始時刻順に並べ替えるので、
+//! UI 側はここで得た値と自分のノート列を index（set_notes に渡した順の番号）で突き合わせる。
+
+use wasm_bindgen::prelude::*;
+
+use crate::NoteSpan;
+
+/// ノート列のスナップショット。各配列は同じ並び（エンジン内の順 = 開始時刻順）。
+#[wasm_bindgen]
+pub struct NoteList {
+    indices: Vec<u32>,
+    starts: Vec<f32>,
+    ends: Vec<f32>,
+    base_semitones: Vec<f32>,
+    pitch_offsets: Vec<f32>,
+    pitch_center_offsets: Vec<f32>,
+    pitch_mod_amounts: Vec<f32>,
+    pitch_drift_amounts: Vec<f32>,
+    time_stretch_starts: Vec<f32>,
+    time_stretch_ends: Vec<f32>,
+    formant_shifts: Vec<f32>,
+    harmonics_per_note: u32,
+    harmonics_flat: Vec<f32>,
+    target_midis: Vec<f32>,
+    correction_strengths: Vec<f32>,
+    correction_offsets: Vec<f32>,
+    just_offsets: Vec<f32>,
+}
+
+impl NoteList {
+    pub(crate) fn from_notes(notes: &[NoteSpan]) -> Self {
+        let col = |f: fn(&NoteSpan) -> f32| notes.iter().map(f).collect::<Vec<f32>>();
+
+        let hp = notes.iter().map(|n| n.harmonic_profile.len()).max().unwrap_or(0);
+        let mut harmonics_flat = Vec::with_capacity(notes.len() * hp);
+        for n in notes {
+            harmonics_flat.extend((0..hp).map(|j| n.harmonic_profile.get(j).copied().unwrap_or(1.0)));
+        }
+
+        Self {
+            indices: notes.iter().map(|n| n.index as u32).collect(),
+            starts: col(|n| n.start),
+            ends: col(|n| n.end),
+            base_semitones: col(|n| n.base_semitone),
+            pitch_offsets: col(|n| n.pitch_offset),
+            pitch_center_offsets: col(|n| n.pitch_center_offset),
+            pitch_mod_amounts: col(|n| n.pitch_mod_amount),
+            pitch_drift_amounts: col(|n| n.pitch_drift_amount),
+            time_stretch_starts: col(|n| n.time_stretch_start),
+            time_stretch_ends: col(|n| n.time_stretch_end),
+            formant_shifts: col(|n| n.formant_shift),
+            harmonics_per_note: hp as u32,
+            harmonics_flat,
+            target_midis: col(|n| n.target_midi.unwrap_or(f32::NAN)),
+            correction_strengths: col(|n| n.correction_strength * 100.0),
+            correction_offsets: col(|n| n.correction_offset),
+            just_offsets: col(|n| n.just_offset),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl NoteList {
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// set_notes に渡した配列での位置（ノートの ID として使う）
+    #[wasm_bindgen(getter)]
+    pub fn indices(&self) -> Vec<u32> {
+        self.indices.clone()
+    }
+
+    /// 秒
+    #[wasm_bindgen(getter)]
+    pub fn starts(&self) -> Vec<f32> {
+        self.starts.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn ends(&self) -> Vec<f32> {
+        self.ends.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn base_semitones(&self) -> Vec<f32> {
+        self.base_semitones.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pitch_offsets(&self) -> Vec<f32> {
+        self.pitch_offsets.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pitch_center_offsets(&self) -> Vec<f32> {
+        self.pitch_center_offsets.clone()
+    }
+
+    /// 0..2 にクランプ済み
+    #[wasm_bindgen(getter)]
+    pub fn pitch_mod_amounts(&self) -> Vec<f32> {
+        self.pitch_mod_amounts.clone()
+    }
+
+    /// 0..2 にクランプ済み
+    #[wasm_bindgen(getter)]
+    pub fn pitch_drift_amounts(&self) -> Vec<f32> {
+        self.pitch_drift_amounts.clone()
+    }
+
+    /// 0.5..2.0 にクランプ済み
+    #[wasm_bindgen(getter)]
+    pub fn time_stretch_starts(&self) -> Vec<f32> {
+        self.time_stretch_starts.clone()
+    }
+
+    /// 0.5..2.0 にクランプ済み
+    #[wasm_bindgen(getter)]
+    pub fn time_stretch_ends(&self) -> Vec<f32> {
+        self.time_stretch_ends.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn formant_shifts(&self) -> Vec<f32> {
+        self.formant_shifts.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn harmonics_per_note(&self) -> u32 {
+        self.harmonics_per_note
+    }
+
+    /// ノートごとの倍音ゲイン（harmonics_per_note 個ずつ、0..4 にクランプ済み）
+    #[wasm_bindgen(getter)]
+    pub fn harmonics_flat(&self) -> Vec<f32> {
+        self.harmonics_flat.clone()
+    }
+
+    /// 目標ピッチ（MIDI）。なければ NaN
+    #[wasm_bindgen(getter)]
+    pub fn target_midis(&self) -> Vec<f32> {
+        self.target_midis.clone()
+    }
+
+    /// 補正量（0..100 %）
+    #[wasm_bindgen(getter)]
+    pub fn correction_strengths(&self) -> Vec<f32> {
+        self.correction_strengths.clone()
+    }
+
+    /// キー/目標ピッチ補正で実際に足される量（半音）
+    #[wasm_bindgen(getter)]
+    pub fn correction_offsets(&self) -> Vec<f32> {
+        self.correction_offsets.clone()
+    }
+
+    /// 純正律で実際に足される量（半音）
+    #[wasm_bindgen(getter)]
+    pub fn just_offsets(&self) -> Vec<f32> {
+        self.just_offsets.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MelodyEngine;
+
+    #[test]
+    fn note_list_reports_the_sanitised_notes() {
+        let nan = f32::NAN;
+        let mut engine = MelodyEngine::new(48000.0);
+        engine.set_key(0.0, "major");
+        engine.set_just_intonation(0.0);
+        engine.set_notes(
+            vec![0.5, nan, -0.2, 1.0],
+            vec![0.9, 1.0, 0.3, 1.0],
+            vec![64.0, 60.0, 60.3, 62.0],
+            vec![0.0; 4],
+            vec![0.0; 4],
+            vec![5.0, 1.0, 1.0, 1.0],
+            vec![-1.0, 1.0, 1.0, 1.0],
+            vec![0.1, 1.0, 1.0, 1.0],
+            vec![9.0, 1.0, 1.0, 1.0],
+            vec![0.0; 4],
+            3,
+            vec![nan, 7.0, -1.0, 1.0, 1.0, 1.0, 0.5, f32::INFINITY, 2.0],
+        );
+        // 範囲外は 0..100 % に、NaN は 0 % に
+        engine.set_note_correction_strengths(vec![250.0, 100.0, nan]);
+
+        let list = engine.note_list();
+        // NaN の開始と長さ 0 のノートは捨てられ、開始時刻順（負の開始は 0）に並ぶ
+        assert_eq!(list.length(), 2);
+        assert_eq!(list.indices(), vec![2, 0]);
+        assert_eq!(list.starts(), vec![0.0, 0.5]);
+        assert_eq!(list.ends(), vec![0.3, 0.9]);
+        assert_eq!(list.base_semitones(), vec![60.3, 64.0]);
+        assert_eq!(list.pitch_mod_amounts(), vec![1.0, 2.0]);
+        assert_eq!(list.pitch_drift_amounts(), vec![1.0, 0.0]);
+        assert_eq!(list.time_stretch_starts(), vec![1.0, 0.5]);
+        assert_eq!(list.time_stretch_ends(), vec![1.0, 2.0]);
+        assert_eq!(list.harmonics_per_note(), 3);
+        assert_eq!(list.harmonics_flat(), vec![0.5, 1.0, 2.0, 1.0, 4.0, 0.0]);
+
+        assert!(list.target_midis().iter().all(|t| t.is_nan()));
+        assert_eq!(list.correction_strengths(), vec![0.0, 100.0]);
+        // 補正量 0 % のノートは何も足されない。E は C の長3度なので純正律で約 14 セント下げる
+        assert_eq!(list.correction_offsets(), vec![0.0, 0.0]);
+        let just = list.just_offsets();
+        assert_eq!(just[0], 0.0);
+        assert!((just[1] + 0.137).abs() < 0.01, "{}", just[1]);
+    }
+
+    #[test]
+    fn note_list_reports_targets_and_offsets() {
+        let mut engine = MelodyEngine::new(48000.0);
+        engine.set_key(0.0, "major");
+        engine.set_correction_strength(50.0);
+        engine.set_notes(
+            vec![0.0, 0.5],
+            vec![0.4, 0.9],
+            vec![60.3, 63.6],
+            vec![0.0; 2],
+            vec![0.0; 2],
+            vec![1.0; 2],
+            vec![1.0; 2],
+            vec![1.0; 2],
+            vec![1.0; 2],
+            vec![0.0; 2],
+            0,
+            Vec::new(),
+        );
+        engine.notes[1].target_midi = Some(67.0);
+        engine.update_corrections();
+
+        let list = engine.note_list();
+        assert_eq!(list.harmonics_per_note(), 0);
+        assert!(list.harmonics_flat().is_empty());
+        let targets = list.target_midis();
+        assert!(targets[0].is_nan());
+        assert_eq!(targets[1], 67.0);
+        assert_eq!(list.correction_strengths(), vec![50.0, 50.0]);
+        let offsets = list.correction_offsets();
+        assert!((offsets[0] + 0.15).abs() < 1.0e-4, "{}", offsets[0]);
+        assert!((offsets[1] - 1.7).abs() < 1.0e-4, "{}", offsets[1]);
+        assert_eq!(list.just_offsets(), vec![0.0, 0.0]);
+    }
+}
@@ -0,0 +1,410 @@
+//! 基準ピッチ / 微分音チューニング（Scala .scl / .kbm）。
+//!
+//! エンジン内の「ノート番号 ⇔ Hz」変換はすべてここを通す。
+//! 既定は A4 = 440 Hz の 12 平均律で、従来の `midi_to_hz` と同じ値になる。
+
+/// Scala .scl 相当：1..n 度のセント値（最後の値が周期 = 通常 1200）
+#[derive(Clone, Debug)]
+struct ScalaScale {
+    cents: Vec<f64>,
+}
+
+impl ScalaScale {
+    fn equal_12() -> Self {
+        Self {
+            cents: (1..=12).map(|i| (i as f64) * 100.0).collect(),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .map(|l| l.trim_end_matches('\r'))
+            .filter(|l| !l.starts_with('!'));
+
+        // 1行目は説明（空でもよい）
+        lines.next().ok_or("scl: missing description line")?;
+
+        let count_line = lines.next().ok_or("scl: missing note count")?;
+        let count: usize = first_token(count_line)
+            .parse()
+            .map_err(|_| format!("scl: invalid note count: {count_line:?}"))?;
+        if count == 0 {
+            return Err("scl: note count must be > 0".to_string());
+        }
+
+        // count はファイルの値なので先に確保しない
+        let mut cents = Vec::new();
+        for line in lines {
+            let tok = first_token(line);
+            if tok.is_empty() {
+                continue;
+            }
+            cents.push(parse_scl_pitch(tok)?);
+            if cents.len() == count {
+                break;
+            }
+        }
+        if cents.len() != count {
+            return Err(format!("scl: expected {count} pitches, found {}", cents.len()));
+        }
+
+        let period = cents[count - 1];
+        if period.is_nan() || period <= 0.0 {
+            return Err("scl: last pitch (period) must be above the tonic".to_string());
+        }
+        Ok(Self { cents })
+    }
+
+    fn len(&self) -> i64 {
+        self.cents.len() as i64
+    }
+
+    fn period(&self) -> f64 {
+        self.cents.last().copied().unwrap_or(1200.0)
+    }
+
+    /// 度数（0 = 主音、負数・周期超えも可）→ セント
+    fn degree_cents(&self, degree: i64) -> f64 {
+        let n = self.len();
+        let octave = degree.div_euclid(n);
+        let idx = degree.rem_euclid(n);
+        let within = if idx == 0 { 0.0 } else { self.cents[(idx - 1) as usize] };
+        (octave as f64) * self.period() + within
+    }
+}
+
+// .kbm のマップの大きさの上限（ファイルの値で巨大な確保をしないように）
+const MAX_KBM_SIZE: i64 = 1024;
+
+/// Scala .kbm 相当：鍵盤（MIDIノート番号）→ 度数のマッピング
+#[derive(Clone, Debug)]
+struct KeyboardMap {
+    // 0 = linear mapping
+    size: i64,
+    first_note: i32,
+    last_note: i32,
+    middle_note: i32,
+    reference_note: i32,
+    reference_hz: f64,
+    // 0 = scale の周期をそのまま使う
+    octave_degree: i64,
+    mapping: Vec<Option<i64>>,
+}
+
+impl KeyboardMap {
+    fn standard(reference_hz: f64) -> Self {
+        Self {
+            size: 0,
+            first_note: 0,
+            last_note: 127,
+            middle_note: 60,
+            reference_note: 69,
+            reference_hz,
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .map(|l| l.trim_end_matches('\r'))
+            .filter(|l| !l.starts_with('!'))
+            .map(first_token)
+            .filter(|t| !t.is_empty());
+
+        let mut next_int = |what: &str| -> Result<i64, String> {
+            let tok = lines.next().ok_or_else(|| format!("kbm: missing {what}"))?;
+            tok.parse::<i64>().map_err(|_| format!("kbm: invalid {what}: {tok:?}"))
+        };
+
+        let size = next_int("map size")?;
+        let first_note = next_int("first note")?;
+        let last_note = next_int("last note")?;
+        let middle_note = next_int("middle note")?;
+        let reference_note = next_int("reference note")?;
+        let reference_hz: f64 = {
+            let tok = lines.next().ok_or("kbm: missing reference frequency")?;
+            tok.parse()
+                .map_err(|_| format!("kbm: invalid reference frequency: {tok:?}"))?
+        };
+        let octave_degree = lines
+            .next()
+            .ok_or("kbm: missing octave degree")?
+            .parse::<i64>()
+            .map_err(|_| "kbm: invalid octave degree".to_string())?;
+
+        if !(0..=MAX_KBM_SIZE).contains(&size) {
+            return Err(format!("kbm: map size must be 0..={MAX_KBM_SIZE}"));
+        }
+        let note = |n: i64, what: &str| i32::try_from(n).map_err(|_| format!("kbm: {what} out of range: {n}"));
+        let first_note = note(first_note, "first note")?;
+        let last_note = note(last_note, "last note")?;
+        let middle_note = note(middle_note, "middle note")?;
+        let reference_note = note(reference_note, "reference note")?;
+        if !reference_hz.is_finite() || reference_hz <= 0.0 {
+            return Err("kbm: reference frequency must be > 0".to_string());
+        }
+
+        let mut mapping = Vec::new();
+        for tok in lines.take(size as usize) {
+            if tok.eq_ignore_ascii_case("x") {
+                mapping.push(None);
+            } else {
+                let d = tok
+                    .parse::<i64>()
+                    .map_err(|_| format!("kbm: invalid mapping entry: {tok:?}"))?;
+                mapping.push(Some(d));
+            }
+        }
+        // 足りないエントリは未割り当て扱い（Scala の仕様どおり）
+        mapping.resize(size as usize, None);
+
+        Ok(Self {
+            size,
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_hz,
+            octave_degree: octave_degree.max(0),
+            mapping,
+        })
+    }
+}
+
+/// A4 = 440 Hz の 12平均律でのノート番号（小数可）→ Hz。
+/// 計測ピッチ（ノートの base_semitone）はこの単位で持つので、音律を変えても計測した周波数は変わらない。
+pub(crate) fn equal_440_hz(midi: f32) -> f32 {
+    440.0 * 2.0_f32.powf((midi - 69.0) / 12.0)
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Tuning {
+    scale: ScalaScale,
+    map: KeyboardMap,
+    // 読み込んだ元テキスト（プロジェクト保存用）
+    scl_source: Option<String>,
+    kbm_source: Option<String>,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self {
+            scale: ScalaScale::equal_12(),
+            map: KeyboardMap::standard(440.0),
+            scl_source: None,
+            kbm_source: None,
+        }
+    }
+}
+
+impl Tuning {
+    pub(crate) fn set_reference_hz(&mut self, hz: f32) {
+        if hz.is_finite() && hz > 0.0 {
+            self.map.reference_hz = hz as f64;
+        }
+    }
+
+    pub(crate) fn reference_hz(&self) -> f32 {
+        self.map.reference_hz as f32
+    }
+
+    pub(crate) fn load_scl(&mut self, text: &str) -> Result<(), String> {
+        self.scale = ScalaScale::parse(text)?;
+        self.scl_source = Some(text.to_string());
+        Ok(())
+    }
+
+    pub(crate) fn load_kbm(&mut self, text: &str) -> Result<(), String> {
+        self.map = KeyboardMap::parse(text)?;
+        self.kbm_source = Some(text.to_string());
+        Ok(())
+    }
+
+    pub(crate) fn scl_source(&self) -> Option<&str> {
+        self.scl_source.as_deref()
+    }
+
+    pub(crate) fn kbm_source(&self) -> Option<&str> {
+        self.kbm_source.as_deref()
+    }
+
+    /// 1周期あたりの鍵盤数（12 なら通常のキー/スケール指定がそのまま使える）
+    pub(crate) fn keys_per_period(&self) -> i64 {
+        if self.map.size > 0 {
+            self.map.size
+        } else {
+            self.scale.len()
+        }
+    }
+
+    /// 鍵盤番号 → 度数（未割り当て・範囲外は None）
+    fn key_degree(&self, key: i32) -> Option<i64> {
+        if key < self.map.first_note || key > self.map.last_note {
+            return None;
+        }
+        let offset = (key - self.map.middle_note) as i64;
+        if self.map.size == 0 {
+            return Some(offset);
+        }
+        let octave = offset.div_euclid(self.map.size);
+        let idx = offset.rem_euclid(self.map.size) as usize;
+        let degree = self.map.mapping.get(idx).copied().flatten()?;
+        let octave_degree = if self.map.octave_degree > 0 {
+            self.map.octave_degree
+        } else {
+            self.scale.len()
+        };
+        Some(octave * octave_degree + degree)
+    }
+
+    /// 鍵盤番号 → 基準ノートからのセント
+    fn key_cents(&self, key: i32) -> Option<f64> {
+        let d = self.key_degree(key)?;
+        let r = self.key_degree(self.map.reference_note).unwrap_or(0);
+        Some(self.scale.degree_cents(d) - self.scale.degree_cents(r))
+    }
+
+    fn equal_fallback_hz(&self, midi: f32) -> f32 {
+        (self.map.reference_hz * 2.0_f64.powf((midi as f64 - self.map.reference_note as f64) / 12.0)) as f32
+    }
+
+    pub(crate) fn is_mapped(&self, key: i32) -> bool {
+        self.key_degree(key).is_some()
+    }
+
+    /// ノート番号（小数可）→ Hz。小数部は隣接鍵の間をセントで補間する。
+    pub(crate) fn midi_to_hz(&self, midi: f32) -> f32 {
+        if !midi.is_finite() {
+            return f32::NAN;
+        }
+        let k0 = midi.floor();
+        let frac = (midi - k0) as f64;
+        let k0 = k0 as i32;
+
+        let c0 = self.key_cents(k0);
+        let c1 = if frac > 0.0 { self.key_cents(k0 + 1) } else { c0 };
+        match (c0, c1) {
+            (Some(c0), Some(c1)) => {
+                let c = c0 + (c1 - c0) * frac;
+                (self.map.reference_hz * 2.0_f64.powf(c / 1200.0)) as f32
+            }
+            // 未割り当て鍵の近傍は 12平均律で近似する
+            _ => self.equal_fallback_hz(midi),
+        }
+    }
+
+    /// Hz → ノート番号（小数）。割り当て済み鍵の間をセントで補間する。
+    pub(crate) fn hz_to_midi(&self, hz: f32) -> f32 {
+        if !hz.is_finite() || hz <= 0.0 {
+            return f32::NAN;
+        }
+        let target = 1200.0 * ((hz as f64) / self.map.reference_hz).log2();
+
+        let mut prev: Option<(i32, f64)> = None;
+        for key in self.map.first_note.max(0)..=self.map.last_note.min(127) {
+            let Some(c) = self.key_cents(key) else {
+                continue;
+            };
+            if let Some((pk, pc)) = prev {
+                if target >= pc && target <= c && c > pc {
+                    let u = (target - pc) / (c - pc);
+                    return pk as f32 + (key - pk) as f32 * u as f32;
+                }
+            } else if target < c {
+                break;
+            }
+            prev = Some((key, c));
+        }
+
+        // 範囲外は 12平均律で近似
+        self.map.reference_note as f32 + 12.0 * (target / 1200.0) as f32
+    }
+
+    /// key（小数可）に最も近い割り当て済みの鍵を返す。小数や割り当て済みならそのまま。
+    pub(crate) fn nearest_mapped_key(&self, key: f32) -> f32 {
+        if !key.is_finite() || key.fract() != 0.0 || self.is_mapped(key as i32) {
+            return key;
+        }
+        let k = key as i32;
+        let span = self.keys_per_period().max(1) as i32;
+        for d in 1..=span {
+            if self.is_mapped(k - d) {
+                return (k - d) as f32;
+            }
+            if self.is_mapped(k + d) {
+                return (k + d) as f32;
+            }
+        }
+        key
+    }
+}
+
+fn first_token(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+/// .scl のピッチ行：小数点あり = セント、なし = 比（"3/2" や "2"）
+fn parse_scl_pitch(tok: &str) -> Result<f64, String> {
+    if tok.contains('.') {
+        return tok
+            .parse::<f64>()
+            .map_err(|_| format!("scl: invalid cents value: {tok:?}"));
+    }
+
+    let (num, den) = match tok.split_once('/') {
+        Some((n, d)) => (n, d),
+        None => (tok, "1"),
+    };
+    let num: f64 = num.parse().map_err(|_| format!("scl: invalid ratio: {tok:?}"))?;
+    let den: f64 = den.parse().map_err(|_| format!("scl: invalid ratio: {tok:?}"))?;
+    if num <= 0.0 || den <= 0.0 {
+        return Err(format!("scl: ratio must be positive: {tok:?}"));
+    }
+    Ok(1200.0 * (num / den).log2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KBM_12: &str = "12\n0\n127\n60\n69\n440.0\n12\n0\n1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n";
+
+    #[test]
+    fn kbm_map_size_is_bounded() {
+        let huge = KBM_12.replacen("12", "4000000000", 1);
+        let err = KeyboardMap::parse(&huge).unwrap_err();
+        assert!(err.contains("map size"), "{err}");
+        assert!(KeyboardMap::parse(&KBM_12.replacen("12", "-1", 1)).is_err());
+
+        // 上限ちょうどは読める（足りないエントリは未割り当て）
+        let map = KeyboardMap::parse(&KBM_12.replacen("12", &MAX_KBM_SIZE.to_string(), 1)).unwrap();
+        assert_eq!(map.mapping.len(), MAX_KBM_SIZE as usize);
+        assert_eq!(map.mapping[1023], None);
+        assert_eq!(KeyboardMap::parse(KBM_12).unwrap().mapping.len(), 12);
+    }
+
+    #[test]
+    fn kbm_note_numbers_must_fit() {
+        let err = KeyboardMap::parse(&KBM_12.replacen("\n0\n", "\n4294967296\n", 1)).unwrap_err();
+        assert!(err.contains("first note"), "{err}");
+        let err = KeyboardMap::parse(&KBM_12.replacen("\n69\n", "\n-3000000000\n", 1)).unwrap_err();
+        assert!(err.contains("reference note"), "{err}");
+    }
+
+    #[test]
+    fn kbm_through_the_engine_rejects_bad_maps() {
+        let mut tuning = Tuning::default();
+        assert!(tuning.load_kbm(&KBM_12.replacen("12", "4000000000", 1)).is_err());
+        tuning.load_kbm(KBM_12).unwrap();
+        assert!((tuning.midi_to_hz(69.0) - 440.0).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn scl_count_is_not_preallocated() {
+        let err = ScalaScale::parse("huge\n4000000000\n100.0\n2/1\n").unwrap_err();
+        assert!(err.contains("expected 4000000000 pitches"), "{err}");
+    }
+}
@@ -0,0 +1,751 @@
+//! WAV（RIFF/WAVE）の読み書き。
+//!
+//! - PCM 8/16/24/32bit, IEEE float 32/64bit
+//! - モノラル/ステレオ/多チャンネル, WAVE_FORMAT_EXTENSIBLE
+//! - cue チャンク, LIST/INFO チャンク（その他のチャンクは読み飛ばす）
+//! - 整数フォーマットへ書き出す時は TPDF ディザを掛けられる
+//!
+//! サンプルは内部では f32 のインターリーブで持つ。
+
+use wasm_bindgen::prelude::*;
+
+use crate::rng::SplitMix64;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// KSDATAFORMAT_SUBTYPE_* の先頭2バイト（フォーマットタグ）以降
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SampleFormat {
+    Pcm8,
+    Pcm16,
+    Pcm24,
+    Pcm32,
+    Float32,
+    Float64,
+}
+
+impl SampleFormat {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "pcm8" => Some(Self::Pcm8),
+            "pcm16" => Some(Self::Pcm16),
+            "pcm24" => Some(Self::Pcm24),
+            "pcm32" => Some(Self::Pcm32),
+            "float32" => Some(Self::Float32),
+            "float64" => Some(Self::Float64),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Pcm8 => "pcm8",
+            Self::Pcm16 => "pcm16",
+            Self::Pcm24 => "pcm24",
+            Self::Pcm32 => "pcm32",
+            Self::Float32 => "float32",
+            Self::Float64 => "float64",
+        }
+    }
+
+    fn from_tag(tag: u16, bits: u16) -> Option<Self> {
+        match (tag, bits) {
+            (FORMAT_PCM, 8) => Some(Self::Pcm8),
+            (FORMAT_PCM, 16) => Some(Self::Pcm16),
+            (FORMAT_PCM, 24) => Some(Self::Pcm24),
+            (FORMAT_PCM, 32) => Some(Self::Pcm32),
+            (FORMAT_FLOAT, 32) => Some(Self::Float32),
+            (FORMAT_FLOAT, 64) => Some(Self::Float64),
+            _ => None,
+        }
+    }
+
+    fn tag(self) -> u16 {
+        if self.is_float() {
+            FORMAT_FLOAT
+        } else {
+            FORMAT_PCM
+        }
+    }
+
+    pub(crate) fn bits(self) -> u16 {
+        match self {
+            Self::Pcm8 => 8,
+            Self::Pcm16 => 16,
+            Self::Pcm24 => 24,
+            Self::Pcm32 | Self::Float32 => 32,
+            Self::Float64 => 64,
+        }
+    }
+
+    pub(crate) fn is_float(self) -> bool {
+        matches!(self, Self::Float32 | Self::Float64)
+    }
+
+    fn bytes(self) -> usize {
+        (self.bits() / 8) as usize
+    }
+
+    /// f32 の仮数（24bit）より粗い整数フォーマットならディザ対象
+    fn reduces_depth(self) -> bool {
+        matches!(self, Self::Pcm8 | Self::Pcm16 | Self::Pcm24)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CuePoint {
+    pub(crate) id: u32,
+    // sample frame
+    pub(crate) position: u32,
+}
+
+/// デコード済み（またはこれからエンコードする）WAV 音声。
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct WavAudio {
+    sample_rate: u32,
+    channels: u16,
+    format: SampleFormat,
+    // WAVE_FORMAT_EXTENSIBLE の dwChannelMask（0 = 未指定）
+    channel_mask: u32,
+    // interleaved
+    samples: Vec<f32>,
+    cues: Vec<CuePoint>,
+    // LIST/INFO: (chunk id, text)
+    info: Vec<([u8; 4], String)>,
+}
+
+#[wasm_bindgen]
+impl WavAudio {
+    /// インターリーブ済みの samples から作る。既定の書き出しフォーマットは pcm16。
+    #[wasm_bindgen(constructor)]
+    pub fn new(sample_rate: u32, channels: u16, samples: Vec<f32>) -> WavAudio {
+        let channels = channels.max(1);
+        let frames = samples.len() / channels as usize;
+        let mut samples = samples;
+        samples.truncate(frames * channels as usize);
+        WavAudio {
+            sample_rate,
+            channels,
+            format: SampleFormat::Pcm16,
+            channel_mask: 0,
+            samples,
+            cues: Vec::new(),
+            info: Vec::new(),
+        }
+    }
+
+    /// planar（[ch0..., ch1..., ...]）の samples から作る。
+    #[wasm_bindgen]
+    pub fn from_planar(sample_rate: u32, channels: u16, planar: &[f32]) -> WavAudio {
+        let channels = channels.max(1);
+        let ch = channels as usize;
+        let frames = planar.len() / ch;
+        let mut samples = vec![0.0_f32; frames * ch];
+        for (i, frame) in samples.chunks_exact_mut(ch).enumerate() {
+            for (c, x) in frame.iter_mut().enumerate() {
+                *x = planar[c * frames + i];
+            }
+        }
+        WavAudio::new(sample_rate, channels, samples)
+    }
+
+    /// WAV ファイルのバイト列を読む。
+    #[wasm_bindgen]
+    pub fn decode(bytes: &[u8]) -> Result<WavAudio, String> {
+        decode(bytes)
+    }
+
+    /// WAV ファイルのバイト列にする。
+    /// dither = true なら 8/16/24bit への量子化時に TPDF ディザを掛ける（seed で再現可能）。
+    /// サンプルレート × チャンネル数やデータ長が WAV のヘッダに収まらなければエラー。
+    #[wasm_bindgen]
+    pub fn encode(&self, dither: bool, seed: u32) -> Result<Vec<u8>, String> {
+        encode(self, dither.then_some(seed as u64))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn bits_per_sample(&self) -> u16 {
+        self.format.bits()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn is_float(&self) -> bool {
+        self.format.is_float()
+    }
+
+    /// pcm8 / pcm16 / pcm24 / pcm32 / float32 / float64
+    #[wasm_bindgen(getter)]
+    pub fn format(&self) -> String {
+        self.format.name().to_string()
+    }
+
+    /// 書き出しフォーマットを変える。未知の名前なら false。
+    #[wasm_bindgen]
+    pub fn set_format(&mut self, name: &str) -> bool {
+        match SampleFormat::from_name(name) {
+            Some(f) => {
+                self.format = f;
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn channel_mask(&self) -> u32 {
+        self.channel_mask
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_channel_mask(&mut self, mask: u32) {
+        self.channel_mask = mask;
+    }
+
+    /// インターリーブのまま全サンプルを返す。
+    #[wasm_bindgen]
+    pub fn samples(&self) -> Vec<f32> {
+        self.samples.clone()
+    }
+
+    /// 1チャンネル分を取り出す（範囲外なら空）。
+    #[wasm_bindgen]
+    pub fn channel(&self, ch: u16) -> Vec<f32> {
+        if ch >= self.channels {
+            return Vec::new();
+        }
+        self.samples
+            .iter()
+            .skip(ch as usize)
+            .step_by(self.channels as usize)
+            .copied()
+            .collect()
+    }
+
+    #[wasm_bindgen]
+    pub fn add_cue(&mut self, id: u32, position: u32) {
+        self.cues.push(CuePoint { id, position });
+    }
+
+    #[wasm_bindgen]
+    pub fn cue_ids(&self) -> Vec<u32> {
+        self.cues.iter().map(|c| c.id).collect()
+    }
+
+    #[wasm_bindgen]
+    pub fn cue_positions(&self) -> Vec<u32> {
+        self.cues.iter().map(|c| c.position).collect()
+    }
+
+    /// LIST/INFO の項目をセットする（key は "INAM" などの4文字）。不正な key なら false。
+    #[wasm_bindgen]
+    pub fn set_info(&mut self, key: &str, value: &str) -> bool {
+        let Some(id) = fourcc(key) else {
+            return false;
+        };
+        match self.info.iter_mut().find(|(k, _)| *k == id) {
+            Some(entry) => entry.1 = value.to_string(),
+            None => self.info.push((id, value.to_string())),
+        }
+        true
+    }
+
+    #[wasm_bindgen]
+    pub fn info_keys(&self) -> Vec<String> {
+        self.info
+            .iter()
+            .map(|(k, _)| String::from_utf8_lossy(k).into_owned())
+            .collect()
+    }
+
+    #[wasm_bindgen]
+    pub fn info_value(&self, key: &str) -> Option<String> {
+        let id = fourcc(key)?;
+        self.info.iter().find(|(k, _)| *k == id).map(|(_, v)| v.clone())
+    }
+}
+
+fn fourcc(key: &str) -> Option<[u8; 4]> {
+    let b = key.as_bytes();
+    if b.len() != 4 || !b.iter().all(|c| c.is_ascii_graphic() || *c == b' ') {
+        return None;
+    }
+    Some([b[0], b[1], b[2], b[3]])
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).filter(|&e| e <= self.bytes.len());
+        match end {
+            Some(end) => {
+                let out = &self.bytes[self.pos..end];
+                self.pos = end;
+                Ok(out)
+            }
+            None => Err("wav: unexpected end of data".to_string()),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn id(&mut self) -> Result<[u8; 4], String> {
+        let b = self.take(4)?;
+        Ok([b[0], b[1], b[2], b[3]])
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+}
+
+struct FmtChunk {
+    channels: u16,
+    sample_rate: u32,
+    format: SampleFormat,
+    channel_mask: u32,
+}
+
+fn parse_fmt(data: &[u8]) -> Result<FmtChunk, String> {
+    let mut r = Reader { bytes: data, pos: 0 };
+    let mut tag = r.u16()?;
+    let channels = r.u16()?;
+    let sample_rate = r.u32()?;
+    let _byte_rate = r.u32()?;
+    let _block_align = r.u16()?;
+    let bits = r.u16()?;
+    let mut channel_mask = 0;
+
+    if tag == FORMAT_EXTENSIBLE {
+        let cb_size = r.u16()?;
+        if cb_size < 22 {
+            return Err("wav: WAVE_FORMAT_EXTENSIBLE with short extension".to_string());
+        }
+        let _valid_bits = r.u16()?;
+        channel_mask = r.u32()?;
+        tag = r.u16()?;
+    }
+
+    if channels == 0 {
+        return Err("wav: zero channels".to_string());
+    }
+    let format = SampleFormat::from_tag(tag, bits)
+        .ok_or_else(|| format!("wav: unsupported format (tag {tag:#06x}, {bits} bits)"))?;
+
+    Ok(FmtChunk {
+        channels,
+        sample_rate,
+        format,
+        channel_mask,
+    })
+}
+
+fn parse_cue(data: &[u8]) -> Result<Vec<CuePoint>, String> {
+    let mut r = Reader { bytes: data, pos: 0 };
+    let count = r.u32()? as usize;
+    let mut cues = Vec::with_capacity(count.min(r.remaining() / 24));
+    for _ in 0..count {
+        let id = r.u32()?;
+        let _play_position = r.u32()?;
+        let _data_chunk_id = r.id()?;
+        let _chunk_start = r.u32()?;
+        let _block_start = r.u32()?;
+        let position = r.u32()?;
+        cues.push(CuePoint { id, position });
+    }
+    Ok(cues)
+}
+
+fn parse_info(data: &[u8]) -> Result<Vec<([u8; 4], String)>, String> {
+    let mut r = Reader { bytes: data, pos: 0 };
+    if r.id()? != *b"INFO" {
+        return Ok(Vec::new()); // adtl などは扱わない
+    }
+    let mut out = Vec::new();
+    while r.remaining() >= 8 {
+        let id = r.id()?;
+        let size = r.u32()? as usize;
+        let raw = r.take(size.min(r.remaining()))?;
+        if size % 2 == 1 && r.remaining() > 0 {
+            r.take(1)?;
+        }
+        let text = raw.split(|&b| b == 0).next().unwrap_or(&[]);
+        out.push((id, String::from_utf8_lossy(text).into_owned()));
+    }
+    Ok(out)
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Result<WavAudio, String> {
+    let mut r = Reader { bytes, pos: 0 };
+    if r.id()? != *b"RIFF" {
+        return Err("wav: not a RIFF file".to_string());
+    }
+    let _riff_size = r.u32()?;
+    if r.id()? != *b"WAVE" {
+        return Err("wav: not a WAVE file".to_string());
+    }
+
+    let mut fmt: Option<FmtChunk> = None;
+    let mut data: Option<&[u8]> = None;
+    let mut cues = Vec::new();
+    let mut info = Vec::new();
+
+    while r.remaining() >= 8 {
+        let id = r.id()?;
+        let size = r.u32()? as usize;
+        // 書きかけのファイルなどでサイズが壊れていても、残りを data として読めるようにする
+        let body = r.take(size.min(r.remaining()))?;
+        if size % 2 == 1 && r.remaining() > 0 {
+            r.take(1)?;
+        }
+
+        match &id {
+            b"fmt " => fmt = Some(parse_fmt(body)?),
+            b"data" => data = Some(body),
+            b"cue " => cues = parse_cue(body)?,
+            b"LIST" => info.extend(parse_info(body)?),
+            _ => {}
+        }
+    }
+
+    let fmt = fmt.ok_or("wav: missing fmt chunk")?;
+    let data = data.ok_or("wav: missing data chunk")?;
+
+    let bps = fmt.format.bytes();
+    let frame_bytes = bps * fmt.channels as usize;
+    let frames = data.len() / frame_bytes;
+    let mut samples = Vec::with_capacity(frames * fmt.channels as usize);
+    for chunk in data[..frames * frame_bytes].chunks_exact(bps) {
+        samples.push(read_sample(chunk, fmt.format));
+    }
+
+    Ok(WavAudio {
+        sample_rate: fmt.sample_rate,
+        channels: fmt.channels,
+        format: fmt.format,
+        channel_mask: fmt.channel_mask,
+        samples,
+        cues,
+        info,
+    })
+}
+
+fn read_sample(b: &[u8], format: SampleFormat) -> f32 {
+    match format {
+        SampleFormat::Pcm8 => (b[0] as f32 - 128.0) / 128.0,
+        SampleFormat::Pcm16 => i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        SampleFormat::Pcm24 => {
+            // sign-extend via the top byte
+            let v = i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8;
+            v as f32 / 8_388_608.0
+        }
+        SampleFormat::Pcm32 => (i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64 / 2_147_483_648.0) as f32,
+        SampleFormat::Float32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        SampleFormat::Float64 => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+    }
+}
+
+/// x（-1..1）を整数フォーマットに量子化する。dither は LSB 単位。
+fn quantize(x: f32, bits: u16, dither: f64) -> i64 {
+    let scale = (1i64 << (bits - 1)) as f64;
+    let x = if x.is_finite() { x.clamp(-1.0, 1.0) as f64 } else { 0.0 };
+    let v = (x * scale + dither).round() as i64;
+    v.clamp(-(scale as i64), scale as i64 - 1)
+}
+
+pub(crate) fn encode(wav: &WavAudio, dither_seed: Option<u64>) -> Result<Vec<u8>, String> {
+    let format = wav.format;
+    let channels = wav.channels.max(1);
+    let bps = format.bytes();
+    let data_len = wav.samples.len() * bps;
+    let block_align = u16::try_from(bps * channels as usize)
+        .map_err(|_| format!("wav: {channels} channels of {} bits do not fit in a frame", format.bits()))?;
+    let byte_rate = wav
+        .sample_rate
+        .checked_mul(block_align as u32)
+        .ok_or_else(|| format!("wav: byte rate overflows ({} Hz x {block_align} bytes)", wav.sample_rate))?;
+    let frames = u32::try_from(wav.samples.len() / channels as usize).map_err(|_| "wav: too many frames".to_string())?;
+
+    // 3ch 以上やチャンネルマスク指定があれば WAVE_FORMAT_EXTENSIBLE
+    let extensible = channels > 2 || wav.channel_mask != 0;
+
+    let mut fmt = Vec::with_capacity(40);
+    fmt.extend_from_slice(&(if extensible { FORMAT_EXTENSIBLE } else { format.tag() }).to_le_bytes());
+    fmt.extend_from_slice(&channels.to_le_bytes());
+    fmt.extend_from_slice(&wav.sample_rate.to_le_bytes());
+    fmt.extend_from_slice(&byte_rate.to_le_bytes());
+    fmt.extend_from_slice(&block_align.to_le_bytes());
+    fmt.extend_from_slice(&format.bits().to_le_bytes());
+    if extensible {
+        fmt.extend_from_slice(&22u16.to_le_bytes());
+        fmt.extend_from_slice(&format.bits().to_le_bytes());
+        fmt.extend_from_slice(&wav.channel_mask.to_le_bytes());
+        fmt.extend_from_slice(&format.tag().to_le_bytes());
+        fmt.extend_from_slice(&SUBFORMAT_GUID_TAIL);
+    } else if format.is_float() {
+        fmt.extend_from_slice(&0u16.to_le_bytes());
+    }
+
+    let mut data = Vec::with_capacity(data_len);
+    let mut rng = dither_seed.filter(|_| format.reduces_depth()).map(SplitMix64::new);
+    for &x in wav.samples.iter() {
+        // TPDF: 2つの一様乱数の和（±1 LSB の三角分布）
+        let d = match rng.as_mut() {
+            Some(rng) => (rng.next_f32() as f64) - (rng.next_f32() as f64),
+            None => 0.0,
+        };
+        match format {
+            SampleFormat::Pcm8 => data.push((quantize(x, 8, d) + 128) as u8),
+            SampleFormat::Pcm16 => data.extend_from_slice(&(quantize(x, 16, d) as i16).to_le_bytes()),
+            SampleFormat::Pcm24 => data.extend_from_slice(&(quantize(x, 24, d) as i32).to_le_bytes()[..3]),
+            SampleFormat::Pcm32 => data.extend_from_slice(&(quantize(x, 32, d) as i32).to_le_bytes()),
+            SampleFormat::Float32 => data.extend_from_slice(&x.to_le_bytes()),
+            SampleFormat::Float64 => data.extend_from_slice(&(x as f64).to_le_bytes()),
+        }
+    }
+
+    let mut chunks: Vec<([u8; 4], Vec<u8>)> = vec![(*b"fmt ", fmt)];
+    // PCM 以外（float）は fact チャンク（フレーム数）が必須
+    if format.is_float() {
+        chunks.push((*b"fact", frames.to_le_bytes().to_vec()));
+    }
+    chunks.push((*b"data", data));
+
+    if !wav.cues.is_empty() {
+        let mut cue = Vec::with_capacity(4 + wav.cues.len() * 24);
+        cue.extend_from_slice(&(wav.cues.len() as u32).to_le_bytes());
+        for c in wav.cues.iter() {
+            cue.extend_from_slice(&c.id.to_le_bytes());
+            cue.extend_from_slice(&c.position.to_le_bytes());
+            cue.extend_from_slice(b"data");
+            cue.extend_from_slice(&0u32.to_le_bytes());
+            cue.extend_from_slice(&0u32.to_le_bytes());
+            cue.extend_from_slice(&c.position.to_le_bytes());
+        }
+        chunks.push((*b"cue ", cue));
+    }
+
+    if !wav.info.is_empty() {
+        let mut list = Vec::new();
+        list.extend_from_slice(b"INFO");
+        for (id, text) in wav.info.iter() {
+            let mut body = text.as_bytes().to_vec();
+            body.push(0);
+            list.extend_from_slice(id);
+            list.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            list.extend_from_slice(&body);
+            if body.len() % 2 == 1 {
+                list.push(0);
+            }
+        }
+        chunks.push((*b"LIST", list));
+    }
+
+    let riff_size: usize = 4 + chunks.iter().map(|(_, b)| 8 + b.len() + b.len() % 2).sum::<usize>();
+    let riff_size_u32 = u32::try_from(riff_size).map_err(|_| format!("wav: {riff_size} bytes exceed the 4 GiB RIFF limit"))?;
+    let mut out = Vec::with_capacity(8 + riff_size);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&riff_size_u32.to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    for (id, body) in chunks.iter() {
+        out.extend_from_slice(id);
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            out.push(0);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RIFF のチャンク ID と中身を順に並べる。
+    fn chunks(bytes: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut out = Vec::new();
+        let mut pos = 12;
+        while pos + 8 <= bytes.len() {
+            let id = [bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]];
+            let size = u32::from_le_bytes([bytes[pos + 4], bytes[pos + 5], bytes[pos + 6], bytes[pos + 7]]) as usize;
+            out.push((id, bytes[pos + 8..pos + 8 + size].to_vec()));
+            pos += 8 + size + size % 2;
+        }
+        out
+    }
+
+    fn stereo(frames: usize) -> WavAudio {
+        let samples = (0..frames * 2).map(|i| ((i as f32) * 0.01).sin() * 0.5).collect();
+        WavAudio::new(48000, 2, samples)
+    }
+
+    #[test]
+    fn float_formats_carry_a_fact_chunk() {
+        for name in ["float32", "float64"] {
+            let mut wav = stereo(1000);
+            assert!(wav.set_format(name));
+            let bytes = wav.encode(false, 0).unwrap();
+            let ids: Vec<[u8; 4]> = chunks(&bytes).iter().map(|(id, _)| *id).collect();
+            assert_eq!(ids, [*b"fmt ", *b"fact", *b"data"], "{name}");
+            let fact = &chunks(&bytes)[1].1;
+            assert_eq!(u32::from_le_bytes([fact[0], fact[1], fact[2], fact[3]]), 1000);
+
+            let back = WavAudio::decode(&bytes).unwrap();
+            assert_eq!(back.format(), name);
+            assert_eq!(back.samples(), wav.samples());
+        }
+
+        let bytes = stereo(1000).encode(false, 0).unwrap();
+        assert!(chunks(&bytes).iter().all(|(id, _)| id != b"fact"));
+    }
+
+    #[test]
+    fn header_overflow_is_an_error() {
+        let wav = WavAudio::new(u32::MAX / 2, 2, vec![0.0; 4]);
+        assert!(wav.encode(false, 0).is_err());
+
+        // 1 フレームが u16 に収まらない
+        let mut wav = WavAudio::new(48000, 9000, vec![0.0; 9000]);
+        assert!(wav.set_format("float64"));
+        assert!(wav.encode(false, 0).is_err());
+    }
+
+    /// 1 LSB（整数フォーマット）
+    fn lsb(format: &str) -> f32 {
+        let bits = SampleFormat::from_name(format).unwrap().bits();
+        1.0 / (1u64 << (bits - 1)) as f32
+    }
+
+    fn max_error(a: &[f32], b: &[f32]) -> f32 {
+        assert_eq!(a.len(), b.len());
+        a.iter().zip(b).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        for name in ["pcm16", "pcm24", "pcm32", "float32"] {
+            let mut wav = stereo(500);
+            wav.samples[0] = 1.0;
+            wav.samples[1] = -1.0;
+            assert!(wav.set_format(name));
+            let back = WavAudio::decode(&wav.encode(false, 0).unwrap()).unwrap();
+            assert_eq!((back.sample_rate(), back.channels(), back.frames()), (48000, 2, 500));
+            assert_eq!(back.format(), name);
+            let err = max_error(&back.samples(), &wav.samples());
+            // 丸めは 0.5 LSB 以内（pcm32 は f32 の精度で決まる）、float32 はそのまま、+1.0 は最大値に丸まる
+            let tolerance = if name == "float32" { 0.0 } else { lsb(name).max(f32::EPSILON) };
+            assert!(err <= tolerance, "{name}: {err}");
+            if name != "float32" {
+                let inner = max_error(&back.samples()[2..], &wav.samples()[2..]);
+                assert!(inner <= 0.5 * lsb(name) + 1.0e-7, "{name}: {inner}");
+            }
+        }
+    }
+
+    #[test]
+    fn extensible_keeps_the_channel_mask() {
+        // 3ch（FL FR FC）は自動で WAVE_FORMAT_EXTENSIBLE
+        let samples: Vec<f32> = (0..300).map(|i| (i as f32 * 0.03).sin() * 0.25).collect();
+        let mut wav = WavAudio::new(44100, 3, samples);
+        wav.set_channel_mask(0x7);
+        assert!(wav.set_format("pcm24"));
+        let bytes = wav.encode(false, 0).unwrap();
+        let fmt = &chunks(&bytes)[0].1;
+        assert_eq!(u16::from_le_bytes([fmt[0], fmt[1]]), FORMAT_EXTENSIBLE);
+        assert_eq!(fmt.len(), 40);
+
+        let back = WavAudio::decode(&bytes).unwrap();
+        assert_eq!((back.channels(), back.channel_mask(), back.format()), (3, 0x7, "pcm24".to_string()));
+        assert!(max_error(&back.channel(2), &wav.channel(2)) <= 0.5 * lsb("pcm24"));
+
+        // ステレオでもマスクがあれば EXTENSIBLE、float でも同じ
+        let mut wav = stereo(100);
+        wav.set_channel_mask(0x3);
+        assert!(wav.set_format("float32"));
+        let back = WavAudio::decode(&wav.encode(false, 0).unwrap()).unwrap();
+        assert_eq!((back.channel_mask(), back.format()), (0x3, "float32".to_string()));
+        assert_eq!(back.samples(), wav.samples());
+    }
+
+    #[test]
+    fn cue_and_info_are_preserved() {
+        let mut wav = stereo(1000);
+        wav.add_cue(1, 0);
+        wav.add_cue(7, 480);
+        assert!(wav.set_info("INAM", "take 3"));
+        assert!(wav.set_info("IART", "odd")); // 奇数長はパディングされる
+        assert!(wav.set_info("INAM", "take 4"));
+        assert!(!wav.set_info("TOOLONG", "x"));
+
+        let back = WavAudio::decode(&wav.encode(false, 0).unwrap()).unwrap();
+        assert_eq!(back.cue_ids(), vec![1, 7]);
+        assert_eq!(back.cue_positions(), vec![0, 480]);
+        assert_eq!(back.info_keys(), vec!["INAM", "IART"]);
+        assert_eq!(back.info_value("INAM").as_deref(), Some("take 4"));
+        assert_eq!(back.info_value("IART").as_deref(), Some("odd"));
+        assert_eq!(back.frames(), 1000);
+    }
+
+    #[test]
+    fn dither_stays_within_one_lsb() {
+        let mut wav = stereo(4000);
+        // 小さい信号ほどディザが効く
+        for v in wav.samples.iter_mut() {
+            *v *= 0.001;
+        }
+        for name in ["pcm16", "pcm24"] {
+            assert!(wav.set_format(name));
+            let plain = WavAudio::decode(&wav.encode(false, 0).unwrap()).unwrap().samples();
+            let dithered = WavAudio::decode(&wav.encode(true, 5).unwrap()).unwrap().samples();
+            let step = lsb(name);
+            let mut changed = 0;
+            for (a, b) in plain.iter().zip(&dithered) {
+                let d = ((a - b) / step).round();
+                assert!(d.abs() <= 1.0, "{name}: {d} LSB");
+                if d != 0.0 {
+                    changed += 1;
+                }
+            }
+            assert!(changed > plain.len() / 10, "{name}: dither changed only {changed} samples");
+            // 同じシードなら同じ結果
+            assert_eq!(WavAudio::decode(&wav.encode(true, 5).unwrap()).unwrap().samples(), dithered);
+        }
+        // float にはディザを掛けない
+        assert!(wav.set_format("float32"));
+        assert_eq!(wav.encode(true, 5).unwrap(), wav.encode(false, 0).unwrap());
+    }
+}
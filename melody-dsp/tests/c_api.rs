@@ -0,0 +1,68 @@
+//! C API: include/melody_dsp.h が src/capi.rs から cbindgen で作ったものと一致するか、
+//! examples/c/host.c がそのヘッダと cdylib でビルドでき、最後まで通るか。
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn crate_dir() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+#[test]
+fn header_matches_cbindgen() {
+    let config = cbindgen::Config::from_file(crate_dir().join("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_crate(crate_dir())
+        .with_config(config)
+        .generate()
+        .expect("cbindgen")
+        .write(&mut generated);
+    let committed = std::fs::read(crate_dir().join("include/melody_dsp.h")).unwrap();
+    assert!(
+        generated == committed,
+        "include/melody_dsp.h is out of date:\n    cbindgen --config cbindgen.toml --output include/melody_dsp.h"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn c_host_builds_and_runs() {
+    // テストの実行ファイルは target/<profile>/deps/ にあり、cdylib は target/<profile>/ にできる
+    let exe = std::env::current_exe().unwrap();
+    let lib_dir: PathBuf = exe.parent().and_then(Path::parent).unwrap().to_path_buf();
+    let host = Path::new(env!("CARGO_TARGET_TMPDIR")).join("c-host");
+
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let built = Command::new(&cc)
+        .args(["-std=c99", "-Wall", "-Werror", "-I"])
+        .arg(crate_dir().join("include"))
+        .arg(crate_dir().join("examples/c/host.c"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .args(["-lmelody_dsp", "-lm", "-o"])
+        .arg(&host)
+        .output();
+    let built = match built {
+        Ok(out) => out,
+        Err(e) => {
+            eprintln!("skipping: cannot run {cc}: {e}");
+            return;
+        }
+    };
+    assert!(built.status.success(), "{cc} failed:\n{}", String::from_utf8_lossy(&built.stderr));
+
+    let run = Command::new(&host)
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .env("DYLD_LIBRARY_PATH", &lib_dir)
+        .output()
+        .unwrap();
+    assert!(
+        run.status.success(),
+        "c-host exited with {}:\n{}{}",
+        run.status,
+        String::from_utf8_lossy(&run.stdout),
+        String::from_utf8_lossy(&run.stderr)
+    );
+    assert!(String::from_utf8_lossy(&run.stdout).starts_with("ok:"));
+}
@@ -0,0 +1,182 @@
+//! ストリーミング処理の中でヒープ確保が起きないことを確かめる（数を数えるアロケータ）。
+//!
+//! 処理系は new / set_* の時点で状態を確保し終えているはずなので、
+//! そのあとの process_* の呼び出し中の確保は 0 回でなければならない。
+//! テストは並列に走るので、数えるのは allocations_in を呼んだスレッドの確保だけ。
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::f32::consts::PI;
+
+use melody_dsp::{LiveTuner, MelodyEngine, MelodyShifter};
+
+const SAMPLE_RATE: f32 = 48000.0;
+// AudioWorklet の 1 量子
+const QUANTUM: usize = 128;
+
+struct CountingAlloc;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_allocation() {
+    if COUNTING.with(Cell::get) {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// f の中で（このスレッドで）起きた確保の回数。
+fn allocations_in(f: impl FnOnce()) -> usize {
+    ALLOCATIONS.with(|n| n.set(0));
+    COUNTING.with(|c| c.set(true));
+    f();
+    COUNTING.with(|c| c.set(false));
+    ALLOCATIONS.with(Cell::get)
+}
+
+/// hz のサイン波（seconds 秒、振幅 0.5）
+fn sine(hz: f32, seconds: f32) -> Vec<f32> {
+    let n = (seconds * SAMPLE_RATE) as usize;
+    (0..n).map(|i| 0.5 * (2.0 * PI * hz * i as f32 / SAMPLE_RATE).sin()).collect()
+}
+
+// 12 * log2(220 / 225): 225 Hz は A3 に寄せられる（本当にシフトしている）
+fn pulled_to_a3(shift: f32) -> bool {
+    (shift + 0.389).abs() < 0.05
+}
+
+#[test]
+fn counter_sees_allocations() {
+    assert_eq!(allocations_in(|| drop(std::hint::black_box(vec![0_u8; 16]))), 1);
+}
+
+#[test]
+fn shifter_process_block() {
+    let input = sine(225.0, 1.0);
+    let mut shifter = MelodyShifter::new(SAMPLE_RATE);
+    let mut quantum = [0.0_f32; QUANTUM];
+    let count = allocations_in(|| {
+        for (i, chunk) in input.chunks_exact(QUANTUM).enumerate() {
+            quantum.copy_from_slice(chunk);
+            // バイパスとシフトを行き来する
+            shifter.process_block(&mut quantum, if i % 40 < 20 { 3.0 } else { 0.0 });
+        }
+    });
+    assert_eq!(count, 0, "MelodyShifter::process_block");
+}
+
+#[test]
+fn live_tuner_process_buffer() {
+    let input = sine(225.0, 1.0);
+    let mut tuner = LiveTuner::new(SAMPLE_RATE, 1);
+    tuner.set_key(9.0, "minor");
+    tuner.set_retune_speed(20.0);
+    tuner.set_formant_shift(2.0);
+    let mut quantum = [0.0_f32; QUANTUM];
+    let count = allocations_in(|| {
+        for chunk in input.chunks_exact(QUANTUM) {
+            quantum.copy_from_slice(chunk);
+            tuner.process_buffer(&mut quantum);
+        }
+    });
+    assert_eq!(count, 0, "LiveTuner::process_buffer");
+    assert!(pulled_to_a3(tuner.current_shift()), "{}", tuner.current_shift());
+}
+
+#[test]
+fn live_tuner_process_planar() {
+    let input = sine(225.0, 1.0);
+    let mut tuner = LiveTuner::new(SAMPLE_RATE, 2);
+    tuner.set_key(9.0, "minor");
+    tuner.set_mix(80.0);
+    let mut planar = [0.0_f32; QUANTUM * 2];
+    let count = allocations_in(|| {
+        for chunk in input.chunks_exact(QUANTUM) {
+            planar[..QUANTUM].copy_from_slice(chunk);
+            planar[QUANTUM..].copy_from_slice(chunk);
+            tuner.process_planar(&mut planar, 2);
+        }
+    });
+    assert_eq!(count, 0, "LiveTuner::process_planar (stereo)");
+    assert!(pulled_to_a3(tuner.current_shift()), "{}", tuner.current_shift());
+}
+
+/// 倍音 EQ とノート 4 つ（ノートごとに f0 が変わるので、倍音 EQ のフィルタが作り直される）
+fn engine_with_notes() -> MelodyEngine {
+    let mut engine = MelodyEngine::new(SAMPLE_RATE);
+    engine.set_harmonic_gains(vec![1.0, 1.5, 0.5, 1.2, 0.8, 1.0, 0.6, 1.1]);
+    engine.set_notes(
+        vec![0.0, 0.25, 0.5, 0.75],
+        vec![0.25, 0.5, 0.75, 1.0],
+        vec![57.2, 60.0, 64.5, 57.0],
+        vec![0.0, 2.0, -1.0, 0.0],
+        vec![0.0; 4],
+        vec![1.0; 4],
+        vec![1.0; 4],
+        vec![1.0; 4],
+        vec![1.0; 4],
+        vec![0.0, 3.0, -2.0, 0.0],
+        0,
+        Vec::new(),
+    );
+    engine.set_key(9.0, "minor");
+    engine
+}
+
+#[test]
+fn engine_process_buffer() {
+    let input = sine(225.0, 1.0);
+    let mut engine = engine_with_notes();
+    let mut buffer = input.clone();
+    let count = allocations_in(|| engine.process_buffer(&mut buffer));
+    assert_eq!(count, 0, "MelodyEngine::process_buffer (harmonic EQ rebuilt per note)");
+    assert_ne!(buffer, input);
+}
+
+#[test]
+fn engine_process_planar() {
+    let input = sine(225.0, 1.0);
+    let mut engine = engine_with_notes();
+    engine.set_max_buffer(input.len(), 2);
+    let mut planar = [input.as_slice(), input.as_slice()].concat();
+    let count = allocations_in(|| engine.process_planar(&mut planar, 2));
+    assert_eq!(count, 0, "MelodyEngine::process_planar (stereo)");
+    assert_ne!(planar[..input.len()], input[..]);
+    assert_eq!(planar[..input.len()], planar[input.len()..]);
+}
+
+#[test]
+fn engine_process_interleaved() {
+    let input = sine(225.0, 1.0);
+    let mut engine = engine_with_notes();
+    engine.set_max_buffer(input.len(), 2);
+    let mut interleaved: Vec<f32> = input.iter().flat_map(|&x| [x, x]).collect();
+    let count = allocations_in(|| {
+        // 2 回目以降も作業領域を使い回す（短いバッファでも広げ直さない）
+        engine.process_interleaved(&mut interleaved, 2);
+        engine.process_interleaved(&mut interleaved[..QUANTUM * 2], 2);
+    });
+    assert_eq!(count, 0, "MelodyEngine::process_interleaved (stereo)");
+    assert!(interleaved.chunks_exact(2).all(|f| f[0] == f[1]));
+}
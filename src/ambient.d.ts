@@ -35,8 +35,34 @@ declare module 'melody-dsp' {
 
     export class MelodyShifter {
         constructor(sample_rate: number);
+        static with_interpolation(sample_rate: number, interpolation: string): MelodyShifter;
         process_block(input: Float32Array, semitones: number): void;
+        readonly latency_samples: number;
         readonly sample_rate: number;
+        readonly interpolation: string;
+    }
+
+    // MelodyEngine.note_list() の戻り値。各配列は同じ並び（開始時刻順）
+    export class NoteList {
+        free(): void;
+        readonly length: number;
+        readonly indices: Uint32Array;
+        readonly starts: Float32Array;
+        readonly ends: Float32Array;
+        readonly base_semitones: Float32Array;
+        readonly pitch_offsets: Float32Array;
+        readonly pitch_center_offsets: Float32Array;
+        readonly pitch_mod_amounts: Float32Array;
+        readonly pitch_drift_amounts: Float32Array;
+        readonly time_stretch_starts: Float32Array;
+        readonly time_stretch_ends: Float32Array;
+        readonly formant_shifts: Float32Array;
+        readonly harmonics_per_note: number;
+        readonly harmonics_flat: Float32Array;
+        readonly target_midis: Float32Array;
+        readonly correction_strengths: Float32Array;
+        readonly correction_offsets: Float32Array;
+        readonly just_offsets: Float32Array;
     }
 
     export class MelodyEngine {
@@ -57,6 +83,10 @@ declare module 'melody-dsp' {
             note_harmonics_flat: Float32Array
         ): void;
         process_buffer(input: Float32Array): void;
+        note_list(): NoteList;
+        set_interpolation(name: string): boolean;
+        readonly latency_samples: number;
         readonly sample_rate: number;
+        readonly interpolation: string;
     }
 }
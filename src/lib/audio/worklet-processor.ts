@@ -7,6 +7,8 @@ class MelodyProcessor extends AudioWorkletProcessor {
 	private _ready = false;
 	private _semitones = 0;
 	private _shifter: MelodyShifter | null = null;
+	// 量子ごとに作り直さない作業バッファ（長さが変わったときだけ確保し直す）
+	private _buf = new Float32Array(128);
 	private _initPromise: Promise<void>;
 
 	constructor(options?: AudioWorkletNodeOptions) {
@@ -60,7 +62,9 @@ class MelodyProcessor extends AudioWorkletProcessor {
 		}
 
 		// ストリーミング向けなので、1量子(通常128)ごとにそのまま処理する
-		const buf = new Float32Array(input);
+		if (this._buf.length !== input.length) this._buf = new Float32Array(input.length);
+		const buf = this._buf;
+		buf.set(input);
 		this._shifter.process_block(buf, this._semitones);
 		output.set(buf);
 		return true;